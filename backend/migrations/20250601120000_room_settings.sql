@@ -0,0 +1,4 @@
+-- Editable room settings
+ALTER TABLE rooms
+  ADD COLUMN description TEXT,
+  ADD COLUMN icon_url TEXT;
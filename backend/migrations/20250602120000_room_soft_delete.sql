@@ -0,0 +1,4 @@
+-- Soft-deleted rooms stay restorable until the purge job removes them
+ALTER TABLE rooms ADD COLUMN deleted_at TIMESTAMPTZ;
+
+CREATE INDEX idx_rooms_deleted ON rooms(deleted_at) WHERE deleted_at IS NOT NULL;
@@ -0,0 +1,21 @@
+-- Per-room roles; `permissions` is a bitfield (see models::roles::permissions)
+CREATE TABLE room_roles (
+  id UUID PRIMARY KEY DEFAULT gen_random_uuid(),
+  room_id UUID NOT NULL REFERENCES rooms(id) ON DELETE CASCADE,
+  name TEXT NOT NULL,
+  permissions BIGINT NOT NULL DEFAULT 0,
+  position INT NOT NULL DEFAULT 0,
+  created_at TIMESTAMPTZ NOT NULL DEFAULT NOW(),
+  UNIQUE (room_id, name)
+);
+
+-- Role assignments, removed automatically when the member leaves
+CREATE TABLE room_member_roles (
+  user_id UUID NOT NULL,
+  room_id UUID NOT NULL,
+  role_id UUID NOT NULL REFERENCES room_roles(id) ON DELETE CASCADE,
+  PRIMARY KEY (user_id, room_id, role_id),
+  FOREIGN KEY (user_id, room_id) REFERENCES room_members(user_id, room_id) ON DELETE CASCADE
+);
+
+CREATE INDEX idx_room_roles_room ON room_roles(room_id);
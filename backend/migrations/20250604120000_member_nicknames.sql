@@ -0,0 +1,6 @@
+-- Per-room display names
+ALTER TABLE room_members ADD COLUMN nickname TEXT;
+
+-- Member list is ordered by username and paginated by keyset
+CREATE INDEX idx_room_members_room ON room_members(room_id);
+CREATE INDEX idx_users_username_prefix ON users(lower(username) text_pattern_ops);
@@ -0,0 +1,24 @@
+-- Append-only record of moderation and settings changes per room
+CREATE TABLE room_audit_log (
+  id BIGSERIAL PRIMARY KEY,
+  room_id UUID NOT NULL REFERENCES rooms(id) ON DELETE CASCADE,
+  actor_id UUID REFERENCES users(id) ON DELETE SET NULL,
+  action TEXT NOT NULL,
+  target_id UUID,
+  before JSONB,
+  after JSONB,
+  created_at TIMESTAMPTZ NOT NULL DEFAULT NOW()
+);
+
+CREATE INDEX idx_room_audit_log_room ON room_audit_log(room_id, id DESC);
+
+-- Entries are never edited; rows only disappear with their room
+CREATE FUNCTION room_audit_log_immutable() RETURNS trigger AS $$
+BEGIN
+  RAISE EXCEPTION 'room_audit_log is append-only';
+END;
+$$ LANGUAGE plpgsql;
+
+CREATE TRIGGER room_audit_log_no_update
+  BEFORE UPDATE ON room_audit_log
+  FOR EACH ROW EXECUTE FUNCTION room_audit_log_immutable();
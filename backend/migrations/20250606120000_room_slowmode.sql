@@ -0,0 +1,6 @@
+-- Minimum seconds between two messages from the same member (0 = off)
+ALTER TABLE rooms
+  ADD COLUMN slowmode_seconds INT NOT NULL DEFAULT 0 CHECK (slowmode_seconds >= 0);
+
+-- Last accepted send, used to enforce slowmode atomically
+ALTER TABLE room_members ADD COLUMN last_message_at TIMESTAMPTZ;
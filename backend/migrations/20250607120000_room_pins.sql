@@ -0,0 +1,9 @@
+-- Pinned messages, listed in the order they were pinned
+CREATE TABLE room_pins (
+  message_id UUID PRIMARY KEY REFERENCES messages(id) ON DELETE CASCADE,
+  room_id UUID NOT NULL REFERENCES rooms(id) ON DELETE CASCADE,
+  pinned_by UUID REFERENCES users(id) ON DELETE SET NULL,
+  pinned_at TIMESTAMPTZ NOT NULL DEFAULT NOW()
+);
+
+CREATE INDEX idx_room_pins_room ON room_pins(room_id, pinned_at);
@@ -0,0 +1,17 @@
+-- Replies to a message, kept out of the main room timeline
+CREATE TABLE thread_replies (
+  id UUID PRIMARY KEY DEFAULT gen_random_uuid(),
+  parent_id UUID NOT NULL REFERENCES messages(id) ON DELETE CASCADE,
+  room_id UUID NOT NULL REFERENCES rooms(id) ON DELETE CASCADE,
+  author_id UUID NOT NULL REFERENCES users(id) ON DELETE SET NULL,
+  content TEXT NOT NULL,
+  created_at TIMESTAMPTZ NOT NULL DEFAULT NOW(),
+  edited_at TIMESTAMPTZ
+);
+
+CREATE INDEX idx_thread_replies_parent ON thread_replies(parent_id, created_at, id);
+
+-- Thread summary shown on the parent message
+ALTER TABLE messages
+  ADD COLUMN thread_reply_count INT NOT NULL DEFAULT 0,
+  ADD COLUMN thread_last_reply_at TIMESTAMPTZ;
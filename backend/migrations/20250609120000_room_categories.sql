@@ -0,0 +1,22 @@
+-- Sidebar structure inside a room: categories holding ordered channels
+CREATE TABLE room_categories (
+  id UUID PRIMARY KEY DEFAULT gen_random_uuid(),
+  room_id UUID NOT NULL REFERENCES rooms(id) ON DELETE CASCADE,
+  name TEXT NOT NULL,
+  position INT NOT NULL DEFAULT 0,
+  created_at TIMESTAMPTZ NOT NULL DEFAULT NOW()
+);
+
+-- Channels without a category are listed before the first category
+CREATE TABLE room_channels (
+  id UUID PRIMARY KEY DEFAULT gen_random_uuid(),
+  room_id UUID NOT NULL REFERENCES rooms(id) ON DELETE CASCADE,
+  category_id UUID REFERENCES room_categories(id) ON DELETE SET NULL,
+  name TEXT NOT NULL,
+  topic TEXT,
+  position INT NOT NULL DEFAULT 0,
+  created_at TIMESTAMPTZ NOT NULL DEFAULT NOW()
+);
+
+CREATE INDEX idx_room_categories_room ON room_categories(room_id, position);
+CREATE INDEX idx_room_channels_room ON room_channels(room_id, position);
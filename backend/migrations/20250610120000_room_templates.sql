@@ -0,0 +1,13 @@
+-- Shown to members when they join
+ALTER TABLE rooms ADD COLUMN welcome_message TEXT;
+
+-- Reusable snapshots of a room's configuration, shared by code
+CREATE TABLE room_templates (
+  id UUID PRIMARY KEY DEFAULT gen_random_uuid(),
+  code TEXT UNIQUE NOT NULL,
+  name TEXT NOT NULL,
+  creator_id UUID NOT NULL REFERENCES users(id) ON DELETE CASCADE,
+  source_room_id UUID REFERENCES rooms(id) ON DELETE SET NULL,
+  config JSONB NOT NULL,
+  created_at TIMESTAMPTZ NOT NULL DEFAULT NOW()
+);
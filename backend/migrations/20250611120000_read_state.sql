@@ -0,0 +1,2 @@
+-- Read marker per member; everything after it counts as unread
+ALTER TABLE room_members ADD COLUMN last_read_at TIMESTAMPTZ NOT NULL DEFAULT NOW();
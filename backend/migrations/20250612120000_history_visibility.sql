@@ -0,0 +1,18 @@
+-- What part of a room's history members get to see
+ALTER TABLE rooms
+    ADD COLUMN history_visibility TEXT NOT NULL DEFAULT 'full'
+        CHECK (history_visibility IN ('full', 'since_joined', 'last_days')),
+    ADD COLUMN history_days INT CHECK (history_days BETWEEN 1 AND 3650),
+    ADD CONSTRAINT rooms_history_days_required
+        CHECK (history_visibility <> 'last_days' OR history_days IS NOT NULL);
+
+-- Oldest message a member may read; NULL means the whole history
+CREATE FUNCTION room_history_cutoff(visibility TEXT, days INT, joined_at TIMESTAMPTZ)
+RETURNS TIMESTAMPTZ
+LANGUAGE SQL STABLE
+AS $$
+    SELECT CASE visibility
+        WHEN 'since_joined' THEN joined_at
+        WHEN 'last_days' THEN NOW() - make_interval(days => days)
+    END
+$$;
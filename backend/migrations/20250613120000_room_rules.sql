@@ -0,0 +1,11 @@
+-- Rules members must accept before posting. Every edit bumps rules_version;
+-- rules_required_version is the oldest accepted version still good enough.
+ALTER TABLE rooms
+    ADD COLUMN rules TEXT,
+    ADD COLUMN rules_version INT NOT NULL DEFAULT 0,
+    ADD COLUMN rules_required_version INT NOT NULL DEFAULT 0,
+    ADD COLUMN rules_updated_at TIMESTAMPTZ;
+
+ALTER TABLE room_members
+    ADD COLUMN rules_accepted_version INT,
+    ADD COLUMN rules_accepted_at TIMESTAMPTZ;
@@ -0,0 +1,34 @@
+-- Scheduled room events (meetups etc.) and member RSVPs
+CREATE TABLE room_events (
+    id UUID PRIMARY KEY DEFAULT gen_random_uuid(),
+    room_id UUID NOT NULL REFERENCES rooms(id) ON DELETE CASCADE,
+    creator_id UUID REFERENCES users(id) ON DELETE SET NULL,
+    title TEXT NOT NULL,
+    description TEXT,
+    location TEXT,
+    starts_at TIMESTAMPTZ NOT NULL,
+    ends_at TIMESTAMPTZ NOT NULL CHECK (ends_at > starts_at),
+    -- Set once the reminder message went out; cleared when the event is rescheduled
+    reminder_sent_at TIMESTAMPTZ,
+    created_at TIMESTAMPTZ NOT NULL DEFAULT NOW(),
+    updated_at TIMESTAMPTZ NOT NULL DEFAULT NOW()
+);
+
+CREATE INDEX idx_room_events_room ON room_events(room_id, starts_at);
+CREATE INDEX idx_room_events_reminder ON room_events(starts_at) WHERE reminder_sent_at IS NULL;
+
+CREATE TABLE room_event_rsvps (
+    event_id UUID NOT NULL REFERENCES room_events(id) ON DELETE CASCADE,
+    user_id UUID NOT NULL REFERENCES users(id) ON DELETE CASCADE,
+    status TEXT NOT NULL CHECK (status IN ('going', 'maybe', 'not_going')),
+    updated_at TIMESTAMPTZ NOT NULL DEFAULT NOW(),
+    PRIMARY KEY (event_id, user_id)
+);
+
+-- 'system' messages are posted by the server (e.g. event reminders)
+ALTER TABLE messages ADD COLUMN kind TEXT NOT NULL DEFAULT 'user'
+    CHECK (kind IN ('user', 'system'));
+
+-- Secret for the member's calendar subscription URL; calendar apps can't send a JWT
+ALTER TABLE room_members ADD COLUMN calendar_token UUID NOT NULL DEFAULT gen_random_uuid();
+CREATE UNIQUE INDEX idx_room_members_calendar_token ON room_members(calendar_token);
@@ -0,0 +1,14 @@
+-- Forum rooms: every top-level message is a titled post whose thread is its reply stream
+ALTER TABLE rooms ADD COLUMN kind TEXT NOT NULL DEFAULT 'chat'
+    CHECK (kind IN ('chat', 'forum'));
+
+ALTER TABLE messages
+    ADD COLUMN title TEXT,
+    ADD COLUMN tags TEXT[] NOT NULL DEFAULT '{}',
+    ADD COLUMN solved_at TIMESTAMPTZ,
+    ADD COLUMN solved_by UUID REFERENCES users(id) ON DELETE SET NULL,
+    -- The thread reply that answered the post, if one was picked
+    ADD COLUMN solution_id UUID REFERENCES thread_replies(id) ON DELETE SET NULL;
+
+CREATE INDEX idx_forum_posts_room ON messages(room_id, created_at) WHERE title IS NOT NULL;
+CREATE INDEX idx_forum_posts_tags ON messages USING GIN (tags) WHERE title IS NOT NULL;
@@ -0,0 +1,18 @@
+ALTER TABLE direct_messages ADD COLUMN edited_at TIMESTAMPTZ;
+
+-- Content a message had before each edit; exactly one of message_id / direct_message_id is set
+CREATE TABLE message_revisions (
+    id UUID PRIMARY KEY DEFAULT gen_random_uuid(),
+    message_id UUID REFERENCES messages(id) ON DELETE CASCADE,
+    direct_message_id UUID REFERENCES direct_messages(id) ON DELETE CASCADE,
+    content TEXT NOT NULL,
+    edited_by UUID REFERENCES users(id) ON DELETE SET NULL,
+    -- When this content was replaced
+    created_at TIMESTAMPTZ NOT NULL DEFAULT NOW(),
+    CHECK (num_nonnulls(message_id, direct_message_id) = 1)
+);
+
+CREATE INDEX idx_message_revisions_message ON message_revisions(message_id, created_at)
+    WHERE message_id IS NOT NULL;
+CREATE INDEX idx_message_revisions_dm ON message_revisions(direct_message_id, created_at)
+    WHERE direct_message_id IS NOT NULL;
@@ -0,0 +1,8 @@
+-- Soft delete: the row stays (ordering, thread links) but its content is wiped
+ALTER TABLE messages
+    ADD COLUMN deleted_at TIMESTAMPTZ,
+    ADD COLUMN deleted_by UUID REFERENCES users(id) ON DELETE SET NULL;
+
+ALTER TABLE direct_messages ADD COLUMN deleted_at TIMESTAMPTZ;
+
+CREATE INDEX idx_messages_room_author ON messages(room_id, author_id, created_at);
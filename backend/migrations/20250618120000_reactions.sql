@@ -0,0 +1,17 @@
+-- One row per (message, emoji, user)
+CREATE TABLE message_reactions (
+    message_id UUID NOT NULL REFERENCES messages(id) ON DELETE CASCADE,
+    user_id UUID NOT NULL REFERENCES users(id) ON DELETE CASCADE,
+    emoji TEXT NOT NULL,
+    created_at TIMESTAMPTZ NOT NULL DEFAULT NOW(),
+    PRIMARY KEY (message_id, emoji, user_id)
+);
+CREATE INDEX idx_message_reactions_who ON message_reactions(message_id, emoji, created_at, user_id);
+
+CREATE TABLE direct_message_reactions (
+    direct_message_id UUID NOT NULL REFERENCES direct_messages(id) ON DELETE CASCADE,
+    user_id UUID NOT NULL REFERENCES users(id) ON DELETE CASCADE,
+    emoji TEXT NOT NULL,
+    created_at TIMESTAMPTZ NOT NULL DEFAULT NOW(),
+    PRIMARY KEY (direct_message_id, emoji, user_id)
+);
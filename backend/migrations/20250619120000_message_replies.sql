@@ -0,0 +1,43 @@
+-- Messages can quote an earlier message from the same room or conversation
+ALTER TABLE messages
+    ADD COLUMN reply_to UUID REFERENCES messages(id) ON DELETE SET NULL;
+
+ALTER TABLE direct_messages
+    ADD COLUMN reply_to UUID REFERENCES direct_messages(id) ON DELETE SET NULL;
+
+CREATE INDEX idx_messages_reply_to ON messages (reply_to) WHERE reply_to IS NOT NULL;
+CREATE INDEX idx_direct_messages_reply_to ON direct_messages (reply_to) WHERE reply_to IS NOT NULL;
+
+-- Compact preview of a replied-to room message. NULL when the reader can't
+-- see it (before their history cutoff); tombstones only report `deleted`.
+CREATE FUNCTION message_reply_preview(target UUID, cutoff TIMESTAMPTZ)
+RETURNS JSONB
+LANGUAGE SQL STABLE
+AS $$
+    SELECT jsonb_build_object(
+        'author_id', m.author_id,
+        'username', u.username,
+        'snippet', CASE WHEN m.deleted_at IS NULL
+                        THEN left(regexp_replace(m.content, '\s+', ' ', 'g'), 100) END,
+        'deleted', m.deleted_at IS NOT NULL
+    )
+    FROM messages m
+    JOIN users u ON u.id = m.author_id
+    WHERE m.id = target AND (cutoff IS NULL OR m.created_at >= cutoff)
+$$;
+
+CREATE FUNCTION direct_message_reply_preview(target UUID)
+RETURNS JSONB
+LANGUAGE SQL STABLE
+AS $$
+    SELECT jsonb_build_object(
+        'author_id', dm.sender_id,
+        'username', u.username,
+        'snippet', CASE WHEN dm.deleted_at IS NULL
+                        THEN left(regexp_replace(dm.content, '\s+', ' ', 'g'), 100) END,
+        'deleted', dm.deleted_at IS NOT NULL
+    )
+    FROM direct_messages dm
+    JOIN users u ON u.id = dm.sender_id
+    WHERE dm.id = target
+$$;
@@ -0,0 +1,35 @@
+-- Mentions resolved when a message is sent, so unread counts and the inbox
+-- don't have to search message text
+ALTER TABLE messages
+    ADD COLUMN mentioned_user_ids UUID[] NOT NULL DEFAULT '{}',
+    ADD COLUMN mentioned_role_ids UUID[] NOT NULL DEFAULT '{}',
+    ADD COLUMN mentions_everyone BOOLEAN NOT NULL DEFAULT FALSE;
+
+-- Best-effort backfill of the @username / @everyone mentions the room list
+-- used to find by searching content
+UPDATE messages m
+SET mentions_everyone = position('@everyone' IN lower(m.content)) > 0,
+    mentioned_user_ids = ARRAY(
+        SELECT u.id FROM room_members rm
+        JOIN users u ON u.id = rm.user_id
+        WHERE rm.room_id = m.room_id AND u.id <> m.author_id
+          AND position('@' || lower(u.username) IN lower(m.content)) > 0
+    )
+WHERE m.deleted_at IS NULL AND position('@' IN m.content) > 0;
+
+CREATE TABLE notifications (
+    id UUID PRIMARY KEY DEFAULT gen_random_uuid(),
+    user_id UUID NOT NULL REFERENCES users(id) ON DELETE CASCADE,
+    kind TEXT NOT NULL DEFAULT 'mention' CHECK (kind IN ('mention')),
+    room_id UUID NOT NULL REFERENCES rooms(id) ON DELETE CASCADE,
+    message_id UUID NOT NULL REFERENCES messages(id) ON DELETE CASCADE,
+    actor_id UUID NOT NULL REFERENCES users(id) ON DELETE CASCADE,
+    created_at TIMESTAMPTZ NOT NULL DEFAULT NOW(),
+    read_at TIMESTAMPTZ,
+    -- One per message, so re-mentioning someone in an edit doesn't notify twice
+    UNIQUE (user_id, message_id)
+);
+
+CREATE INDEX idx_notifications_user ON notifications (user_id, created_at DESC, id DESC);
+CREATE INDEX idx_notifications_unread ON notifications (user_id) WHERE read_at IS NULL;
+CREATE INDEX idx_notifications_message ON notifications (message_id);
@@ -0,0 +1,58 @@
+-- Uploaded files. An upload starts out pending (no message) and scoped to the
+-- room or DM conversation it was uploaded to; sending a message claims it.
+CREATE TABLE attachments (
+    id UUID PRIMARY KEY DEFAULT gen_random_uuid(),
+    uploader_id UUID NOT NULL REFERENCES users(id) ON DELETE CASCADE,
+    room_id UUID REFERENCES rooms(id) ON DELETE SET NULL,
+    -- The other side of the DM conversation for DM uploads
+    dm_peer_id UUID REFERENCES users(id) ON DELETE CASCADE,
+    message_id UUID REFERENCES messages(id) ON DELETE SET NULL,
+    direct_message_id UUID REFERENCES direct_messages(id) ON DELETE SET NULL,
+    storage_key TEXT NOT NULL UNIQUE,
+    filename TEXT NOT NULL,
+    content_type TEXT NOT NULL,
+    size_bytes BIGINT NOT NULL,
+    created_at TIMESTAMPTZ NOT NULL DEFAULT NOW(),
+    -- Set when the message is deleted; the cleanup job removes the blob
+    deleted_at TIMESTAMPTZ,
+    CHECK (room_id IS NULL OR dm_peer_id IS NULL),
+    CHECK (message_id IS NULL OR direct_message_id IS NULL)
+);
+
+CREATE INDEX idx_attachments_message ON attachments (message_id) WHERE message_id IS NOT NULL;
+CREATE INDEX idx_attachments_direct_message ON attachments (direct_message_id)
+    WHERE direct_message_id IS NOT NULL;
+-- Unclaimed uploads and attachments of deleted messages, for the cleanup job
+CREATE INDEX idx_attachments_unlinked ON attachments (created_at)
+    WHERE message_id IS NULL AND direct_message_id IS NULL;
+
+-- Attachment list embedded in message responses
+CREATE FUNCTION message_attachments(target UUID)
+RETURNS JSONB
+LANGUAGE SQL STABLE
+AS $$
+    SELECT COALESCE(jsonb_agg(jsonb_build_object(
+        'id', a.id,
+        'filename', a.filename,
+        'content_type', a.content_type,
+        'size_bytes', a.size_bytes,
+        'url', '/api/attachments/' || a.id
+    ) ORDER BY a.created_at, a.id), '[]'::JSONB)
+    FROM attachments a
+    WHERE a.message_id = target AND a.deleted_at IS NULL
+$$;
+
+CREATE FUNCTION direct_message_attachments(target UUID)
+RETURNS JSONB
+LANGUAGE SQL STABLE
+AS $$
+    SELECT COALESCE(jsonb_agg(jsonb_build_object(
+        'id', a.id,
+        'filename', a.filename,
+        'content_type', a.content_type,
+        'size_bytes', a.size_bytes,
+        'url', '/api/attachments/' || a.id
+    ) ORDER BY a.created_at, a.id), '[]'::JSONB)
+    FROM attachments a
+    WHERE a.direct_message_id = target AND a.deleted_at IS NULL
+$$;
@@ -0,0 +1,71 @@
+-- Image attachments are measured, thumbnailed and given a blurhash by a
+-- background worker. `none` is for files that aren't processed at all.
+ALTER TABLE attachments
+    ADD COLUMN processing TEXT NOT NULL DEFAULT 'none'
+        CHECK (processing IN ('none', 'pending', 'processing', 'ready', 'failed')),
+    ADD COLUMN processing_started_at TIMESTAMPTZ,
+    -- EXIF orientation read before the metadata was stripped
+    ADD COLUMN orientation SMALLINT NOT NULL DEFAULT 1,
+    ADD COLUMN width INT,
+    ADD COLUMN height INT,
+    ADD COLUMN blurhash TEXT;
+
+CREATE INDEX idx_attachments_processing ON attachments (created_at)
+    WHERE processing IN ('pending', 'processing');
+
+CREATE TABLE attachment_thumbnails (
+    attachment_id UUID NOT NULL REFERENCES attachments(id) ON DELETE CASCADE,
+    -- Longest edge the thumbnail was fitted into
+    size INT NOT NULL,
+    width INT NOT NULL,
+    height INT NOT NULL,
+    storage_key TEXT NOT NULL UNIQUE,
+    size_bytes BIGINT NOT NULL,
+    PRIMARY KEY (attachment_id, size)
+);
+
+-- One attachment as the API shows it
+CREATE FUNCTION attachment_json(a attachments)
+RETURNS JSONB
+LANGUAGE SQL STABLE
+AS $$
+    SELECT jsonb_build_object(
+        'id', a.id,
+        'filename', a.filename,
+        'content_type', a.content_type,
+        'size_bytes', a.size_bytes,
+        'url', '/api/attachments/' || a.id,
+        'processing', a.processing,
+        'width', a.width,
+        'height', a.height,
+        'blurhash', a.blurhash,
+        'thumbnails', COALESCE((
+            SELECT jsonb_agg(jsonb_build_object(
+                'size', t.size,
+                'width', t.width,
+                'height', t.height,
+                'url', '/api/attachments/' || a.id || '/thumbnails/' || t.size
+            ) ORDER BY t.size)
+            FROM attachment_thumbnails t
+            WHERE t.attachment_id = a.id
+        ), '[]'::JSONB)
+    )
+$$;
+
+CREATE OR REPLACE FUNCTION message_attachments(target UUID)
+RETURNS JSONB
+LANGUAGE SQL STABLE
+AS $$
+    SELECT COALESCE(jsonb_agg(attachment_json(a) ORDER BY a.created_at, a.id), '[]'::JSONB)
+    FROM attachments a
+    WHERE a.message_id = target AND a.deleted_at IS NULL
+$$;
+
+CREATE OR REPLACE FUNCTION direct_message_attachments(target UUID)
+RETURNS JSONB
+LANGUAGE SQL STABLE
+AS $$
+    SELECT COALESCE(jsonb_agg(attachment_json(a) ORDER BY a.created_at, a.id), '[]'::JSONB)
+    FROM attachments a
+    WHERE a.direct_message_id = target AND a.deleted_at IS NULL
+$$;
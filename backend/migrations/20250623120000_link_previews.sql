@@ -0,0 +1,19 @@
+-- Previews of links in messages, fetched in the background after a send or
+-- edit. Each message keeps its own copy so later cache refreshes don't
+-- change what was shown.
+ALTER TABLE messages ADD COLUMN embeds JSONB NOT NULL DEFAULT '[]';
+ALTER TABLE direct_messages ADD COLUMN embeds JSONB NOT NULL DEFAULT '[]';
+
+-- One fetch per URL, shared by every message linking it. Failed fetches are
+-- cached too (`ok = FALSE`) so a dead link isn't retried on every mention.
+CREATE TABLE link_previews (
+    url TEXT PRIMARY KEY,
+    ok BOOLEAN NOT NULL,
+    title TEXT,
+    description TEXT,
+    image_url TEXT,
+    site_name TEXT,
+    fetched_at TIMESTAMPTZ NOT NULL DEFAULT NOW()
+);
+
+CREATE INDEX idx_link_previews_fetched_at ON link_previews (fetched_at);
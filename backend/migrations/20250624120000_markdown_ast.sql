@@ -0,0 +1,7 @@
+-- Parsed markdown of each message, written with the content. Messages from
+-- before this are parsed by a background job; until then the column is NULL.
+ALTER TABLE messages ADD COLUMN content_ast JSONB;
+ALTER TABLE direct_messages ADD COLUMN content_ast JSONB;
+
+CREATE INDEX idx_messages_content_ast_pending ON messages (id) WHERE content_ast IS NULL;
+CREATE INDEX idx_direct_messages_content_ast_pending ON direct_messages (id) WHERE content_ast IS NULL;
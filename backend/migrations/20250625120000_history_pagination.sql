@@ -0,0 +1,9 @@
+-- History is paged by (created_at, id). The DM index is keyed by the
+-- conversation, whichever side sent the message, so one range scan serves it.
+DROP INDEX idx_messages_room_sent;
+CREATE INDEX idx_messages_room_history ON messages (room_id, created_at, id);
+
+DROP INDEX idx_direct_messages;
+CREATE INDEX idx_direct_messages_conversation ON direct_messages (
+    LEAST(sender_id, receiver_id), GREATEST(sender_id, receiver_id), created_at, id
+);
@@ -0,0 +1,13 @@
+-- Thread replies (and forum replies) resolve mentions like top-level messages.
+-- Their notifications point at the parent message and name the reply.
+ALTER TABLE thread_replies
+    ADD COLUMN mentioned_user_ids UUID[] NOT NULL DEFAULT '{}',
+    ADD COLUMN mentioned_role_ids UUID[] NOT NULL DEFAULT '{}',
+    ADD COLUMN mentions_everyone BOOLEAN NOT NULL DEFAULT FALSE;
+
+ALTER TABLE notifications
+    ADD COLUMN thread_reply_id UUID REFERENCES thread_replies(id) ON DELETE CASCADE,
+    DROP CONSTRAINT notifications_user_id_message_id_key,
+    -- Still one per message, and one per reply within its thread
+    ADD CONSTRAINT notifications_user_message_reply_key
+        UNIQUE NULLS NOT DISTINCT (user_id, message_id, thread_reply_id);
@@ -0,0 +1,5 @@
+-- Thread and forum replies get a parsed AST like top-level messages. Older
+-- replies are parsed by the background job.
+ALTER TABLE thread_replies ADD COLUMN content_ast JSONB;
+
+CREATE INDEX idx_thread_replies_content_ast_pending ON thread_replies (id) WHERE content_ast IS NULL;
@@ -0,0 +1,35 @@
+-- Reply previews report when the quoted message was sent, so live frames can
+-- be checked against each reader's history cutoff.
+CREATE OR REPLACE FUNCTION message_reply_preview(target UUID, cutoff TIMESTAMPTZ)
+RETURNS JSONB
+LANGUAGE SQL STABLE
+AS $$
+    SELECT jsonb_build_object(
+        'author_id', m.author_id,
+        'username', u.username,
+        'snippet', CASE WHEN m.deleted_at IS NULL
+                        THEN left(regexp_replace(m.content, '\s+', ' ', 'g'), 100) END,
+        'deleted', m.deleted_at IS NOT NULL,
+        'created_at', m.created_at
+    )
+    FROM messages m
+    JOIN users u ON u.id = m.author_id
+    WHERE m.id = target AND (cutoff IS NULL OR m.created_at >= cutoff)
+$$;
+
+CREATE OR REPLACE FUNCTION direct_message_reply_preview(target UUID)
+RETURNS JSONB
+LANGUAGE SQL STABLE
+AS $$
+    SELECT jsonb_build_object(
+        'author_id', dm.sender_id,
+        'username', u.username,
+        'snippet', CASE WHEN dm.deleted_at IS NULL
+                        THEN left(regexp_replace(dm.content, '\s+', ' ', 'g'), 100) END,
+        'deleted', dm.deleted_at IS NOT NULL,
+        'created_at', dm.created_at
+    )
+    FROM direct_messages dm
+    JOIN users u ON u.id = dm.sender_id
+    WHERE dm.id = target
+$$;
@@ -0,0 +1,6 @@
+-- Messages can be posted to one of the room's channels. Messages without a
+-- channel (and those whose channel was deleted) belong to the room as a whole.
+ALTER TABLE messages
+    ADD COLUMN channel_id UUID REFERENCES room_channels(id) ON DELETE SET NULL;
+
+CREATE INDEX idx_messages_channel_history ON messages (channel_id, created_at, id) WHERE channel_id IS NOT NULL;
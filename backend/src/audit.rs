@@ -0,0 +1,86 @@
+use serde_json::Value;
+use sqlx::PgPool;
+use uuid::Uuid;
+
+/// Everything that ends up in `room_audit_log.action`.
+#[derive(Debug, Clone, Copy)]
+pub enum AuditAction {
+    MemberJoin,
+    NicknameUpdate,
+    RoomUpdate,
+    RoomDelete,
+    RoomRestore,
+    OwnerTransfer,
+    RoleCreate,
+    RoleDelete,
+    RoleAssign,
+    RoleUnassign,
+    CategoryCreate,
+    CategoryUpdate,
+    CategoryDelete,
+    ChannelCreate,
+    ChannelUpdate,
+    ChannelDelete,
+    LayoutUpdate,
+    RulesUpdate,
+    MessageDelete,
+    MessagePurge,
+}
+
+impl AuditAction {
+    pub fn as_str(self) -> &'static str {
+        match self {
+            AuditAction::MemberJoin => "member_join",
+            AuditAction::NicknameUpdate => "nickname_update",
+            AuditAction::RoomUpdate => "room_update",
+            AuditAction::RoomDelete => "room_delete",
+            AuditAction::RoomRestore => "room_restore",
+            AuditAction::OwnerTransfer => "owner_transfer",
+            AuditAction::RoleCreate => "role_create",
+            AuditAction::RoleDelete => "role_delete",
+            AuditAction::RoleAssign => "role_assign",
+            AuditAction::RoleUnassign => "role_unassign",
+            AuditAction::CategoryCreate => "category_create",
+            AuditAction::CategoryUpdate => "category_update",
+            AuditAction::CategoryDelete => "category_delete",
+            AuditAction::ChannelCreate => "channel_create",
+            AuditAction::ChannelUpdate => "channel_update",
+            AuditAction::ChannelDelete => "channel_delete",
+            AuditAction::LayoutUpdate => "layout_update",
+            AuditAction::RulesUpdate => "rules_update",
+            AuditAction::MessageDelete => "message_delete",
+            AuditAction::MessagePurge => "message_purge",
+        }
+    }
+}
+
+/// Appends an entry to the room's audit log. Failures are logged rather than
+/// surfaced: the action being audited has already happened.
+pub async fn record(
+    pool: &PgPool,
+    room_id: Uuid,
+    actor_id: Uuid,
+    action: AuditAction,
+    target_id: Option<Uuid>,
+    before: Option<Value>,
+    after: Option<Value>,
+) {
+    let result = sqlx::query!(
+        r#"
+        INSERT INTO room_audit_log (room_id, actor_id, action, target_id, before, after)
+        VALUES ($1, $2, $3, $4, $5, $6)
+        "#,
+        room_id,
+        actor_id,
+        action.as_str(),
+        target_id,
+        before,
+        after
+    )
+    .execute(pool)
+    .await;
+
+    if let Err(e) = result {
+        eprintln!("❌ Failed to write audit log entry ({}): {e}", action.as_str());
+    }
+}
@@ -0,0 +1,347 @@
+use axum::{
+    extract::{FromRequestParts, Path},
+    http::{request::Parts, StatusCode},
+};
+use serde::Serialize;
+use sqlx::PgPool;
+use std::sync::Arc;
+use time::OffsetDateTime;
+use uuid::Uuid;
+use crate::auth::middleware::CurrentUser;
+use crate::state::AppState;
+
+/// The caller's membership in the room named by the first path parameter.
+///
+/// Extracting it is the authorization check: non-members (and callers of a
+/// deleted room) are rejected with `403` before the handler runs.
+#[derive(Debug, Clone, Serialize)]
+pub struct RoomMember {
+    pub room_id: Uuid,
+    pub user_id: Uuid,
+    pub is_owner: bool,
+    pub role_ids: Vec<Uuid>,
+    /// Union of the permission bits of every role the member holds.
+    pub permissions: i64,
+    #[serde(serialize_with = "time::serde::rfc3339::serialize")]
+    pub joined_at: OffsetDateTime,
+    /// Oldest message this member may read under the room's history
+    /// visibility; `None` when the full history is visible.
+    #[serde(serialize_with = "time::serde::rfc3339::option::serialize")]
+    pub history_cutoff: Option<OffsetDateTime>,
+}
+
+impl RoomMember {
+    pub async fn load(
+        pool: &PgPool,
+        room_id: Uuid,
+        user_id: Uuid,
+    ) -> Result<Option<RoomMember>, sqlx::Error> {
+        let row = sqlx::query!(
+            r#"
+            SELECT
+                r.owner_id = rm.user_id AS "is_owner!",
+                rm.joined_at,
+                COALESCE(
+                    array_agg(mr.role_id) FILTER (WHERE mr.role_id IS NOT NULL),
+                    '{}'
+                ) AS "role_ids!",
+                COALESCE(bit_or(rr.permissions), 0) AS "permissions!",
+                room_history_cutoff(r.history_visibility, r.history_days, rm.joined_at) AS history_cutoff
+            FROM room_members rm
+            JOIN rooms r ON r.id = rm.room_id AND r.deleted_at IS NULL
+            LEFT JOIN room_member_roles mr ON mr.user_id = rm.user_id AND mr.room_id = rm.room_id
+            LEFT JOIN room_roles rr ON rr.id = mr.role_id
+            WHERE rm.room_id = $1 AND rm.user_id = $2
+            GROUP BY r.owner_id, r.history_visibility, r.history_days, rm.user_id, rm.joined_at
+            "#,
+            room_id,
+            user_id
+        )
+        .fetch_optional(pool)
+        .await?;
+
+        Ok(row.map(|row| RoomMember {
+            room_id,
+            user_id,
+            is_owner: row.is_owner,
+            role_ids: row.role_ids,
+            permissions: row.permissions,
+            joined_at: row.joined_at,
+            history_cutoff: row.history_cutoff,
+        }))
+    }
+
+    /// Whether `message_id` is a live message of this room the member is
+    /// allowed to read, and so whether its thread is open to them. Deleted
+    /// messages take their thread with them.
+    pub async fn sees_thread(&self, pool: &PgPool, message_id: Uuid) -> Result<bool, sqlx::Error> {
+        sqlx::query_scalar!(
+            r#"
+            SELECT EXISTS (
+                SELECT 1 FROM messages
+                WHERE id = $1 AND room_id = $2 AND deleted_at IS NULL
+                  AND ($3::TIMESTAMPTZ IS NULL OR created_at >= $3)
+            ) AS "exists!"
+            "#,
+            message_id,
+            self.room_id,
+            self.history_cutoff
+        )
+        .fetch_one(pool)
+        .await
+    }
+
+    pub fn can(&self, permission: i64) -> bool {
+        self.is_owner || self.permissions & permission == permission
+    }
+
+    pub fn require(&self, permission: i64) -> Result<(), (StatusCode, String)> {
+        if self.can(permission) {
+            Ok(())
+        } else {
+            Err((StatusCode::FORBIDDEN, "Missing permission for this room".into()))
+        }
+    }
+
+    pub fn require_owner(&self) -> Result<(), (StatusCode, String)> {
+        if self.is_owner {
+            Ok(())
+        } else {
+            Err((StatusCode::FORBIDDEN, "Only the room owner can do that".into()))
+        }
+    }
+}
+
+pub fn require_member(member: Option<RoomMember>) -> Result<RoomMember, (StatusCode, String)> {
+    member.ok_or((StatusCode::FORBIDDEN, "You are not a member of this room".into()))
+}
+
+impl FromRequestParts<Arc<AppState>> for RoomMember {
+    type Rejection = (StatusCode, String);
+
+    async fn from_request_parts(
+        parts: &mut Parts,
+        state: &Arc<AppState>,
+    ) -> Result<Self, Self::Rejection> {
+        let user = parts
+            .extensions
+            .get::<CurrentUser>()
+            .cloned()
+            .ok_or((StatusCode::UNAUTHORIZED, "Not authenticated".to_string()))?;
+
+        let Path(params) = Path::<Vec<(String, String)>>::from_request_parts(parts, state)
+            .await
+            .map_err(|e| (StatusCode::BAD_REQUEST, e.to_string()))?;
+        let room_id = params
+            .first()
+            .and_then(|(_, value)| Uuid::parse_str(value).ok())
+            .ok_or((StatusCode::BAD_REQUEST, "Invalid room ID".to_string()))?;
+
+        let member = RoomMember::load(&state.pool, room_id, user.id)
+            .await
+            .map_err(|_| (StatusCode::INTERNAL_SERVER_ERROR, "Authorization check failed".to_string()))?;
+
+        require_member(member)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::auth::jwt::create_jwt;
+    use crate::link_previews::LinkFetcher;
+    use crate::models::roles::permissions;
+    use crate::routes::{create_routes, ws_routes};
+    use crate::storage::local::LocalStore;
+    use axum::Router;
+    use std::collections::HashMap;
+    use tokio::sync::{Notify, RwLock};
+
+    fn member(is_owner: bool, permissions: i64) -> RoomMember {
+        RoomMember {
+            room_id: Uuid::new_v4(),
+            user_id: Uuid::new_v4(),
+            is_owner,
+            role_ids: Vec::new(),
+            permissions,
+            joined_at: OffsetDateTime::now_utc(),
+            history_cutoff: None,
+        }
+    }
+
+    #[test]
+    fn non_member_is_forbidden() {
+        let err = require_member(None).unwrap_err();
+        assert_eq!(err.0, StatusCode::FORBIDDEN);
+    }
+
+    #[test]
+    fn member_without_permission_is_forbidden() {
+        let m = member(false, permissions::MANAGE_ROLES);
+        assert!(m.require(permissions::MANAGE_ROLES).is_ok());
+        assert_eq!(m.require(permissions::MANAGE_ROOM).unwrap_err().0, StatusCode::FORBIDDEN);
+        assert_eq!(m.require_owner().unwrap_err().0, StatusCode::FORBIDDEN);
+    }
+
+    #[test]
+    fn owner_holds_every_permission() {
+        let m = member(true, 0);
+        assert!(m.require(permissions::ALL).is_ok());
+        assert!(m.require_owner().is_ok());
+    }
+
+    /// Serves the real routers on a random port and returns their base URL.
+    async fn serve(pool: PgPool) -> String {
+        let state = Arc::new(AppState {
+            pool,
+            rooms: Arc::new(RwLock::new(HashMap::new())),
+            room_restore_window: time::Duration::days(7),
+            event_reminder_lead: time::Duration::minutes(15),
+            blobs: Arc::new(LocalStore::new(std::env::temp_dir())),
+            attachment_max_bytes: 1024 * 1024,
+            image_jobs: Arc::new(Notify::new()),
+            link_fetcher: Arc::new(LinkFetcher::new(false)),
+        });
+        let app = Router::new()
+            .merge(create_routes(state.clone()))
+            .merge(ws_routes(state.clone()))
+            .with_state(state);
+
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        tokio::spawn(async move { axum::serve(listener, app).await.unwrap() });
+        format!("http://{addr}")
+    }
+
+    /// A user with a live session, and their token.
+    async fn sign_in(pool: &PgPool, username: &str) -> (Uuid, String) {
+        static SECRET: std::sync::Once = std::sync::Once::new();
+        // SAFETY: set once, before any token is created or checked
+        SECRET.call_once(|| unsafe { std::env::set_var("JWT_SECRET", "membership-tests") });
+
+        let user_id = sqlx::query_scalar!(
+            "INSERT INTO users (username, password_hash) VALUES ($1, '') RETURNING id",
+            username
+        )
+        .fetch_one(pool)
+        .await
+        .unwrap();
+        let session_id = Uuid::new_v4();
+        sqlx::query!(
+            "INSERT INTO sessions (token, user_id, expires_at) VALUES ($1, $2, NOW() + INTERVAL '1 hour')",
+            session_id.to_string(),
+            user_id
+        )
+        .execute(pool)
+        .await
+        .unwrap();
+
+        (user_id, create_jwt(&user_id.to_string(), &session_id.to_string(), username))
+    }
+
+    async fn room_owned_by(pool: &PgPool, owner_id: Uuid) -> Uuid {
+        let room_id = sqlx::query_scalar!(
+            "INSERT INTO rooms (name, owner_id) VALUES ('general', $1) RETURNING id",
+            owner_id
+        )
+        .fetch_one(pool)
+        .await
+        .unwrap();
+        sqlx::query!("INSERT INTO room_members (user_id, room_id) VALUES ($1, $2)", owner_id, room_id)
+            .execute(pool)
+            .await
+            .unwrap();
+        room_id
+    }
+
+    #[sqlx::test]
+    async fn non_member_is_forbidden_on_room_routes(pool: PgPool) {
+        let (owner_id, owner) = sign_in(&pool, "owner").await;
+        let (_, outsider) = sign_in(&pool, "outsider").await;
+        let room_id = room_owned_by(&pool, owner_id).await;
+        let base = serve(pool).await;
+        let client = reqwest::Client::new();
+
+        for path in ["messages", "members", "pins"] {
+            let get = |token: &str| client.get(format!("{base}/api/rooms/{room_id}/{path}")).bearer_auth(token).send();
+            assert_eq!(get(&owner).await.unwrap().status(), StatusCode::OK, "{path}");
+            assert_eq!(get(&outsider).await.unwrap().status(), StatusCode::FORBIDDEN, "{path}");
+        }
+
+        let post = client
+            .post(format!("{base}/api/rooms/{room_id}/messages"))
+            .bearer_auth(&outsider)
+            .header("content-type", "application/json")
+            .body(r#"{ "content": "hello" }"#)
+            .send()
+            .await
+            .unwrap();
+        assert_eq!(post.status(), StatusCode::FORBIDDEN);
+    }
+
+    async fn open_socket(client: &reqwest::Client, base: &str, room_id: Uuid, token: &str) -> reqwest::Response {
+        client
+            .get(format!("{base}/api/ws/{room_id}?token={token}"))
+            .header("connection", "upgrade")
+            .header("upgrade", "websocket")
+            .header("sec-websocket-version", "13")
+            .header("sec-websocket-key", "dGhlIHNhbXBsZSBub25jZQ==")
+            .send()
+            .await
+            .unwrap()
+    }
+
+    #[sqlx::test]
+    async fn non_member_cannot_open_the_room_socket(pool: PgPool) {
+        let (owner_id, owner) = sign_in(&pool, "owner").await;
+        let (_, outsider) = sign_in(&pool, "outsider").await;
+        let room_id = room_owned_by(&pool, owner_id).await;
+        let base = serve(pool).await;
+        let client = reqwest::Client::new();
+
+        let upgrade = open_socket(&client, &base, room_id, &owner).await;
+        assert_eq!(upgrade.status(), StatusCode::SWITCHING_PROTOCOLS);
+        let upgrade = open_socket(&client, &base, room_id, &outsider).await;
+        assert_eq!(upgrade.status(), StatusCode::FORBIDDEN);
+    }
+
+    #[sqlx::test]
+    async fn room_socket_closes_once_the_room_is_deleted(pool: PgPool) {
+        use tokio::io::{AsyncReadExt, AsyncWriteExt};
+
+        let (owner_id, owner) = sign_in(&pool, "owner").await;
+        let room_id = room_owned_by(&pool, owner_id).await;
+        let base = serve(pool).await;
+        let client = reqwest::Client::new();
+        let mut socket = open_socket(&client, &base, room_id, &owner).await.upgrade().await.unwrap();
+
+        // A masked text frame (all-zero mask) saying "hello"; seeing it come
+        // back means the socket is subscribed to the room
+        socket.write_all(b"\x81\x85\0\0\0\0hello").await.unwrap();
+        let mut received = Vec::new();
+        while !String::from_utf8_lossy(&received).contains(r#""content":"hello""#) {
+            let mut chunk = [0; 4096];
+            let n = socket.read(&mut chunk).await.unwrap();
+            assert!(n > 0, "socket closed early");
+            received.extend_from_slice(&chunk[..n]);
+        }
+
+        let deleted = client
+            .delete(format!("{base}/api/rooms/{room_id}?confirm=general"))
+            .bearer_auth(&owner)
+            .send()
+            .await
+            .unwrap();
+        assert_eq!(deleted.status(), StatusCode::OK);
+
+        // The server says why and then hangs up
+        received.clear();
+        tokio::time::timeout(std::time::Duration::from_secs(5), socket.read_to_end(&mut received))
+            .await
+            .expect("socket stayed open")
+            .unwrap();
+        let received = String::from_utf8_lossy(&received);
+        assert!(received.contains(r#""type":"room_deleted""#));
+        assert!(received.contains(r#""code":"not_member""#));
+    }
+}
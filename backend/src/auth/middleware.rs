@@ -30,6 +30,21 @@ pub async fn auth_middleware(
         .and_then(|s| s.strip_prefix("Bearer "))
         .ok_or((StatusCode::UNAUTHORIZED, "Missing or invalid Authorization header"))?;
 
+    let user = authenticate(&state, token).await?;
+
+    // Add user to request extensions
+    request.extensions_mut().insert(user);
+
+    // Continue with the request
+    Ok(next.run(request).await)
+}
+
+/// Validates a JWT and its backing session. Shared by the HTTP middleware and
+/// the WebSocket upgrades, which receive the token as a query parameter.
+pub async fn authenticate(
+    state: &AppState,
+    token: &str,
+) -> Result<CurrentUser, (StatusCode, &'static str)> {
     // Decode JWT
     let claims = decode_jwt(token)
         .map_err(|_| (StatusCode::UNAUTHORIZED, "Invalid or expired JWT"))?
@@ -62,14 +77,9 @@ pub async fn auth_middleware(
         return Err((StatusCode::UNAUTHORIZED, "Session is invalid or expired"));
     }
 
-    // Add user to request extensions
-    request.extensions_mut().insert(CurrentUser {
+    Ok(CurrentUser {
         id: user_id,
         username: claims.username,
         session_id,
-    });
-
-    // Continue with the request
-    Ok(next.run(request).await)
+    })
 }
-
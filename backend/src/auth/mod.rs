@@ -1,3 +1,4 @@
 pub mod handlers;
 pub mod jwt;
-pub mod middleware;
\ No newline at end of file
+pub mod middleware;
+pub mod membership;
@@ -0,0 +1,190 @@
+//! Keyset pagination for room and DM history. Messages are ordered by
+//! `(created_at, id)`, so ones sent in the same microsecond still keep a stable
+//! order across pages.
+
+use axum::http::StatusCode;
+use std::future::Future;
+use time::OffsetDateTime;
+use uuid::Uuid;
+use crate::models::messages::HistoryQuery;
+
+pub const DEFAULT_LIMIT: i64 = 50;
+pub const MAX_LIMIT: i64 = 100;
+
+/// A position in the history. `(t, Uuid::nil())` sits before every message
+/// sent at `t`.
+pub type Key = (OffsetDateTime, Uuid);
+
+/// Messages with `from <= key < to`; an unset bound is open.
+#[derive(Clone, Copy)]
+pub struct Range {
+    pub from: Option<Key>,
+    pub to: Option<Key>,
+}
+
+/// What the query asked for, before cursor ids are looked up.
+pub enum Cursor {
+    Latest,
+    Before(Uuid),
+    After(Uuid),
+    Around(Uuid),
+    At(OffsetDateTime),
+}
+
+impl HistoryQuery {
+    pub fn cursor(&self) -> Result<Cursor, (StatusCode, String)> {
+        let cursor = match (self.before, self.after, self.around, self.at) {
+            (None, None, None, None) => Cursor::Latest,
+            (Some(id), None, None, None) => Cursor::Before(id),
+            (None, Some(id), None, None) => Cursor::After(id),
+            (None, None, Some(id), None) => Cursor::Around(id),
+            (None, None, None, Some(at)) => Cursor::At(at),
+            _ => {
+                return Err((
+                    StatusCode::BAD_REQUEST,
+                    "Use only one of before, after, around and at".into(),
+                ));
+            }
+        };
+        Ok(cursor)
+    }
+
+    pub fn limit(&self) -> i64 {
+        self.limit.unwrap_or(DEFAULT_LIMIT).clamp(1, MAX_LIMIT)
+    }
+}
+
+impl Cursor {
+    /// The message whose key the caller needs to look up, if any.
+    pub fn message_id(&self) -> Option<Uuid> {
+        match *self {
+            Cursor::Before(id) | Cursor::After(id) | Cursor::Around(id) => Some(id),
+            Cursor::Latest | Cursor::At(_) => None,
+        }
+    }
+}
+
+/// The key right after `key`, so that an exclusive `after` cursor fits the
+/// inclusive lower bound of a `Range`. Postgres orders UUIDs bytewise, which
+/// is the order of their big-endian `u128` value.
+fn successor((created_at, id): Key) -> Key {
+    match id.as_u128().checked_add(1) {
+        Some(next) => (created_at, Uuid::from_u128(next)),
+        None => (created_at + time::Duration::microseconds(1), Uuid::nil()),
+    }
+}
+
+/// Loads one page in reading order (oldest first). `key` is the looked-up
+/// key of `cursor.message_id()`; `floor` is where the caller's visible history
+/// starts. `fetch(range, newest_first, limit)` runs the table's query.
+///
+/// - no cursor: the newest messages
+/// - `before` / `after`: the messages strictly older / newer than the cursor
+/// - `around`: the cursor message in the middle of the page
+/// - `at`: the same, centred on the first message sent at or after that time
+pub async fn load<T, F, Fut>(
+    cursor: Cursor,
+    key: Option<Key>,
+    floor: Option<Key>,
+    limit: i64,
+    fetch: F,
+) -> Result<Vec<T>, sqlx::Error>
+where
+    F: Fn(Range, bool, i64) -> Fut,
+    Fut: Future<Output = Result<Vec<T>, sqlx::Error>>,
+{
+    let at_least = |key: Key| Some(floor.map_or(key, |floor| floor.max(key)));
+
+    let split = match (cursor, key) {
+        (Cursor::Latest, _) => {
+            let mut page = fetch(Range { from: floor, to: None }, true, limit).await?;
+            page.reverse();
+            return Ok(page);
+        }
+        (Cursor::Before(_), Some(key)) => {
+            let mut page = fetch(Range { from: floor, to: Some(key) }, true, limit).await?;
+            page.reverse();
+            return Ok(page);
+        }
+        (Cursor::After(_), Some(key)) => {
+            return fetch(Range { from: at_least(successor(key)), to: None }, false, limit).await;
+        }
+        (Cursor::Around(_), Some(key)) => key,
+        (Cursor::At(at), _) => (at, Uuid::nil()),
+        _ => return Ok(Vec::new()),
+    };
+
+    // Both sides are fetched in full so a page near either end of the history
+    // is still filled from the other side.
+    let mut newer = fetch(Range { from: at_least(split), to: None }, false, limit).await?;
+    let mut older = fetch(Range { from: floor, to: Some(split) }, true, limit).await?;
+    let limit = limit as usize;
+    let take_older = older.len().min((limit / 2).max(limit - newer.len()));
+    older.truncate(take_older);
+    newer.truncate(limit - take_older);
+    older.reverse();
+    older.extend(newer);
+    Ok(older)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Six messages, three of them sharing a timestamp.
+    fn messages() -> Vec<Key> {
+        let t = OffsetDateTime::UNIX_EPOCH;
+        let s = time::Duration::seconds;
+        vec![
+            (t, Uuid::from_u128(5)),
+            (t + s(1), Uuid::from_u128(3)),
+            (t + s(1), Uuid::from_u128(4)),
+            (t + s(1), Uuid::from_u128(u128::MAX)),
+            (t + s(2), Uuid::from_u128(1)),
+            (t + s(3), Uuid::from_u128(2)),
+        ]
+    }
+
+    async fn page(cursor: Cursor, floor: Option<Key>, limit: i64) -> Vec<usize> {
+        let all = messages();
+        let key = cursor.message_id().map(|id| *all.iter().find(|m| m.1 == id).unwrap());
+        let fetch = |range: Range, newest_first: bool, limit: i64| {
+            let mut rows: Vec<Key> = all
+                .iter()
+                .copied()
+                .filter(|m| range.from.is_none_or(|from| *m >= from) && range.to.is_none_or(|to| *m < to))
+                .collect();
+            if newest_first {
+                rows.reverse();
+            }
+            rows.truncate(limit as usize);
+            async move { Ok::<_, sqlx::Error>(rows) }
+        };
+        let rows = load(cursor, key, floor, limit, fetch).await.unwrap();
+        rows.iter().map(|row| all.iter().position(|m| m == row).unwrap()).collect()
+    }
+
+    #[tokio::test]
+    async fn pages_follow_each_other_through_equal_timestamps() {
+        assert_eq!(page(Cursor::Latest, None, 2).await, [4, 5]);
+        assert_eq!(page(Cursor::Before(Uuid::from_u128(1)), None, 2).await, [2, 3]);
+        assert_eq!(page(Cursor::Before(Uuid::from_u128(4)), None, 2).await, [0, 1]);
+        assert_eq!(page(Cursor::After(Uuid::from_u128(3)), None, 2).await, [2, 3]);
+        assert_eq!(page(Cursor::After(Uuid::from_u128(u128::MAX)), None, 2).await, [4, 5]);
+    }
+
+    #[tokio::test]
+    async fn around_and_at_centre_the_page() {
+        assert_eq!(page(Cursor::Around(Uuid::from_u128(4)), None, 3).await, [1, 2, 3]);
+        assert_eq!(page(Cursor::Around(Uuid::from_u128(2)), None, 4).await, [2, 3, 4, 5]);
+        let at = OffsetDateTime::UNIX_EPOCH + time::Duration::milliseconds(1500);
+        assert_eq!(page(Cursor::At(at), None, 2).await, [3, 4]);
+    }
+
+    #[tokio::test]
+    async fn nothing_before_the_floor() {
+        let floor = Some((OffsetDateTime::UNIX_EPOCH + time::Duration::seconds(1), Uuid::nil()));
+        assert_eq!(page(Cursor::Latest, floor, 10).await, [1, 2, 3, 4, 5]);
+        assert_eq!(page(Cursor::Around(Uuid::from_u128(3)), floor, 4).await, [1, 2, 3, 4]);
+    }
+}
@@ -0,0 +1,124 @@
+use time::{OffsetDateTime, UtcOffset};
+use crate::models::scheduled_events::ScheduledEvent;
+
+/// Renders room events as an RFC 5545 calendar.
+pub fn calendar(name: &str, events: &[ScheduledEvent]) -> String {
+    let mut out = String::new();
+    let now = timestamp(OffsetDateTime::now_utc());
+
+    push_line(&mut out, "BEGIN:VCALENDAR");
+    push_line(&mut out, "VERSION:2.0");
+    push_line(&mut out, "PRODID:-//Rusty//Room events//EN");
+    push_line(&mut out, "CALSCALE:GREGORIAN");
+    push_line(&mut out, &format!("X-WR-CALNAME:{}", escape(name)));
+
+    for event in events {
+        push_line(&mut out, "BEGIN:VEVENT");
+        push_line(&mut out, &format!("UID:{}@rusty", event.id));
+        push_line(&mut out, &format!("DTSTAMP:{now}"));
+        push_line(&mut out, &format!("DTSTART:{}", timestamp(event.starts_at)));
+        push_line(&mut out, &format!("DTEND:{}", timestamp(event.ends_at)));
+        push_line(&mut out, &format!("LAST-MODIFIED:{}", timestamp(event.updated_at)));
+        push_line(&mut out, &format!("SUMMARY:{}", escape(&event.title)));
+        if let Some(description) = &event.description {
+            push_line(&mut out, &format!("DESCRIPTION:{}", escape(description)));
+        }
+        if let Some(location) = &event.location {
+            push_line(&mut out, &format!("LOCATION:{}", escape(location)));
+        }
+        push_line(&mut out, "END:VEVENT");
+    }
+
+    push_line(&mut out, "END:VCALENDAR");
+    out
+}
+
+/// UTC "form #2" date-time, e.g. `20250614T180000Z`.
+fn timestamp(at: OffsetDateTime) -> String {
+    let at = at.to_offset(UtcOffset::UTC);
+    format!(
+        "{:04}{:02}{:02}T{:02}{:02}{:02}Z",
+        at.year(),
+        at.month() as u8,
+        at.day(),
+        at.hour(),
+        at.minute(),
+        at.second()
+    )
+}
+
+/// TEXT value escaping: backslash, `;`, `,` and newlines.
+fn escape(text: &str) -> String {
+    let mut out = String::with_capacity(text.len());
+    for c in text.chars() {
+        match c {
+            '\\' => out.push_str("\\\\"),
+            ';' => out.push_str("\\;"),
+            ',' => out.push_str("\\,"),
+            '\n' => out.push_str("\\n"),
+            '\r' => {}
+            c => out.push(c),
+        }
+    }
+    out
+}
+
+/// Appends a content line, folded at 75 octets without splitting a character.
+fn push_line(out: &mut String, line: &str) {
+    let mut width = 0;
+    for c in line.chars() {
+        if width + c.len_utf8() > 75 {
+            out.push_str("\r\n ");
+            width = 1;
+        }
+        out.push(c);
+        width += c.len_utf8();
+    }
+    out.push_str("\r\n");
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use time::macros::datetime;
+
+    #[test]
+    fn escapes_text_values() {
+        assert_eq!(escape(r"a\b"), r"a\\b");
+        assert_eq!(escape("one; two, three"), r"one\; two\, three");
+        assert_eq!(escape("line one\r\nline two\nend"), r"line one\nline two\nend");
+    }
+
+    #[test]
+    fn folds_long_lines_at_75_octets() {
+        let mut out = String::new();
+        let summary = format!("SUMMARY:{}", "x".repeat(100));
+        push_line(&mut out, &summary);
+
+        let lines: Vec<&str> = out.trim_end_matches("\r\n").split("\r\n").collect();
+        assert_eq!(lines.len(), 2);
+        assert_eq!(lines[0].len(), 75);
+        assert!(lines[1].starts_with(' '));
+        assert_eq!(lines.join("\r\n").replace("\r\n ", ""), summary);
+    }
+
+    #[test]
+    fn folding_never_splits_a_character() {
+        let mut out = String::new();
+        // Three-octet characters straddle the 75-octet boundary
+        let summary = format!("SUMMARY:{}", "日".repeat(40));
+        push_line(&mut out, &summary);
+
+        for line in out.trim_end_matches("\r\n").split("\r\n") {
+            assert!(line.len() <= 75, "{} octets", line.len());
+        }
+        let unfolded = out.trim_end_matches("\r\n").replace("\r\n ", "");
+        assert_eq!(unfolded, summary);
+    }
+
+    #[test]
+    fn timestamps_are_utc() {
+        assert_eq!(timestamp(datetime!(2025-06-14 18:00:00 UTC)), "20250614T180000Z");
+        assert_eq!(timestamp(datetime!(2025-06-14 20:30:05 +02:00)), "20250614T183005Z");
+    }
+}
@@ -0,0 +1,470 @@
+//! Image attachments: metadata stripping at upload time, and the decoding work
+//! (dimensions, thumbnails, blurhash) the background worker does afterwards.
+
+use image::metadata::Orientation;
+use image::{DynamicImage, GenericImageView, ImageError, ImageFormat, ImageReader, Limits};
+use std::io::Cursor;
+
+/// Longest edge of each generated thumbnail. Images already smaller than a
+/// size don't get that thumbnail.
+pub const THUMBNAIL_SIZES: [u32; 2] = [256, 1024];
+
+/// Formats that are stripped and processed. Other files are stored as-is.
+pub fn is_processable(content_type: &str) -> bool {
+    matches!(content_type, "image/jpeg" | "image/png" | "image/webp" | "image/gif")
+}
+
+/// Thrown out when the file doesn't have the structure its type promises.
+#[derive(Debug)]
+pub struct Malformed;
+
+/// Removes EXIF, XMP, comments and text metadata (GPS position included) without
+/// re-encoding, and returns the cleaned bytes with the EXIF orientation so the
+/// worker can still rotate the picture upright.
+pub fn strip_metadata(content_type: &str, data: &[u8]) -> Result<(Vec<u8>, u8), Malformed> {
+    let (data, orientation) = match content_type {
+        "image/jpeg" => strip_jpeg(data)?,
+        "image/png" => strip_png(data)?,
+        "image/webp" => strip_webp(data)?,
+        "image/gif" => (strip_gif(data)?, None),
+        _ => (data.to_vec(), None),
+    };
+    Ok((data, orientation.unwrap_or(Orientation::NoTransforms).to_exif()))
+}
+
+fn exif_orientation(exif: &[u8]) -> Option<Orientation> {
+    let tiff = exif.strip_prefix(b"Exif\0\0").unwrap_or(exif);
+    Orientation::from_exif_chunk(tiff)
+}
+
+/// Drops APP1 (EXIF, XMP), APP13 (IPTC), MPF and comment segments, and
+/// everything after the end of image: phones append secondary images there
+/// (depth maps, previews) that carry their own EXIF and GPS.
+fn strip_jpeg(data: &[u8]) -> Result<(Vec<u8>, Option<Orientation>), Malformed> {
+    if !data.starts_with(&[0xFF, 0xD8]) {
+        return Err(Malformed);
+    }
+    let mut out = Vec::with_capacity(data.len());
+    out.extend_from_slice(&data[..2]);
+    let mut orientation = None;
+    let mut pos = 2;
+
+    loop {
+        let marker = match data.get(pos..pos + 2) {
+            Some([0xFF, marker]) => *marker,
+            _ => return Err(Malformed),
+        };
+        // Fill byte before a marker
+        if marker == 0xFF {
+            pos += 1;
+            continue;
+        }
+        if marker == 0xD9 {
+            out.extend_from_slice(&data[pos..pos + 2]);
+            return Ok((out, orientation));
+        }
+        if marker == 0x01 || (0xD0..=0xD7).contains(&marker) {
+            out.extend_from_slice(&data[pos..pos + 2]);
+            pos += 2;
+            continue;
+        }
+
+        let len = match data.get(pos + 2..pos + 4) {
+            Some(len) => u16::from_be_bytes([len[0], len[1]]) as usize,
+            None => return Err(Malformed),
+        };
+        if len < 2 {
+            return Err(Malformed);
+        }
+        let segment = data.get(pos..pos + 2 + len).ok_or(Malformed)?;
+        match marker {
+            0xE1 => {
+                let payload = &segment[4..];
+                if payload.starts_with(b"Exif\0\0") {
+                    orientation = exif_orientation(payload);
+                }
+            }
+            0xE2 if segment[4..].starts_with(b"MPF\0") => {}
+            0xED | 0xFE => {}
+            _ => out.extend_from_slice(segment),
+        }
+        pos += 2 + len;
+
+        // Entropy-coded data follows a start of scan. Inside it 0xFF is only
+        // followed by a stuffed zero or a restart marker; any other marker
+        // ends the scan.
+        if marker == 0xDA {
+            let start = pos;
+            loop {
+                match data.get(pos..pos + 2) {
+                    Some([0xFF, 0x00 | 0xD0..=0xD7]) => pos += 2,
+                    Some([0xFF, _]) => break,
+                    Some(_) => pos += 1,
+                    None => return Err(Malformed),
+                }
+            }
+            out.extend_from_slice(&data[start..pos]);
+        }
+    }
+}
+
+/// Drops `eXIf` and the text / timestamp chunks, and anything after `IEND`.
+fn strip_png(data: &[u8]) -> Result<(Vec<u8>, Option<Orientation>), Malformed> {
+    const SIGNATURE: &[u8] = b"\x89PNG\r\n\x1a\n";
+    if !data.starts_with(SIGNATURE) {
+        return Err(Malformed);
+    }
+    let mut out = Vec::with_capacity(data.len());
+    out.extend_from_slice(SIGNATURE);
+    let mut orientation = None;
+    let mut pos = SIGNATURE.len();
+
+    loop {
+        let header = data.get(pos..pos + 8).ok_or(Malformed)?;
+        let len = u32::from_be_bytes([header[0], header[1], header[2], header[3]]) as usize;
+        let kind = &header[4..8];
+        let chunk = data.get(pos..pos + 12 + len).ok_or(Malformed)?;
+
+        match kind {
+            b"eXIf" => orientation = exif_orientation(&chunk[8..8 + len]),
+            b"tEXt" | b"zTXt" | b"iTXt" | b"tIME" => {}
+            _ => out.extend_from_slice(chunk),
+        }
+        pos += 12 + len;
+
+        if kind == b"IEND" {
+            return Ok((out, orientation));
+        }
+    }
+}
+
+/// Drops the `EXIF` and `XMP ` chunks and clears their flags in `VP8X`.
+fn strip_webp(data: &[u8]) -> Result<(Vec<u8>, Option<Orientation>), Malformed> {
+    if data.len() < 12 || &data[..4] != b"RIFF" || &data[8..12] != b"WEBP" {
+        return Err(Malformed);
+    }
+    let mut chunks = Vec::with_capacity(data.len());
+    let mut orientation = None;
+    let mut pos = 12;
+
+    while pos < data.len() {
+        let header = data.get(pos..pos + 8).ok_or(Malformed)?;
+        let len = u32::from_le_bytes([header[4], header[5], header[6], header[7]]) as usize;
+        let padded = len + (len & 1);
+        let chunk = data.get(pos..(pos + 8 + padded).min(data.len())).ok_or(Malformed)?;
+        if chunk.len() < 8 + len {
+            return Err(Malformed);
+        }
+
+        match &header[..4] {
+            b"EXIF" => orientation = exif_orientation(&chunk[8..8 + len]),
+            b"XMP " => {}
+            b"VP8X" if len >= 1 => {
+                let mut chunk = chunk.to_vec();
+                chunk[8] &= !(0x08 | 0x04);
+                chunks.extend_from_slice(&chunk);
+            }
+            _ => chunks.extend_from_slice(chunk),
+        }
+        pos += 8 + padded;
+    }
+
+    let mut out = Vec::with_capacity(chunks.len() + 12);
+    out.extend_from_slice(b"RIFF");
+    out.extend_from_slice(&(4 + chunks.len() as u32).to_le_bytes());
+    out.extend_from_slice(b"WEBP");
+    out.extend_from_slice(&chunks);
+    Ok((out, orientation))
+}
+
+/// Drops comments and application extensions (XMP rides in one) except the
+/// animation loop count, and anything after the trailer.
+fn strip_gif(data: &[u8]) -> Result<Vec<u8>, Malformed> {
+    if data.len() < 13 || !(data.starts_with(b"GIF87a") || data.starts_with(b"GIF89a")) {
+        return Err(Malformed);
+    }
+    let color_table = |packed: u8| if packed & 0x80 != 0 { 3usize << ((packed & 7) + 1) } else { 0 };
+    let mut pos = 13 + color_table(data[10]);
+    let mut out = data.get(..pos).ok_or(Malformed)?.to_vec();
+
+    loop {
+        match *data.get(pos).ok_or(Malformed)? {
+            0x3B => {
+                out.push(0x3B);
+                return Ok(out);
+            }
+            // Image descriptor, local color table and LZW code size, then the pixels
+            0x2C => {
+                let packed = *data.get(pos + 9).ok_or(Malformed)?;
+                let end = skip_sub_blocks(data, pos + 11 + color_table(packed))?;
+                out.extend_from_slice(&data[pos..end]);
+                pos = end;
+            }
+            0x21 => {
+                let label = *data.get(pos + 1).ok_or(Malformed)?;
+                let end = skip_sub_blocks(data, pos + 2)?;
+                let block = &data[pos..end];
+                let keep = match label {
+                    0xFE => false,
+                    0xFF => matches!(block.get(3..14), Some(b"NETSCAPE2.0" | b"ANIMEXTS1.0")),
+                    _ => true,
+                };
+                if keep {
+                    out.extend_from_slice(block);
+                }
+                pos = end;
+            }
+            _ => return Err(Malformed),
+        }
+    }
+}
+
+/// Index just past a chain of GIF data sub-blocks and its terminator.
+fn skip_sub_blocks(data: &[u8], mut pos: usize) -> Result<usize, Malformed> {
+    loop {
+        let len = *data.get(pos).ok_or(Malformed)? as usize;
+        pos += 1 + len;
+        if len == 0 {
+            return Ok(pos);
+        }
+    }
+}
+
+pub struct Thumbnail {
+    pub size: u32,
+    pub width: u32,
+    pub height: u32,
+    pub webp: Vec<u8>,
+}
+
+pub struct Processed {
+    pub width: u32,
+    pub height: u32,
+    pub blurhash: String,
+    pub thumbnails: Vec<Thumbnail>,
+    /// The original rotated upright and re-encoded, when its orientation
+    /// wasn't already upright.
+    pub upright: Option<Vec<u8>>,
+}
+
+/// Decodes an already stripped image and derives everything clients show.
+/// CPU-bound; run it off the async runtime.
+pub fn process(data: &[u8], orientation: u8) -> Result<Processed, ImageError> {
+    let mut reader = ImageReader::new(Cursor::new(data)).with_guessed_format()?;
+    let mut limits = Limits::default();
+    limits.max_image_width = Some(12_000);
+    limits.max_image_height = Some(12_000);
+    limits.max_alloc = Some(256 * 1024 * 1024);
+    reader.limits(limits);
+
+    let format = reader.format();
+    let mut image = reader.decode()?;
+
+    let orientation = Orientation::from_exif(orientation).unwrap_or(Orientation::NoTransforms);
+    let upright = if orientation == Orientation::NoTransforms {
+        None
+    } else {
+        image.apply_orientation(orientation);
+        match format {
+            Some(format @ (ImageFormat::Jpeg | ImageFormat::Png | ImageFormat::WebP)) => Some(encode(&image, format)?),
+            _ => None,
+        }
+    };
+
+    let (width, height) = image.dimensions();
+
+    let mut thumbnails = Vec::new();
+    for size in THUMBNAIL_SIZES {
+        if width.max(height) <= size {
+            continue;
+        }
+        let thumbnail = image.thumbnail(size, size);
+        thumbnails.push(Thumbnail {
+            size,
+            width: thumbnail.width(),
+            height: thumbnail.height(),
+            webp: encode(&thumbnail, ImageFormat::WebP)?,
+        });
+    }
+
+    let small = image.thumbnail(32, 32).to_rgba8();
+    let blurhash = blurhash::encode(4, 3, small.width(), small.height(), small.as_raw())
+        .map_err(|e| ImageError::IoError(std::io::Error::other(e.to_string())))?;
+
+    Ok(Processed { width, height, blurhash, thumbnails, upright })
+}
+
+/// JPEG has no alpha channel and the WebP encoder only takes RGB(A), so
+/// pixels are normalised first.
+fn encode(image: &DynamicImage, format: ImageFormat) -> Result<Vec<u8>, ImageError> {
+    let image = match format {
+        ImageFormat::Jpeg => DynamicImage::ImageRgb8(image.to_rgb8()),
+        _ => DynamicImage::ImageRgba8(image.to_rgba8()),
+    };
+    let mut out = Cursor::new(Vec::new());
+    image.write_to(&mut out, format)?;
+    Ok(out.into_inner())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use image::{Rgb, RgbImage};
+
+    /// Big-endian TIFF with an orientation tag and a GPS IFD holding a latitude.
+    fn exif(orientation: u16) -> Vec<u8> {
+        let mut tiff = b"MM\0\x2A\0\0\0\x08".to_vec();
+        tiff.extend_from_slice(&[0, 2]);
+        tiff.extend_from_slice(&[0x01, 0x12, 0, 3, 0, 0, 0, 1]);
+        tiff.extend_from_slice(&orientation.to_be_bytes());
+        tiff.extend_from_slice(&[0, 0]);
+        tiff.extend_from_slice(&[0x88, 0x25, 0, 4, 0, 0, 0, 1, 0, 0, 0, 38]);
+        tiff.extend_from_slice(&[0, 0, 0, 0]);
+        tiff.extend_from_slice(&[0, 1]);
+        tiff.extend_from_slice(&[0x00, 0x01, 0, 2, 0, 0, 0, 2, b'N', 0, 0, 0]);
+        tiff.extend_from_slice(&[0, 0, 0, 0]);
+        tiff
+    }
+
+    fn contains(haystack: &[u8], needle: &[u8]) -> bool {
+        haystack.windows(needle.len()).any(|w| w == needle)
+    }
+
+    fn encoded(width: u32, height: u32, format: ImageFormat) -> Vec<u8> {
+        let image = RgbImage::from_fn(width, height, |x, y| Rgb([x as u8, y as u8, 128]));
+        encode(&DynamicImage::ImageRgb8(image), format).unwrap()
+    }
+
+    fn jpeg_with_exif(orientation: u16) -> Vec<u8> {
+        let plain = encoded(16, 8, ImageFormat::Jpeg);
+        let payload = [b"Exif\0\0".as_slice(), &exif(orientation)].concat();
+        let mut out = vec![0xFF, 0xD8, 0xFF, 0xE1];
+        out.extend_from_slice(&(payload.len() as u16 + 2).to_be_bytes());
+        out.extend_from_slice(&payload);
+        out.extend_from_slice(&plain[2..]);
+        out
+    }
+
+    #[test]
+    fn jpeg_loses_exif_and_trailing_images() {
+        let mut data = jpeg_with_exif(6);
+        // A phone's secondary image after the end of the primary one
+        data.extend_from_slice(&jpeg_with_exif(1));
+
+        let (stripped, orientation) = strip_metadata("image/jpeg", &data).unwrap();
+        assert_eq!(orientation, 6);
+        assert!(!contains(&stripped, &exif(6)) && !contains(&stripped, &exif(1)));
+        assert!(stripped.ends_with(&[0xFF, 0xD9]));
+        assert_eq!(image::load_from_memory(&stripped).unwrap().width(), 16);
+    }
+
+    #[test]
+    fn png_loses_exif_text_and_trailing_data() {
+        let plain = encoded(16, 8, ImageFormat::Png);
+        let chunk = |kind: &[u8], body: &[u8]| {
+            [&(body.len() as u32).to_be_bytes(), kind, body, &[0; 4]].concat()
+        };
+        // After the signature and IHDR
+        let mut data = plain[..33].to_vec();
+        data.extend_from_slice(&chunk(b"eXIf", &exif(3)));
+        data.extend_from_slice(&chunk(b"tEXt", b"Comment\0taken at home"));
+        data.extend_from_slice(&plain[33..]);
+        data.extend_from_slice(b"trailing");
+
+        let (stripped, orientation) = strip_metadata("image/png", &data).unwrap();
+        assert_eq!(orientation, 3);
+        assert_eq!(stripped, plain);
+    }
+
+    #[test]
+    fn webp_loses_exif_and_xmp() {
+        let plain = encoded(16, 8, ImageFormat::WebP);
+        let mut data = plain.clone();
+        let tiff = exif(8);
+        for (kind, body) in [(b"EXIF", tiff.as_slice()), (b"XMP ", b"<x:xmpmeta/>".as_slice())] {
+            data.extend_from_slice(kind);
+            data.extend_from_slice(&(body.len() as u32).to_le_bytes());
+            data.extend_from_slice(body);
+            if body.len() % 2 == 1 {
+                data.push(0);
+            }
+        }
+        let size = data.len() as u32 - 8;
+        data[4..8].copy_from_slice(&size.to_le_bytes());
+
+        let (stripped, orientation) = strip_metadata("image/webp", &data).unwrap();
+        assert_eq!(orientation, 8);
+        assert_eq!(stripped, plain);
+    }
+
+    #[test]
+    fn gif_loses_comments_xmp_and_trailing_data() {
+        let plain = encoded(16, 8, ImageFormat::Gif);
+        let mut data = plain[..plain.len() - 1].to_vec();
+        data.extend_from_slice(&[0x21, 0xFE, 4]);
+        data.extend_from_slice(b"home");
+        data.push(0);
+        data.extend_from_slice(&[0x21, 0xFF, 11]);
+        data.extend_from_slice(b"XMP DataXMP");
+        data.push(3);
+        data.extend_from_slice(b"<x>");
+        data.push(0);
+        data.push(0x3B);
+        data.extend_from_slice(b"trailing");
+
+        let (stripped, orientation) = strip_metadata("image/gif", &data).unwrap();
+        assert_eq!(orientation, 1);
+        assert_eq!(stripped, plain);
+    }
+
+    #[test]
+    fn truncated_or_garbled_input_is_malformed() {
+        let files = [
+            ("image/jpeg", jpeg_with_exif(1)),
+            ("image/png", encoded(16, 8, ImageFormat::Png)),
+            ("image/gif", encoded(16, 8, ImageFormat::Gif)),
+        ];
+        for (content_type, data) in files {
+            for len in 0..data.len() {
+                assert!(strip_metadata(content_type, &data[..len]).is_err(), "{content_type} cut at {len}");
+            }
+        }
+
+        // A WebP can end on any chunk boundary; just never panic
+        let webp = encoded(16, 8, ImageFormat::WebP);
+        for len in 0..webp.len() {
+            let _ = strip_metadata("image/webp", &webp[..len]);
+        }
+
+        let mut garbled = jpeg_with_exif(1);
+        garbled[4..6].copy_from_slice(&[0xFF, 0xFF]);
+        assert!(strip_metadata("image/jpeg", &garbled).is_err());
+        assert!(strip_metadata("image/png", b"\x89PNG\r\n\x1a\n\xFF\xFF\xFF\xFFIHDR").is_err());
+    }
+
+    #[test]
+    fn thumbnails_and_blurhash_come_out_at_their_sizes() {
+        let processed = process(&encoded(1100, 550, ImageFormat::Png), 1).unwrap();
+        assert_eq!((processed.width, processed.height), (1100, 550));
+        let sizes: Vec<_> = processed.thumbnails.iter().map(|t| (t.size, t.width, t.height)).collect();
+        assert_eq!(sizes, [(256, 256, 128), (1024, 1024, 512)]);
+        for thumbnail in &processed.thumbnails {
+            let decoded = image::load_from_memory_with_format(&thumbnail.webp, ImageFormat::WebP).unwrap();
+            assert_eq!((decoded.width(), decoded.height()), (thumbnail.width, thumbnail.height));
+        }
+        // 4x3 components
+        assert_eq!(processed.blurhash.len(), 28);
+        assert!(processed.upright.is_none());
+
+        let small = process(&encoded(100, 50, ImageFormat::Png), 1).unwrap();
+        assert!(small.thumbnails.is_empty());
+    }
+
+    #[test]
+    fn orientation_is_applied() {
+        let processed = process(&encoded(40, 20, ImageFormat::Png), 6).unwrap();
+        assert_eq!((processed.width, processed.height), (20, 40));
+        let upright = image::load_from_memory(&processed.upright.unwrap()).unwrap();
+        assert_eq!((upright.width(), upright.height()), (20, 40));
+    }
+}
@@ -0,0 +1,498 @@
+use std::sync::Arc;
+use std::time::Duration;
+use time::OffsetDateTime;
+use axum::body::Bytes;
+use uuid::Uuid;
+use crate::images;
+use crate::markdown::{self, Refs};
+use crate::messaging;
+use crate::models::attachments::Attachment;
+use crate::models::events::{DmEvent, RoomEvent};
+use crate::state::AppState;
+
+/// Hard-deletes soft-deleted rooms once their restore window has passed.
+/// The cascade on `rooms` takes members and messages with them.
+pub async fn purge_deleted_rooms(state: Arc<AppState>) {
+    let mut interval = tokio::time::interval(Duration::from_secs(60 * 10));
+
+    loop {
+        interval.tick().await;
+
+        let cutoff = OffsetDateTime::now_utc() - state.room_restore_window;
+        let result = sqlx::query!(
+            r#"
+            DELETE FROM rooms
+            WHERE deleted_at IS NOT NULL AND deleted_at <= $1
+            "#,
+            cutoff
+        )
+        .execute(&state.pool)
+        .await;
+
+        match result {
+            Ok(r) if r.rows_affected() > 0 => {
+                println!("🧹 Purged {} deleted room(s)", r.rows_affected())
+            }
+            Ok(_) => {}
+            Err(e) => eprintln!("❌ Room purge failed: {e}"),
+        }
+    }
+}
+
+/// Posts a system message into the room shortly before each event starts.
+/// Events are claimed by setting `reminder_sent_at` first, so a reminder is
+/// never sent twice; ones whose start already passed are skipped.
+pub async fn send_event_reminders(state: Arc<AppState>) {
+    let mut interval = tokio::time::interval(Duration::from_secs(30));
+
+    loop {
+        interval.tick().await;
+
+        let now = OffsetDateTime::now_utc();
+        let due = sqlx::query!(
+            r#"
+            UPDATE room_events e
+            SET reminder_sent_at = NOW()
+            FROM rooms r
+            WHERE r.id = e.room_id AND r.deleted_at IS NULL
+              AND e.reminder_sent_at IS NULL
+              AND e.starts_at > $1 AND e.starts_at <= $2
+            RETURNING e.room_id, COALESCE(e.creator_id, r.owner_id) AS "author_id!",
+                      e.title, e.location, e.starts_at
+            "#,
+            now,
+            now + state.event_reminder_lead
+        )
+        .fetch_all(&state.pool)
+        .await;
+
+        let due = match due {
+            Ok(due) => due,
+            Err(e) => {
+                eprintln!("❌ Event reminders failed: {e}");
+                continue;
+            }
+        };
+
+        for event in due {
+            let minutes = (event.starts_at - now).whole_minutes().max(1);
+            let mut content = format!("⏰ \"{}\" starts in {minutes} min", event.title);
+            if let Some(location) = event.location {
+                content.push_str(&format!(" at {location}"));
+            }
+
+            if let Err(e) =
+                messaging::post_system_message(&state, event.room_id, event.author_id, content).await
+            {
+                eprintln!("❌ Event reminder failed: {e}");
+            }
+        }
+    }
+}
+
+/// Removes attachments that no message holds any more: uploads never sent
+/// within a day, and those released by message deletion or a room purge.
+/// Rows go first so nothing points at a missing blob; thumbnails go with
+/// their image.
+pub async fn purge_attachments(state: Arc<AppState>) {
+    let mut interval = tokio::time::interval(Duration::from_secs(60 * 10));
+
+    loop {
+        interval.tick().await;
+
+        let released = sqlx::query_scalar!(
+            r#"
+            WITH released AS (
+                DELETE FROM attachments
+                WHERE id IN (
+                    SELECT id FROM attachments
+                    WHERE message_id IS NULL AND direct_message_id IS NULL
+                      AND (deleted_at IS NOT NULL OR created_at < NOW() - INTERVAL '1 day')
+                    LIMIT 500
+                )
+                RETURNING id, storage_key
+            )
+            SELECT storage_key AS "storage_key!" FROM released
+            UNION ALL
+            SELECT t.storage_key FROM attachment_thumbnails t
+            JOIN released r ON r.id = t.attachment_id
+            "#
+        )
+        .fetch_all(&state.pool)
+        .await;
+
+        let keys = match released {
+            Ok(keys) => keys,
+            Err(e) => {
+                eprintln!("❌ Attachment cleanup failed: {e}");
+                continue;
+            }
+        };
+
+        for key in &keys {
+            if let Err(e) = state.blobs.delete(key).await {
+                eprintln!("❌ Failed to delete blob {key}: {e}");
+            }
+        }
+        if !keys.is_empty() {
+            println!("🧹 Removed {} unused attachment(s)", keys.len());
+        }
+    }
+}
+
+/// Images claimed per round; each is decoded on the blocking pool.
+const IMAGE_BATCH: i64 = 4;
+
+/// Measures queued images, writes their thumbnails and blurhash, and tells
+/// the message's listeners. Woken by uploads, with a periodic sweep that also
+/// picks up jobs left `processing` by a crashed worker.
+pub async fn process_images(state: Arc<AppState>) {
+    let mut interval = tokio::time::interval(Duration::from_secs(30));
+
+    loop {
+        tokio::select! {
+            _ = interval.tick() => {}
+            _ = state.image_jobs.notified() => {}
+        }
+
+        loop {
+            let claimed = sqlx::query!(
+                r#"
+                UPDATE attachments
+                SET processing = 'processing', processing_started_at = NOW()
+                WHERE id IN (
+                    SELECT id FROM attachments
+                    WHERE deleted_at IS NULL
+                      AND (processing = 'pending'
+                           OR (processing = 'processing' AND processing_started_at < NOW() - INTERVAL '10 minutes'))
+                    ORDER BY created_at
+                    LIMIT $1
+                    FOR UPDATE SKIP LOCKED
+                )
+                RETURNING id, storage_key, content_type, orientation
+                "#,
+                IMAGE_BATCH
+            )
+            .fetch_all(&state.pool)
+            .await;
+
+            let claimed = match claimed {
+                Ok(claimed) if !claimed.is_empty() => claimed,
+                Ok(_) => break,
+                Err(e) => {
+                    eprintln!("❌ Image processing failed to claim jobs: {e}");
+                    break;
+                }
+            };
+
+            for job in claimed {
+                let result = process_image(&state, job.id, &job.storage_key, &job.content_type, job.orientation).await;
+                if let Err(e) = result {
+                    eprintln!("❌ Image processing failed for {}: {e}", job.id);
+                    let failed = sqlx::query_scalar!(
+                        r#"
+                        UPDATE attachments SET processing = 'failed'
+                        WHERE id = $1
+                        RETURNING attachment_json(attachments) AS "attachment!: sqlx::types::Json<Attachment>"
+                        "#,
+                        job.id
+                    )
+                    .fetch_optional(&state.pool)
+                    .await;
+                    if let Ok(Some(attachment)) = failed {
+                        announce_attachment(&state, attachment.0).await;
+                    }
+                }
+            }
+        }
+    }
+}
+
+async fn process_image(
+    state: &AppState,
+    id: Uuid,
+    storage_key: &str,
+    content_type: &str,
+    orientation: i16,
+) -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
+    let data = state.blobs.get(storage_key).await?;
+    let processed = tokio::task::spawn_blocking(move || images::process(&data, orientation as u8)).await??;
+
+    let mut thumbnail_keys = Vec::with_capacity(processed.thumbnails.len());
+    for thumbnail in &processed.thumbnails {
+        let key = format!("thumbnails/{id}_{}.webp", thumbnail.size);
+        state.blobs.put(&key, Bytes::from(thumbnail.webp.clone()), "image/webp").await?;
+        thumbnail_keys.push(key);
+    }
+
+    // The upright copy replaces the original, which had only lost its metadata
+    let mut size_bytes = None;
+    if let Some(upright) = processed.upright {
+        size_bytes = Some(upright.len() as i64);
+        state.blobs.put(storage_key, Bytes::from(upright), content_type).await?;
+    }
+
+    let mut tx = state.pool.begin().await?;
+    let attachment = sqlx::query_scalar!(
+        r#"
+        UPDATE attachments
+        SET processing = 'ready', orientation = 1, width = $2, height = $3, blurhash = $4,
+            size_bytes = COALESCE($5, size_bytes)
+        WHERE id = $1 AND deleted_at IS NULL
+        RETURNING id
+        "#,
+        id,
+        processed.width as i32,
+        processed.height as i32,
+        processed.blurhash,
+        size_bytes
+    )
+    .fetch_optional(&mut *tx)
+    .await?;
+
+    // Deleted while it was being processed: the cleanup job takes the row,
+    // but it can't know about thumbnails it never saw
+    if attachment.is_none() {
+        drop(tx);
+        for key in &thumbnail_keys {
+            let _ = state.blobs.delete(key).await;
+        }
+        return Ok(());
+    }
+
+    let sizes: Vec<i32> = processed.thumbnails.iter().map(|t| t.size as i32).collect();
+    let widths: Vec<i32> = processed.thumbnails.iter().map(|t| t.width as i32).collect();
+    let heights: Vec<i32> = processed.thumbnails.iter().map(|t| t.height as i32).collect();
+    let byte_sizes: Vec<i64> = processed.thumbnails.iter().map(|t| t.webp.len() as i64).collect();
+    sqlx::query!(
+        r#"
+        INSERT INTO attachment_thumbnails (attachment_id, size, width, height, storage_key, size_bytes)
+        SELECT $1, * FROM UNNEST($2::INT[], $3::INT[], $4::INT[], $5::TEXT[], $6::BIGINT[])
+        ON CONFLICT (attachment_id, size) DO UPDATE
+        SET width = EXCLUDED.width, height = EXCLUDED.height, size_bytes = EXCLUDED.size_bytes
+        "#,
+        id,
+        &sizes,
+        &widths,
+        &heights,
+        &thumbnail_keys,
+        &byte_sizes
+    )
+    .execute(&mut *tx)
+    .await?;
+
+    let attachment = sqlx::query_scalar!(
+        r#"SELECT attachment_json(a) AS "attachment!: sqlx::types::Json<Attachment>" FROM attachments a WHERE a.id = $1"#,
+        id
+    )
+    .fetch_one(&mut *tx)
+    .await?;
+    tx.commit().await?;
+
+    announce_attachment(state, attachment.0).await;
+    Ok(())
+}
+
+/// Pushes a processed attachment to whoever can see the message it is on.
+/// Still-pending uploads have nobody to tell yet; clients get the current
+/// state when the message is sent.
+async fn announce_attachment(state: &AppState, attachment: Attachment) {
+    let linked = sqlx::query!(
+        r#"
+        SELECT a.message_id, m.room_id AS "room_id?", m.created_at AS "sent_at?", a.direct_message_id,
+               dm.sender_id AS "sender_id?", dm.receiver_id AS "receiver_id?"
+        FROM attachments a
+        LEFT JOIN messages m ON m.id = a.message_id
+        LEFT JOIN direct_messages dm ON dm.id = a.direct_message_id
+        WHERE a.id = $1
+        "#,
+        attachment.id
+    )
+    .fetch_optional(&state.pool)
+    .await;
+
+    let linked = match linked {
+        Ok(Some(linked)) => linked,
+        Ok(None) => return,
+        Err(e) => {
+            eprintln!("❌ Failed to announce attachment {}: {e}", attachment.id);
+            return;
+        }
+    };
+
+    if let (Some(message_id), Some(room_id), Some(sent_at)) = (linked.message_id, linked.room_id, linked.sent_at) {
+        state.broadcast(room_id, &RoomEvent::AttachmentUpdated { message_id, attachment, sent_at }).await;
+    } else if let (Some(message_id), Some(sender_id), Some(receiver_id)) =
+        (linked.direct_message_id, linked.sender_id, linked.receiver_id)
+    {
+        let event = DmEvent::AttachmentUpdated { message_id, attachment };
+        state.broadcast_dm(sender_id, receiver_id, &event).await;
+    }
+}
+
+/// Drops cached link previews once they're too old to be reused. Messages
+/// keep their own copies.
+pub async fn purge_link_previews(state: Arc<AppState>) {
+    let mut interval = tokio::time::interval(Duration::from_secs(60 * 60));
+
+    loop {
+        interval.tick().await;
+
+        let result = sqlx::query!("DELETE FROM link_previews WHERE fetched_at < NOW() - INTERVAL '1 day'")
+            .execute(&state.pool)
+            .await;
+
+        match result {
+            Ok(r) if r.rows_affected() > 0 => {
+                println!("🧹 Removed {} cached link preview(s)", r.rows_affected())
+            }
+            Ok(_) => {}
+            Err(e) => eprintln!("❌ Link preview cleanup failed: {e}"),
+        }
+    }
+}
+
+/// Parses the markdown of messages and thread replies written before ASTs were
+/// stored, a batch at a time, then stops. New and edited ones are parsed when
+/// written.
+pub async fn parse_old_messages(state: Arc<AppState>) {
+    let mut parsed = 0;
+
+    loop {
+        let batch = sqlx::query!(
+            r#"
+            SELECT id, room_id, content FROM messages
+            WHERE content_ast IS NULL
+            LIMIT 200
+            "#
+        )
+        .fetch_all(&state.pool)
+        .await;
+
+        let batch = match batch {
+            Ok(batch) if !batch.is_empty() => batch,
+            Ok(_) => break,
+            Err(e) => {
+                eprintln!("❌ Markdown backfill failed: {e}");
+                return;
+            }
+        };
+
+        for message in batch {
+            let ast = match Refs::for_room(&state.pool, message.room_id, &message.content).await {
+                Ok(refs) => markdown::parse(&message.content, &refs),
+                Err(e) => {
+                    eprintln!("❌ Markdown backfill failed: {e}");
+                    return;
+                }
+            };
+            // Skipped if edited meanwhile; the edit stored its own AST
+            let result = sqlx::query!(
+                "UPDATE messages SET content_ast = $3 WHERE id = $1 AND content = $2 AND content_ast IS NULL",
+                message.id,
+                message.content,
+                sqlx::types::Json(&ast) as _
+            )
+            .execute(&state.pool)
+            .await;
+            if let Err(e) = result {
+                eprintln!("❌ Markdown backfill failed: {e}");
+                return;
+            }
+            parsed += 1;
+        }
+    }
+
+    loop {
+        let batch = sqlx::query!(
+            r#"
+            SELECT id, sender_id, receiver_id, content FROM direct_messages
+            WHERE content_ast IS NULL
+            LIMIT 200
+            "#
+        )
+        .fetch_all(&state.pool)
+        .await;
+
+        let batch = match batch {
+            Ok(batch) if !batch.is_empty() => batch,
+            Ok(_) => break,
+            Err(e) => {
+                eprintln!("❌ Markdown backfill failed: {e}");
+                return;
+            }
+        };
+
+        for message in batch {
+            let users = [message.sender_id, message.receiver_id];
+            let ast = match Refs::for_dm(&state.pool, users, &message.content).await {
+                Ok(refs) => markdown::parse(&message.content, &refs),
+                Err(e) => {
+                    eprintln!("❌ Markdown backfill failed: {e}");
+                    return;
+                }
+            };
+            let result = sqlx::query!(
+                "UPDATE direct_messages SET content_ast = $3 WHERE id = $1 AND content = $2 AND content_ast IS NULL",
+                message.id,
+                message.content,
+                sqlx::types::Json(&ast) as _
+            )
+            .execute(&state.pool)
+            .await;
+            if let Err(e) = result {
+                eprintln!("❌ Markdown backfill failed: {e}");
+                return;
+            }
+            parsed += 1;
+        }
+    }
+
+    loop {
+        let batch = sqlx::query!(
+            r#"
+            SELECT id, room_id, content FROM thread_replies
+            WHERE content_ast IS NULL
+            LIMIT 200
+            "#
+        )
+        .fetch_all(&state.pool)
+        .await;
+
+        let batch = match batch {
+            Ok(batch) if !batch.is_empty() => batch,
+            Ok(_) => break,
+            Err(e) => {
+                eprintln!("❌ Markdown backfill failed: {e}");
+                return;
+            }
+        };
+
+        for reply in batch {
+            let ast = match Refs::for_room(&state.pool, reply.room_id, &reply.content).await {
+                Ok(refs) => markdown::parse(&reply.content, &refs),
+                Err(e) => {
+                    eprintln!("❌ Markdown backfill failed: {e}");
+                    return;
+                }
+            };
+            let result = sqlx::query!(
+                "UPDATE thread_replies SET content_ast = $3 WHERE id = $1 AND content = $2 AND content_ast IS NULL",
+                reply.id,
+                reply.content,
+                sqlx::types::Json(&ast) as _
+            )
+            .execute(&state.pool)
+            .await;
+            if let Err(e) = result {
+                eprintln!("❌ Markdown backfill failed: {e}");
+                return;
+            }
+            parsed += 1;
+        }
+    }
+
+    if parsed > 0 {
+        println!("📝 Parsed markdown of {parsed} older message(s)");
+    }
+}
@@ -0,0 +1,543 @@
+//! Link previews: URLs in a message are fetched in the background, their
+//! OpenGraph / Twitter-card tags stored as embeds on the message, and the
+//! result pushed to the message's listeners.
+//!
+//! Fetches never reach private, loopback or link-local addresses: hostnames
+//! are checked after resolution (on every redirect hop, since the resolver
+//! is the client's own), literal IPs before the request, and connections go
+//! to the vetted address only.
+
+use futures_util::future::join_all;
+use reqwest::dns::{Addrs, Name, Resolve, Resolving};
+use reqwest::{header, redirect, Client, StatusCode, Url};
+use scraper::{Html, Selector};
+use std::collections::HashMap;
+use std::net::{IpAddr, Ipv4Addr, SocketAddr};
+use std::sync::Arc;
+use std::time::Duration;
+use sqlx::types::Json;
+use tokio::sync::Semaphore;
+use uuid::Uuid;
+use crate::models::embeds::Embed;
+use crate::models::events::{DmEvent, RoomEvent};
+use crate::state::AppState;
+
+/// Links previewed per message; later ones are ignored.
+pub const MAX_EMBEDS: usize = 3;
+/// Only the head of a page is needed, so the rest isn't downloaded.
+const MAX_BODY_BYTES: usize = 512 * 1024;
+const FETCH_TIMEOUT: Duration = Duration::from_secs(5);
+const MAX_REDIRECTS: usize = 3;
+/// Fetches in flight at once across the server.
+const MAX_CONCURRENT_FETCHES: usize = 8;
+
+#[derive(Debug, thiserror::Error)]
+pub enum FetchError {
+    #[error("only http(s) URLs on the default ports are fetched")]
+    Unsupported,
+    #[error("address is not public")]
+    Blocked,
+    #[error("too many redirects")]
+    TooManyRedirects,
+    #[error("status {0}")]
+    Status(StatusCode),
+    #[error("not an HTML page")]
+    NotHtml,
+    #[error(transparent)]
+    Http(#[from] reqwest::Error),
+}
+
+/// What a page says about itself. Empty when it has no usable tags.
+#[derive(Debug, Default, PartialEq)]
+pub struct PageMeta {
+    pub title: Option<String>,
+    pub description: Option<String>,
+    pub image_url: Option<String>,
+    pub site_name: Option<String>,
+}
+
+impl PageMeta {
+    fn is_empty(&self) -> bool {
+        self.title.is_none() && self.description.is_none() && self.image_url.is_none()
+    }
+}
+
+/// HTTP client for previews. `allow_private` lifts the address checks so it
+/// can be pointed at a local server in development and tests.
+pub struct LinkFetcher {
+    client: Client,
+    allow_private: bool,
+    permits: Semaphore,
+}
+
+impl LinkFetcher {
+    pub fn new(allow_private: bool) -> Self {
+        let client = Client::builder()
+            .user_agent(concat!("RustyLinkPreview/", env!("CARGO_PKG_VERSION")))
+            .redirect(redirect::Policy::none())
+            .no_proxy()
+            .dns_resolver(Arc::new(PublicResolver { allow_private }))
+            .connect_timeout(FETCH_TIMEOUT)
+            .timeout(FETCH_TIMEOUT)
+            .build()
+            .expect("Failed to build the link preview client");
+
+        Self { client, allow_private, permits: Semaphore::new(MAX_CONCURRENT_FETCHES) }
+    }
+
+    /// Reads `LINK_PREVIEW_ALLOW_PRIVATE`; off unless set to `true`.
+    pub fn from_env() -> Self {
+        let allow_private = std::env::var("LINK_PREVIEW_ALLOW_PRIVATE").is_ok_and(|v| v == "true");
+        Self::new(allow_private)
+    }
+
+    /// Fetches the page (following a few redirects, each checked again) and
+    /// parses its metadata.
+    pub async fn fetch(&self, url: &str) -> Result<PageMeta, FetchError> {
+        let _permit = self.permits.acquire().await.expect("semaphore is never closed");
+        let mut url = Url::parse(url).map_err(|_| FetchError::Unsupported)?;
+
+        for _ in 0..=MAX_REDIRECTS {
+            self.check_url(&url)?;
+
+            let mut response = self
+                .client
+                .get(url.clone())
+                .header(header::ACCEPT, "text/html,application/xhtml+xml")
+                .send()
+                .await?;
+
+            if response.status().is_redirection() {
+                let location = response
+                    .headers()
+                    .get(header::LOCATION)
+                    .and_then(|l| l.to_str().ok())
+                    .ok_or(FetchError::Status(response.status()))?;
+                url = url.join(location).map_err(|_| FetchError::Unsupported)?;
+                continue;
+            }
+            if !response.status().is_success() {
+                return Err(FetchError::Status(response.status()));
+            }
+
+            let is_html = response
+                .headers()
+                .get(header::CONTENT_TYPE)
+                .and_then(|t| t.to_str().ok())
+                .is_some_and(|t| t.starts_with("text/html") || t.starts_with("application/xhtml+xml"));
+            if !is_html {
+                return Err(FetchError::NotHtml);
+            }
+
+            let mut body = Vec::new();
+            while let Some(chunk) = response.chunk().await? {
+                let room = MAX_BODY_BYTES - body.len();
+                body.extend_from_slice(&chunk[..chunk.len().min(room)]);
+                if body.len() == MAX_BODY_BYTES {
+                    break;
+                }
+            }
+
+            return Ok(parse_meta(&String::from_utf8_lossy(&body), &url));
+        }
+
+        Err(FetchError::TooManyRedirects)
+    }
+
+    fn check_url(&self, url: &Url) -> Result<(), FetchError> {
+        if !matches!(url.scheme(), "http" | "https") || url.host_str().is_none() {
+            return Err(FetchError::Unsupported);
+        }
+        if self.allow_private {
+            return Ok(());
+        }
+        if url.port().is_some_and(|p| p != 80 && p != 443) {
+            return Err(FetchError::Unsupported);
+        }
+        // Hostnames are vetted by the resolver; literal addresses never reach it
+        let host = url.host_str().unwrap_or_default();
+        match host.trim_start_matches('[').trim_end_matches(']').parse::<IpAddr>() {
+            Ok(ip) if !is_public(ip) => Err(FetchError::Blocked),
+            _ => Ok(()),
+        }
+    }
+}
+
+/// DNS for the preview client: drops every non-public address, and fails the
+/// lookup when none is left.
+struct PublicResolver {
+    allow_private: bool,
+}
+
+impl Resolve for PublicResolver {
+    fn resolve(&self, name: Name) -> Resolving {
+        let allow_private = self.allow_private;
+        Box::pin(async move {
+            let addrs: Vec<SocketAddr> = tokio::net::lookup_host((name.as_str(), 0))
+                .await?
+                .filter(|addr| allow_private || is_public(addr.ip()))
+                .collect();
+            if addrs.is_empty() {
+                return Err(Box::new(FetchError::Blocked) as Box<dyn std::error::Error + Send + Sync>);
+            }
+            Ok(Box::new(addrs.into_iter()) as Addrs)
+        })
+    }
+}
+
+/// Whether an address is on the public internet, i.e. not loopback, private,
+/// link-local, shared (CGNAT), reserved, multicast or documentation space.
+pub fn is_public(ip: IpAddr) -> bool {
+    match ip {
+        IpAddr::V4(ip) => is_public_v4(ip),
+        IpAddr::V6(ip) => {
+            if let Some(v4) = ip.to_ipv4_mapped() {
+                return is_public_v4(v4);
+            }
+            let first = ip.segments()[0];
+            !(ip.is_loopback()
+                || ip.is_unspecified()
+                || ip.is_multicast()
+                || (first & 0xfe00) == 0xfc00 // unique local
+                || (first & 0xffc0) == 0xfe80 // link-local
+                || (first == 0x2001 && ip.segments()[1] == 0x0db8) // documentation
+                || (first == 0x0064 && ip.segments()[1] == 0xff9b) // NAT64
+                || first == 0x2002) // 6to4
+        }
+    }
+}
+
+fn is_public_v4(ip: Ipv4Addr) -> bool {
+    let [a, b, c, _] = ip.octets();
+    !(ip.is_private()
+        || ip.is_loopback()
+        || ip.is_link_local()
+        || ip.is_unspecified()
+        || ip.is_broadcast()
+        || ip.is_multicast()
+        || ip.is_documentation()
+        || a == 0
+        || (a == 100 && (64..128).contains(&b)) // shared address space
+        || (a == 192 && b == 0 && c == 0)
+        || (a == 198 && (b == 18 || b == 19)) // benchmarking
+        || a >= 240)
+}
+
+/// Collapses whitespace and caps the length in characters.
+fn clean_text(text: &str, max_chars: usize) -> Option<String> {
+    let text = text.split_whitespace().collect::<Vec<_>>().join(" ");
+    let text: String = text.chars().take(max_chars).collect();
+    (!text.is_empty()).then_some(text)
+}
+
+/// Reads OpenGraph tags, falling back to Twitter cards and then the plain
+/// `<title>` / description. The image URL is made absolute against the page.
+pub fn parse_meta(html: &str, page: &Url) -> PageMeta {
+    let document = Html::parse_document(html);
+    let meta_selector = Selector::parse("meta").expect("valid selector");
+    let title_selector = Selector::parse("title").expect("valid selector");
+
+    // First occurrence of each tag wins
+    let mut tags: HashMap<String, &str> = HashMap::new();
+    for meta in document.select(&meta_selector) {
+        let element = meta.value();
+        let (Some(key), Some(content)) = (element.attr("property").or(element.attr("name")), element.attr("content"))
+        else {
+            continue;
+        };
+        tags.entry(key.to_ascii_lowercase()).or_insert(content);
+    }
+    let tag = |keys: &[&str]| keys.iter().find_map(|k| tags.get(*k).copied());
+
+    let title = tag(&["og:title", "twitter:title"])
+        .map(str::to_string)
+        .or_else(|| document.select(&title_selector).next().map(|t| t.text().collect()));
+
+    let image_url = tag(&["og:image", "og:image:url", "og:image:secure_url", "twitter:image", "twitter:image:src"])
+        .and_then(|src| page.join(src.trim()).ok())
+        .filter(|url| matches!(url.scheme(), "http" | "https"))
+        .map(String::from);
+
+    PageMeta {
+        title: title.and_then(|t| clean_text(&t, 256)),
+        description: tag(&["og:description", "twitter:description", "description"]).and_then(|d| clean_text(d, 512)),
+        image_url: image_url.filter(|u| u.len() <= 2048),
+        site_name: tag(&["og:site_name"]).and_then(|s| clean_text(s, 128)),
+    }
+}
+
+/// Drops trailing punctuation (and an unbalanced closing parenthesis) from
+/// a link found in running text.
+pub fn trim_url(mut candidate: &str) -> &str {
+    loop {
+        let trimmed = candidate.trim_end_matches(['.', ',', '!', '?', ';', ':', '\'', '"', ']', '>', '*', '_', '~', '|']);
+        let trimmed = match trimmed.strip_suffix(')') {
+            Some(inner) if trimmed.matches('(').count() < trimmed.matches(')').count() => inner,
+            _ => trimmed,
+        };
+        if trimmed == candidate {
+            return candidate;
+        }
+        candidate = trimmed;
+    }
+}
+
+/// The `http(s)` links in a message, in order and without duplicates, up to
+/// `MAX_EMBEDS`. A link wrapped in `<…>` is left without a preview.
+pub fn extract_urls(content: &str) -> Vec<String> {
+    let mut urls: Vec<String> = Vec::new();
+
+    for word in content.split_whitespace() {
+        let Some(start) = word.find("https://").or_else(|| word.find("http://")) else {
+            continue;
+        };
+        if word[..start].ends_with('<') && word.ends_with('>') {
+            continue;
+        }
+
+        let Ok(url) = Url::parse(trim_url(&word[start..])) else {
+            continue;
+        };
+        if url.host_str().is_none() {
+            continue;
+        }
+        let url = String::from(url);
+        if !urls.contains(&url) {
+            urls.push(url);
+        }
+        if urls.len() == MAX_EMBEDS {
+            break;
+        }
+    }
+    urls
+}
+
+/// Which message the previews belong to, and so who hears about them.
+#[derive(Clone, Copy)]
+pub enum Target {
+    Room { room_id: Uuid, message_id: Uuid },
+    Dm { sender_id: Uuid, receiver_id: Uuid, message_id: Uuid },
+}
+
+/// Previews the links of a newly sent message in the background.
+pub fn unfurl(state: &AppState, target: Target, content: &str) {
+    let urls = extract_urls(content);
+    if !urls.is_empty() {
+        spawn(state, target, content, urls);
+    }
+}
+
+/// Same after an edit, which can also drop links that had previews.
+pub fn refresh(state: &AppState, target: Target, content: &str) {
+    spawn(state, target, content, extract_urls(content));
+}
+
+fn spawn(state: &AppState, target: Target, content: &str, urls: Vec<String>) {
+    let state = state.clone();
+    let content = content.to_string();
+
+    tokio::spawn(async move {
+        let previews = join_all(urls.iter().map(|url| preview(&state, url))).await;
+        let embeds: Vec<Embed> = previews.into_iter().flatten().collect();
+
+        if let Err(e) = store_embeds(&state, target, &content, embeds).await {
+            eprintln!("❌ Failed to store link previews: {e}");
+        }
+    });
+}
+
+/// A cached preview when there is a fresh one, otherwise a new fetch. Failed
+/// fetches are cached for a shorter time than successful ones.
+async fn preview(state: &AppState, url: &str) -> Option<Embed> {
+    let cached = sqlx::query!(
+        r#"
+        SELECT ok, title, description, image_url, site_name FROM link_previews
+        WHERE url = $1
+          AND fetched_at > NOW() - CASE WHEN ok THEN INTERVAL '1 day' ELSE INTERVAL '1 hour' END
+        "#,
+        url
+    )
+    .fetch_optional(&state.pool)
+    .await;
+
+    let meta = match cached {
+        Ok(Some(row)) if !row.ok => return None,
+        Ok(Some(row)) => PageMeta {
+            title: row.title,
+            description: row.description,
+            image_url: row.image_url,
+            site_name: row.site_name,
+        },
+        Ok(None) | Err(_) => {
+            let meta = state.link_fetcher.fetch(url).await.unwrap_or_default();
+            let stored = sqlx::query!(
+                r#"
+                INSERT INTO link_previews (url, ok, title, description, image_url, site_name)
+                VALUES ($1, $2, $3, $4, $5, $6)
+                ON CONFLICT (url) DO UPDATE
+                SET ok = EXCLUDED.ok, title = EXCLUDED.title, description = EXCLUDED.description,
+                    image_url = EXCLUDED.image_url, site_name = EXCLUDED.site_name, fetched_at = NOW()
+                "#,
+                url,
+                !meta.is_empty(),
+                meta.title,
+                meta.description,
+                meta.image_url,
+                meta.site_name
+            )
+            .execute(&state.pool)
+            .await;
+            if let Err(e) = stored {
+                eprintln!("❌ Failed to cache link preview for {url}: {e}");
+            }
+            meta
+        }
+    };
+
+    (!meta.is_empty()).then(|| Embed {
+        url: url.to_string(),
+        title: meta.title,
+        description: meta.description,
+        image_url: meta.image_url,
+        site_name: meta.site_name,
+    })
+}
+
+/// Saves the embeds unless the message was edited or deleted meanwhile (the
+/// newer content gets its own run), and pushes them if they changed.
+async fn store_embeds(state: &AppState, target: Target, content: &str, embeds: Vec<Embed>) -> Result<(), sqlx::Error> {
+    match target {
+        Target::Room { room_id, message_id } => {
+            let updated = sqlx::query_scalar!(
+                r#"
+                UPDATE messages SET embeds = $3
+                WHERE id = $1 AND content = $2 AND deleted_at IS NULL AND embeds IS DISTINCT FROM $3
+                RETURNING created_at
+                "#,
+                message_id,
+                content,
+                Json(&embeds) as _
+            )
+            .fetch_optional(&state.pool)
+            .await?;
+
+            if let Some(sent_at) = updated {
+                state.broadcast(room_id, &RoomEvent::EmbedsUpdated { message_id, embeds, sent_at }).await;
+            }
+        }
+        Target::Dm { sender_id, receiver_id, message_id } => {
+            let updated = sqlx::query!(
+                r#"
+                UPDATE direct_messages SET embeds = $3
+                WHERE id = $1 AND content = $2 AND deleted_at IS NULL AND embeds IS DISTINCT FROM $3
+                "#,
+                message_id,
+                content,
+                Json(&embeds) as _
+            )
+            .execute(&state.pool)
+            .await?;
+
+            if updated.rows_affected() > 0 {
+                let event = DmEvent::EmbedsUpdated { message_id, embeds };
+                state.broadcast_dm(sender_id, receiver_id, &event).await;
+            }
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tokio::io::{AsyncReadExt, AsyncWriteExt};
+    use tokio::net::TcpListener;
+
+    /// Serves `response` verbatim to every connection on a loopback port.
+    async fn serve(response: &'static str) -> SocketAddr {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        tokio::spawn(async move {
+            while let Ok((mut socket, _)) = listener.accept().await {
+                let mut request = [0u8; 4096];
+                let _ = socket.read(&mut request).await;
+                let _ = socket.write_all(response.as_bytes()).await;
+            }
+        });
+        addr
+    }
+
+    const PAGE: &str = "HTTP/1.1 200 OK\r\nContent-Type: text/html; charset=utf-8\r\nConnection: close\r\n\r\n\
+        <html><head><title>Fallback</title>\
+        <meta property=\"og:title\" content=\"  Hello\n  world \">\
+        <meta name=\"description\" content=\"A page\">\
+        <meta property=\"og:image\" content=\"/cover.png\">\
+        <meta property=\"og:site_name\" content=\"Example\">\
+        </head><body></body></html>";
+
+    #[test]
+    fn private_addresses_are_not_public() {
+        for ip in ["127.0.0.1", "10.1.2.3", "172.16.0.1", "192.168.1.1", "169.254.169.254", "100.64.0.1", "0.0.0.0", "::1", "fd00::1", "fe80::1", "::ffff:127.0.0.1"] {
+            assert!(!is_public(ip.parse().unwrap()), "{ip}");
+        }
+        for ip in ["93.184.216.34", "2606:2800:220:1:248:1893:25c8:1946"] {
+            assert!(is_public(ip.parse().unwrap()), "{ip}");
+        }
+    }
+
+    #[test]
+    fn urls_are_extracted_without_punctuation() {
+        let urls = extract_urls(
+            "see https://example.com/a, (https://en.wikipedia.org/wiki/Rust_(language)) <https://quiet.example> https://example.com/a",
+        );
+        assert_eq!(urls, ["https://example.com/a", "https://en.wikipedia.org/wiki/Rust_(language)"]);
+    }
+
+    #[tokio::test]
+    async fn loopback_is_refused() {
+        let addr = serve(PAGE).await;
+        let fetcher = LinkFetcher::new(false);
+
+        let err = fetcher.fetch(&format!("http://{addr}/")).await.unwrap_err();
+        assert!(matches!(err, FetchError::Unsupported | FetchError::Blocked), "{err}");
+        let err = fetcher.fetch("http://127.0.0.1/").await.unwrap_err();
+        assert!(matches!(err, FetchError::Blocked), "{err}");
+        let err = fetcher.fetch("http://localhost/").await.unwrap_err();
+        assert!(matches!(err, FetchError::Http(_)), "{err}");
+    }
+
+    #[tokio::test]
+    async fn fetches_and_parses_open_graph() {
+        let addr = serve(PAGE).await;
+        let fetcher = LinkFetcher::new(true);
+
+        let meta = fetcher.fetch(&format!("http://{addr}/post")).await.unwrap();
+        assert_eq!(
+            meta,
+            PageMeta {
+                title: Some("Hello world".into()),
+                description: Some("A page".into()),
+                image_url: Some(format!("http://{addr}/cover.png")),
+                site_name: Some("Example".into()),
+            }
+        );
+    }
+
+    #[tokio::test]
+    async fn redirects_are_capped() {
+        let addr = serve("HTTP/1.1 302 Found\r\nLocation: /again\r\nContent-Length: 0\r\n\r\n").await;
+        let fetcher = LinkFetcher::new(true);
+
+        let err = fetcher.fetch(&format!("http://{addr}/")).await.unwrap_err();
+        assert!(matches!(err, FetchError::TooManyRedirects), "{err}");
+    }
+
+    #[tokio::test]
+    async fn non_html_is_skipped() {
+        let addr = serve("HTTP/1.1 200 OK\r\nContent-Type: image/png\r\nContent-Length: 0\r\n\r\n").await;
+        let fetcher = LinkFetcher::new(true);
+
+        let err = fetcher.fetch(&format!("http://{addr}/")).await.unwrap_err();
+        assert!(matches!(err, FetchError::NotHtml), "{err}");
+    }
+}
@@ -15,6 +15,16 @@ mod db;
 mod route_handlers;
 mod models;
 mod state;
+mod jobs;
+mod audit;
+mod messaging;
+mod ical;
+mod mentions;
+mod storage;
+mod images;
+mod link_previews;
+mod markdown;
+mod history;
 
 use crate::state::AppState;
 use crate::routes::{create_routes,ws_routes};
@@ -29,14 +39,46 @@ async fn main() {
         .await
         .expect("Failed to connect to the database");
 
+    let room_restore_window = std::env::var("ROOM_RESTORE_WINDOW_HOURS")
+        .ok()
+        .and_then(|h| h.parse::<i64>().ok())
+        .map(time::Duration::hours)
+        .unwrap_or(time::Duration::days(7));
+
+    let event_reminder_lead = std::env::var("EVENT_REMINDER_MINUTES")
+        .ok()
+        .and_then(|m| m.parse::<i64>().ok())
+        .map(time::Duration::minutes)
+        .unwrap_or(time::Duration::minutes(15));
+
+    let attachment_max_bytes = std::env::var("ATTACHMENT_MAX_MB")
+        .ok()
+        .and_then(|m| m.parse::<usize>().ok())
+        .unwrap_or(10)
+        * 1024
+        * 1024;
+
     let app_state = Arc::new(AppState {
         pool: db_pool.clone(),
             rooms: Arc::new(RwLock::new(HashMap::new())),
+        room_restore_window,
+        event_reminder_lead,
+        blobs: storage::from_env(),
+        attachment_max_bytes,
+        image_jobs: Arc::new(tokio::sync::Notify::new()),
+        link_fetcher: Arc::new(link_previews::LinkFetcher::from_env()),
     });
 
+    tokio::spawn(jobs::purge_deleted_rooms(app_state.clone()));
+    tokio::spawn(jobs::send_event_reminders(app_state.clone()));
+    tokio::spawn(jobs::purge_attachments(app_state.clone()));
+    tokio::spawn(jobs::process_images(app_state.clone()));
+    tokio::spawn(jobs::purge_link_previews(app_state.clone()));
+    tokio::spawn(jobs::parse_old_messages(app_state.clone()));
+
     let cors = CorsLayer::new()
         .allow_origin("http://localhost:3000".parse::<axum::http::HeaderValue>().unwrap())
-        .allow_methods([Method::GET, Method::POST, Method::DELETE, Method::PUT])
+        .allow_methods([Method::GET, Method::POST, Method::DELETE, Method::PUT, Method::PATCH])
         .allow_headers(Any);
     
 
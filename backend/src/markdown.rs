@@ -0,0 +1,564 @@
+//! The message markdown dialect, parsed into the AST in `models::markdown`.
+//!
+//! Blocks are line based: a line starting with ```` ``` ```` (optionally
+//! followed by a language) opens a code block that runs to a closing fence or
+//! the end of the message, consecutive lines starting with `>` form a quote,
+//! and blank lines separate paragraphs. Inside paragraphs `**bold**`,
+//! `*italic*` / `_italic_`, `~~strikethrough~~`, `||spoiler||` and `` `code` ``
+//! nest up to `MAX_DEPTH` levels; `\` escapes punctuation, `http(s)` URLs
+//! (also as `<url>`) become links, and `@name` / `#channel` become mentions
+//! and channel links when they match something. Anything that doesn't parse
+//! is kept as text, so every message has an AST.
+
+use std::collections::HashMap;
+use reqwest::Url;
+use sqlx::PgPool;
+use uuid::Uuid;
+use crate::link_previews::trim_url;
+use crate::mentions::mention_names;
+use crate::models::markdown::{Block, Inline};
+
+/// Deeper emphasis markers are left as text.
+pub const MAX_DEPTH: usize = 5;
+const MAX_LANGUAGE_LEN: usize = 32;
+
+/// What `@name` and `#name` can point at, keyed by lowercased name.
+#[derive(Default)]
+pub struct Refs {
+    users: HashMap<String, Uuid>,
+    roles: HashMap<String, Uuid>,
+    channels: HashMap<String, Uuid>,
+    everyone: bool,
+}
+
+impl Refs {
+    /// Members, roles and channels of the room named in the content.
+    pub async fn for_room(pool: &PgPool, room_id: Uuid, content: &str) -> Result<Self, sqlx::Error> {
+        let names = mention_names(content);
+        let channel_names = channel_names(content);
+        let mut refs = Refs { everyone: true, ..Refs::default() };
+
+        if !names.is_empty() {
+            let users = sqlx::query!(
+                r#"
+                SELECT u.id, lower(u.username) AS "name!" FROM room_members rm
+                JOIN users u ON u.id = rm.user_id
+                WHERE rm.room_id = $1 AND lower(u.username) = ANY($2)
+                "#,
+                room_id,
+                &names
+            )
+            .fetch_all(pool)
+            .await?;
+            refs.users = users.into_iter().map(|u| (u.name, u.id)).collect();
+
+            let roles = sqlx::query!(
+                r#"SELECT id, lower(name) AS "name!" FROM room_roles WHERE room_id = $1 AND lower(name) = ANY($2)"#,
+                room_id,
+                &names
+            )
+            .fetch_all(pool)
+            .await?;
+            refs.roles = roles.into_iter().map(|r| (r.name, r.id)).collect();
+        }
+
+        if !channel_names.is_empty() {
+            let channels = sqlx::query!(
+                r#"
+                SELECT DISTINCT ON (lower(name)) id, lower(name) AS "name!" FROM room_channels
+                WHERE room_id = $1 AND lower(name) = ANY($2)
+                ORDER BY lower(name), position
+                "#,
+                room_id,
+                &channel_names
+            )
+            .fetch_all(pool)
+            .await?;
+            refs.channels = channels.into_iter().map(|c| (c.name, c.id)).collect();
+        }
+
+        Ok(refs)
+    }
+
+    /// Only the two participants can be mentioned in a DM.
+    pub async fn for_dm(pool: &PgPool, user_ids: [Uuid; 2], content: &str) -> Result<Self, sqlx::Error> {
+        let names = mention_names(content);
+        let mut refs = Refs::default();
+
+        if !names.is_empty() {
+            let users = sqlx::query!(
+                r#"SELECT id, lower(username) AS "name!" FROM users WHERE id = ANY($1) AND lower(username) = ANY($2)"#,
+                &user_ids,
+                &names
+            )
+            .fetch_all(pool)
+            .await?;
+            refs.users = users.into_iter().map(|u| (u.name, u.id)).collect();
+        }
+
+        Ok(refs)
+    }
+
+    fn mention(&self, name: &str) -> Option<Inline> {
+        let key = name.to_lowercase();
+        if let Some(&user_id) = self.users.get(&key) {
+            Some(Inline::UserMention { user_id, name: name.to_string() })
+        } else if let Some(&role_id) = self.roles.get(&key) {
+            Some(Inline::RoleMention { role_id, name: name.to_string() })
+        } else if self.everyone && key == "everyone" {
+            Some(Inline::Everyone)
+        } else {
+            None
+        }
+    }
+}
+
+fn is_word_char(c: char) -> bool {
+    c.is_alphanumeric() || c == '_'
+}
+
+/// The lowercased names after each `#` that starts a word.
+fn channel_names(content: &str) -> Vec<String> {
+    let mut names: Vec<String> = Vec::new();
+    for (i, _) in content.match_indices('#') {
+        if content[..i].chars().next_back().is_some_and(|p| is_word_char(p) || p == '#' || p == '&') {
+            continue;
+        }
+        let name = channel_name(&content[i + 1..]).to_lowercase();
+        if !name.is_empty() && !names.contains(&name) {
+            names.push(name);
+        }
+    }
+    names
+}
+
+fn channel_name(text: &str) -> &str {
+    let end = text.find(|c: char| !(is_word_char(c) || c == '-')).unwrap_or(text.len());
+    text[..end].trim_end_matches('-')
+}
+
+/// Same rules as `mentions::mention_names`.
+fn mention_name(text: &str) -> &str {
+    let end = text.find(|c: char| !(is_word_char(c) || c == '.' || c == '-')).unwrap_or(text.len());
+    text[..end].trim_end_matches(['.', '-'])
+}
+
+/// Parses a message. Never fails: malformed markup is kept as text.
+pub fn parse(content: &str, refs: &Refs) -> Vec<Block> {
+    parse_blocks(content, refs, true)
+}
+
+fn parse_blocks(text: &str, refs: &Refs, allow_quotes: bool) -> Vec<Block> {
+    let mut blocks = Vec::new();
+    let mut paragraph: Vec<&str> = Vec::new();
+    let mut quote: Vec<&str> = Vec::new();
+    let mut lines = text.lines().peekable();
+
+    while let Some(line) = lines.next() {
+        let is_quote = allow_quotes && line.starts_with('>');
+        if !is_quote && !quote.is_empty() {
+            blocks.push(Block::Quote { children: parse_blocks(&quote.join("\n"), refs, false) });
+            quote.clear();
+        }
+
+        if let Some(info) = line.strip_prefix("```").filter(|info| !info.contains("```")) {
+            flush_paragraph(&mut paragraph, &mut blocks, refs);
+            let info = info.trim();
+            let is_language = !info.is_empty()
+                && info.len() <= MAX_LANGUAGE_LEN
+                && info.chars().all(|c| c.is_ascii_alphanumeric() || "_+#.-".contains(c));
+
+            let mut code: Vec<&str> = Vec::new();
+            if !is_language && !info.is_empty() {
+                code.push(info);
+            }
+            for line in lines.by_ref() {
+                if let Some(last) = line.trim_end().strip_suffix("```") {
+                    if !last.is_empty() {
+                        code.push(last);
+                    }
+                    break;
+                }
+                code.push(line);
+            }
+
+            blocks.push(Block::Code {
+                language: is_language.then(|| info.to_ascii_lowercase()),
+                code: code.join("\n"),
+            });
+        } else if is_quote {
+            flush_paragraph(&mut paragraph, &mut blocks, refs);
+            let inner = &line[1..];
+            quote.push(inner.strip_prefix(' ').unwrap_or(inner));
+        } else if line.trim().is_empty() {
+            flush_paragraph(&mut paragraph, &mut blocks, refs);
+        } else {
+            paragraph.push(line);
+        }
+    }
+
+    flush_paragraph(&mut paragraph, &mut blocks, refs);
+    if !quote.is_empty() {
+        blocks.push(Block::Quote { children: parse_blocks(&quote.join("\n"), refs, false) });
+    }
+    blocks
+}
+
+fn flush_paragraph(lines: &mut Vec<&str>, blocks: &mut Vec<Block>, refs: &Refs) {
+    if !lines.is_empty() {
+        blocks.push(Block::Paragraph { children: parse_inlines(&lines.join("\n"), refs, 0) });
+        lines.clear();
+    }
+}
+
+/// Emphasis markers, longest first so `**` isn't read as two `*`.
+const EMPHASIS: [&str; 5] = ["**", "||", "~~", "*", "_"];
+
+fn emphasis(marker: &str, children: Vec<Inline>) -> Inline {
+    match marker {
+        "**" => Inline::Bold { children },
+        "||" => Inline::Spoiler { children },
+        "~~" => Inline::Strikethrough { children },
+        _ => Inline::Italic { children },
+    }
+}
+
+/// Where the closing `marker` is in `text`, skipping escapes and, for a
+/// single-character marker, doubled ones (`**` doesn't close `*`).
+fn find_closer(text: &str, marker: &str) -> Option<usize> {
+    let mut chars = text.char_indices().peekable();
+    while let Some((i, c)) = chars.next() {
+        if c == '\\' {
+            chars.next();
+            continue;
+        }
+        if !text[i..].starts_with(marker) {
+            continue;
+        }
+        if marker.len() == 1 && text[i + 1..].starts_with(marker) {
+            chars.next();
+            continue;
+        }
+        if i > 0 {
+            // In `***` closing `**`, the inner `*` belongs to the content
+            let run = text[i..].len() - text[i..].trim_start_matches(c).len();
+            return Some(i + run.saturating_sub(marker.len()));
+        }
+    }
+    None
+}
+
+fn parse_inlines(text: &str, refs: &Refs, depth: usize) -> Vec<Inline> {
+    let mut out = Vec::new();
+    let mut buf = String::new();
+    let mut i = 0;
+
+    let flush = |buf: &mut String, out: &mut Vec<Inline>| {
+        if !buf.is_empty() {
+            out.push(Inline::Text { text: std::mem::take(buf) });
+        }
+    };
+
+    'scan: while let Some(c) = text[i..].chars().next() {
+        let rest = &text[i..];
+        let previous = text[..i].chars().next_back();
+
+        match c {
+            '\\' => {
+                if let Some(next) = rest[1..].chars().next().filter(|n| n.is_ascii_punctuation()) {
+                    buf.push(next);
+                    i += 1 + next.len_utf8();
+                    continue;
+                }
+            }
+            '\n' => {
+                flush(&mut buf, &mut out);
+                out.push(Inline::LineBreak);
+                i += 1;
+                continue;
+            }
+            '`' => {
+                // Closed by a run of exactly as many backticks
+                let run = rest.len() - rest.trim_start_matches('`').len();
+                let fence = &rest[..run];
+                let mut from = run;
+                while let Some(found) = rest[from..].find(fence) {
+                    let end = from + found;
+                    let closing = rest[end..].len() - rest[end..].trim_start_matches('`').len();
+                    if closing != run {
+                        from = end + closing;
+                        continue;
+                    }
+                    let code = rest[run..end].trim_matches('\n');
+                    if !code.is_empty() {
+                        flush(&mut buf, &mut out);
+                        out.push(Inline::Code { code: code.to_string() });
+                        i += end + run;
+                        continue 'scan;
+                    }
+                    break;
+                }
+                buf.push_str(fence);
+                i += run;
+                continue;
+            }
+            '@' if !previous.is_some_and(is_word_char) => {
+                let name = mention_name(&rest[1..]);
+                if let Some(mention) = refs.mention(name) {
+                    flush(&mut buf, &mut out);
+                    out.push(mention);
+                    i += 1 + name.len();
+                    continue;
+                }
+            }
+            '#' if !previous.is_some_and(|p| is_word_char(p) || p == '#' || p == '&') => {
+                let name = channel_name(&rest[1..]);
+                if let Some(&channel_id) = refs.channels.get(&name.to_lowercase()) {
+                    flush(&mut buf, &mut out);
+                    out.push(Inline::ChannelLink { channel_id, name: name.to_string() });
+                    i += 1 + name.len();
+                    continue;
+                }
+            }
+            '<' => {
+                if let Some(end) = rest.find('>') {
+                    let inner = &rest[1..end];
+                    if !inner.contains(char::is_whitespace) && is_web_url(inner) {
+                        flush(&mut buf, &mut out);
+                        out.push(Inline::Link { url: inner.to_string() });
+                        i += end + 1;
+                        continue;
+                    }
+                }
+            }
+            'h' if !previous.is_some_and(char::is_alphanumeric)
+                && (rest.starts_with("https://") || rest.starts_with("http://")) =>
+            {
+                let end = rest.find(|c: char| c.is_whitespace() || c == '<').unwrap_or(rest.len());
+                let url = trim_url(&rest[..end]);
+                if is_web_url(url) {
+                    flush(&mut buf, &mut out);
+                    out.push(Inline::Link { url: url.to_string() });
+                    i += url.len();
+                    continue;
+                }
+            }
+            _ => {}
+        }
+
+        if depth < MAX_DEPTH {
+            for marker in EMPHASIS {
+                if !rest.starts_with(marker) {
+                    continue;
+                }
+                // `snake_case` words aren't italic
+                if marker == "_" && previous.is_some_and(char::is_alphanumeric) {
+                    break;
+                }
+                let Some(len) = find_closer(&rest[marker.len()..], marker) else {
+                    continue;
+                };
+                let inner = &rest[marker.len()..marker.len() + len];
+                let after = rest[2 * marker.len() + len..].chars().next();
+                let flanked = inner.starts_with(char::is_whitespace) || inner.ends_with(char::is_whitespace);
+                if (marker.len() == 1 && flanked) || (marker == "_" && after.is_some_and(char::is_alphanumeric)) {
+                    continue;
+                }
+
+                flush(&mut buf, &mut out);
+                out.push(emphasis(marker, parse_inlines(inner, refs, depth + 1)));
+                i += 2 * marker.len() + len;
+                continue 'scan;
+            }
+        }
+
+        buf.push(c);
+        i += c.len_utf8();
+    }
+
+    flush(&mut buf, &mut out);
+    out
+}
+
+fn is_web_url(url: &str) -> bool {
+    Url::parse(url).is_ok_and(|u| matches!(u.scheme(), "http" | "https") && u.host_str().is_some())
+}
+
+/// Renders the AST as an HTML fragment. Only the fixed set of tags below is
+/// produced, every piece of text and attribute is escaped, and links are
+/// checked again, so stored ASTs can't inject markup either.
+pub fn to_html(blocks: &[Block]) -> String {
+    let mut html = String::new();
+    for block in blocks {
+        render_block(block, &mut html);
+    }
+    html
+}
+
+fn render_block(block: &Block, html: &mut String) {
+    match block {
+        Block::Paragraph { children } => {
+            html.push_str("<p>");
+            render_inlines(children, html);
+            html.push_str("</p>");
+        }
+        Block::Code { language, code } => {
+            let language = language.as_deref().filter(|l| {
+                l.len() <= MAX_LANGUAGE_LEN && l.chars().all(|c| c.is_ascii_alphanumeric() || "_+#.-".contains(c))
+            });
+            match language {
+                Some(language) => html.push_str(&format!("<pre><code class=\"language-{}\">", escape(language))),
+                None => html.push_str("<pre><code>"),
+            }
+            html.push_str(&escape(code));
+            html.push_str("</code></pre>");
+        }
+        Block::Quote { children } => {
+            html.push_str("<blockquote>");
+            for child in children {
+                // Quotes don't nest
+                if !matches!(child, Block::Quote { .. }) {
+                    render_block(child, html);
+                }
+            }
+            html.push_str("</blockquote>");
+        }
+    }
+}
+
+fn render_inlines(inlines: &[Inline], html: &mut String) {
+    for inline in inlines {
+        match inline {
+            Inline::Text { text } => html.push_str(&escape(text)),
+            Inline::Bold { children } => wrap("<strong>", children, "</strong>", html),
+            Inline::Italic { children } => wrap("<em>", children, "</em>", html),
+            Inline::Strikethrough { children } => wrap("<del>", children, "</del>", html),
+            Inline::Spoiler { children } => wrap("<span class=\"spoiler\">", children, "</span>", html),
+            Inline::Code { code } => html.push_str(&format!("<code>{}</code>", escape(code))),
+            Inline::LineBreak => html.push_str("<br>"),
+            Inline::Link { url } if is_web_url(url) => html.push_str(&format!(
+                "<a href=\"{0}\" rel=\"nofollow noopener noreferrer ugc\" target=\"_blank\">{0}</a>",
+                escape(url)
+            )),
+            Inline::Link { url } => html.push_str(&escape(url)),
+            Inline::UserMention { user_id, name } => html.push_str(&format!(
+                "<span class=\"mention\" data-user-id=\"{user_id}\">@{}</span>",
+                escape(name)
+            )),
+            Inline::RoleMention { role_id, name } => html.push_str(&format!(
+                "<span class=\"mention\" data-role-id=\"{role_id}\">@{}</span>",
+                escape(name)
+            )),
+            Inline::Everyone => html.push_str("<span class=\"mention\">@everyone</span>"),
+            Inline::ChannelLink { channel_id, name } => html.push_str(&format!(
+                "<span class=\"channel\" data-channel-id=\"{channel_id}\">#{}</span>",
+                escape(name)
+            )),
+        }
+    }
+}
+
+fn wrap(open: &str, children: &[Inline], close: &str, html: &mut String) {
+    html.push_str(open);
+    render_inlines(children, html);
+    html.push_str(close);
+}
+
+fn escape(text: &str) -> String {
+    let mut out = String::with_capacity(text.len());
+    for c in text.chars() {
+        match c {
+            '&' => out.push_str("&amp;"),
+            '<' => out.push_str("&lt;"),
+            '>' => out.push_str("&gt;"),
+            '"' => out.push_str("&quot;"),
+            '\'' => out.push_str("&#39;"),
+            _ => out.push(c),
+        }
+    }
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn text(text: &str) -> Inline {
+        Inline::Text { text: text.into() }
+    }
+
+    #[test]
+    fn parses_nested_emphasis_and_code() {
+        let blocks = parse("**bold *and italic*** `a *b*` ||secret|| snake_case_name", &Refs::default());
+        assert_eq!(
+            blocks,
+            [Block::Paragraph {
+                children: vec![
+                    Inline::Bold { children: vec![text("bold "), Inline::Italic { children: vec![text("and italic")] }] },
+                    text(" "),
+                    Inline::Code { code: "a *b*".into() },
+                    text(" "),
+                    Inline::Spoiler { children: vec![text("secret")] },
+                    text(" snake_case_name"),
+                ],
+            }]
+        );
+    }
+
+    #[test]
+    fn parses_blocks() {
+        let blocks = parse("> quoted\n> > not nested\n```Rust\nfn main() {}\n```\nafter", &Refs::default());
+        assert_eq!(
+            blocks,
+            [
+                Block::Quote {
+                    children: vec![Block::Paragraph {
+                        children: vec![text("quoted"), Inline::LineBreak, text("> not nested")],
+                    }],
+                },
+                Block::Code { language: Some("rust".into()), code: "fn main() {}".into() },
+                Block::Paragraph { children: vec![text("after")] },
+            ]
+        );
+    }
+
+    #[test]
+    fn resolves_only_known_mentions_and_channels() {
+        let alice = Uuid::new_v4();
+        let general = Uuid::new_v4();
+        let refs = Refs {
+            users: HashMap::from([("alice".into(), alice)]),
+            channels: HashMap::from([("general".into(), general)]),
+            everyone: true,
+            ..Refs::default()
+        };
+        let blocks = parse("@Alice @bob #general, @everyone a@alice.com", &refs);
+        assert_eq!(
+            blocks,
+            [Block::Paragraph {
+                children: vec![
+                    Inline::UserMention { user_id: alice, name: "Alice".into() },
+                    text(" @bob "),
+                    Inline::ChannelLink { channel_id: general, name: "general".into() },
+                    text(", "),
+                    Inline::Everyone,
+                    text(" a@alice.com"),
+                ],
+            }]
+        );
+    }
+
+    #[test]
+    fn html_is_escaped() {
+        let blocks = parse(
+            "<script>alert(1)</script> **<b>** <javascript:alert(1)> (https://example.com/?q=\"x\"&y=1)\n```\"><img>\n```",
+            &Refs::default(),
+        );
+        assert_eq!(
+            to_html(&blocks),
+            "<p>&lt;script&gt;alert(1)&lt;/script&gt; <strong>&lt;b&gt;</strong> &lt;javascript:alert(1)&gt; \
+             (<a href=\"https://example.com/?q=&quot;x&quot;&amp;y=1\" rel=\"nofollow noopener noreferrer ugc\" target=\"_blank\">\
+             https://example.com/?q=&quot;x&quot;&amp;y=1</a>)</p><pre><code>&quot;&gt;&lt;img&gt;</code></pre>"
+        );
+    }
+}
@@ -0,0 +1,191 @@
+use sqlx::PgPool;
+use uuid::Uuid;
+use crate::models::events::UserEvent;
+use crate::models::notifications::Notification;
+use crate::state::AppState;
+
+/// Who a room message or thread reply mentions, resolved when it is sent or
+/// edited and stored on its row.
+#[derive(Default)]
+pub struct Mentions {
+    pub user_ids: Vec<Uuid>,
+    pub role_ids: Vec<Uuid>,
+    pub everyone: bool,
+}
+
+impl Mentions {
+    pub fn is_empty(&self) -> bool {
+        !self.everyone && self.user_ids.is_empty() && self.role_ids.is_empty()
+    }
+}
+
+/// The lowercased names after each `@`, without duplicates. An `@` inside a
+/// word (e.g. an email address) doesn't start a mention, and trailing `.` or
+/// `-` are treated as punctuation.
+pub fn mention_names(content: &str) -> Vec<String> {
+    let mut names: Vec<String> = Vec::new();
+    let mut previous: Option<char> = None;
+    let mut chars = content.char_indices().peekable();
+
+    while let Some((i, c)) = chars.next() {
+        let starts_mention = c == '@' && !previous.is_some_and(|p| p.is_alphanumeric() || p == '_');
+        previous = Some(c);
+        if !starts_mention {
+            continue;
+        }
+
+        let start = i + 1;
+        let mut end = start;
+        while let Some(&(j, n)) = chars.peek() {
+            if !(n.is_alphanumeric() || matches!(n, '_' | '.' | '-')) {
+                break;
+            }
+            end = j + n.len_utf8();
+            previous = Some(n);
+            chars.next();
+        }
+
+        let name = content[start..end].trim_end_matches(['.', '-']).to_lowercase();
+        if !name.is_empty() && !names.contains(&name) {
+            names.push(name);
+        }
+    }
+    names
+}
+
+/// Resolves `@username`, `@role` and `@everyone` against the room: only
+/// members and the room's own roles count. A name matching both a member and
+/// a role mentions both.
+pub async fn resolve(pool: &PgPool, room_id: Uuid, content: &str) -> Result<Mentions, sqlx::Error> {
+    let names = mention_names(content);
+    if names.is_empty() {
+        return Ok(Mentions::default());
+    }
+
+    let user_ids = sqlx::query_scalar!(
+        r#"
+        SELECT u.id FROM room_members rm
+        JOIN users u ON u.id = rm.user_id
+        WHERE rm.room_id = $1 AND lower(u.username) = ANY($2)
+        "#,
+        room_id,
+        &names
+    )
+    .fetch_all(pool)
+    .await?;
+
+    let role_ids = sqlx::query_scalar!(
+        "SELECT id FROM room_roles WHERE room_id = $1 AND lower(name) = ANY($2)",
+        room_id,
+        &names
+    )
+    .fetch_all(pool)
+    .await?;
+
+    Ok(Mentions {
+        user_ids,
+        role_ids,
+        everyone: names.iter().any(|n| n == "everyone"),
+    })
+}
+
+/// Files a notification for every member the message mentions, except its
+/// author, and pushes it to their notification sockets. For a thread reply
+/// `message_id` is the parent and `thread_reply_id` the reply. Members notified
+/// for this message or reply before (e.g. ahead of an edit) are skipped.
+/// Failures are logged rather than failing the send, which has already happened.
+pub async fn notify(
+    state: &AppState,
+    room_id: Uuid,
+    message_id: Uuid,
+    thread_reply_id: Option<Uuid>,
+    author_id: Uuid,
+    mentions: &Mentions,
+) {
+    if mentions.is_empty() {
+        return;
+    }
+
+    let created = sqlx::query!(
+        r#"
+        WITH created AS (
+            INSERT INTO notifications (user_id, room_id, message_id, thread_reply_id, actor_id)
+            SELECT rm.user_id, $1, $2, $7, $3
+            FROM room_members rm
+            WHERE rm.room_id = $1 AND rm.user_id <> $3
+              AND ($4 OR rm.user_id = ANY($5) OR EXISTS (
+                    SELECT 1 FROM room_member_roles mr
+                    WHERE mr.room_id = $1 AND mr.user_id = rm.user_id AND mr.role_id = ANY($6)
+                  ))
+            ON CONFLICT (user_id, message_id, thread_reply_id) DO NOTHING
+            RETURNING *
+        )
+        SELECT c.id, c.user_id AS "recipient_id!", c.kind, c.room_id, r.name AS room_name,
+               c.message_id, c.thread_reply_id, c.actor_id, a.username AS actor_username,
+               LEFT(COALESCE(tr.content, m.content), 100) AS "snippet!", c.created_at, c.read_at
+        FROM created c
+        JOIN rooms r ON r.id = c.room_id
+        JOIN users a ON a.id = c.actor_id
+        JOIN messages m ON m.id = c.message_id
+        LEFT JOIN thread_replies tr ON tr.id = c.thread_reply_id
+        "#,
+        room_id,
+        message_id,
+        author_id,
+        mentions.everyone,
+        &mentions.user_ids,
+        &mentions.role_ids,
+        thread_reply_id
+    )
+    .fetch_all(&state.pool)
+    .await;
+
+    match created {
+        Ok(created) => {
+            for row in created {
+                let notification = Notification {
+                    id: row.id,
+                    kind: row.kind,
+                    room_id: row.room_id,
+                    room_name: row.room_name,
+                    message_id: row.message_id,
+                    thread_reply_id: row.thread_reply_id,
+                    actor_id: row.actor_id,
+                    actor_username: row.actor_username,
+                    snippet: row.snippet,
+                    created_at: row.created_at,
+                    read_at: row.read_at,
+                };
+                state.notify_user(row.recipient_id, &UserEvent::Notification(notification)).await;
+            }
+        }
+        Err(e) => eprintln!(
+            "❌ Failed to file mention notifications for {}: {e}",
+            thread_reply_id.unwrap_or(message_id)
+        ),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn email_addresses_are_not_mentions() {
+        assert_eq!(mention_names("mail bob@example.com or @alice"), ["alice"]);
+        assert!(mention_names("a_b@c").is_empty());
+    }
+
+    #[test]
+    fn trailing_punctuation_is_dropped() {
+        assert_eq!(mention_names("thanks @alice. and @bob-, (@carol)!"), ["alice", "bob", "carol"]);
+        assert_eq!(mention_names("@j.doe-smith: hi"), ["j.doe-smith"]);
+        assert!(mention_names("just an @ sign, @. and @-").is_empty());
+    }
+
+    #[test]
+    fn names_are_lowercased_once() {
+        assert_eq!(mention_names("@Alice @alice @ALICE @everyone @Everyone"), ["alice", "everyone"]);
+        assert_eq!(mention_names("@Ünïcode_1"), ["ünïcode_1"]);
+    }
+}
@@ -0,0 +1,727 @@
+use axum::{
+    http::{header, StatusCode},
+    response::{IntoResponse, Response},
+};
+use crate::auth::membership::RoomMember;
+use crate::link_previews::{self, Target};
+use crate::markdown::{self, Refs};
+use crate::mentions;
+use crate::models::events::{DmEvent, RoomEvent};
+use crate::models::roles::permissions;
+use crate::models::forum::ForumPost;
+use crate::models::attachments::{Attachment, MAX_ATTACHMENTS_PER_MESSAGE};
+use crate::models::embeds::Embed;
+use crate::models::markdown::Block;
+use crate::models::messages::{DirectMessage, ReplyPreview, SendMessageInput};
+use crate::models::rooms::{RoomKind, RoomMessage, RoomMessageInput};
+use crate::models::threads::ThreadReply;
+use crate::state::AppState;
+use sqlx::{PgConnection, PgPool};
+use sqlx::types::Json;
+use time::OffsetDateTime;
+use uuid::Uuid;
+
+pub const MAX_SLOWMODE_SECONDS: i32 = 6 * 60 * 60;
+
+/// Why a room message was rejected. The REST handler turns this into an HTTP
+/// response, the WebSocket loop into an `error` event for the sender.
+#[derive(Debug)]
+pub enum SendError {
+    Slowmode { retry_after: i64 },
+    RulesNotAccepted,
+    /// The sender left or was removed, or the room was deleted, since the
+    /// socket was opened.
+    NotMember,
+    /// The request can never succeed as sent, e.g. a plain message in a forum room.
+    Invalid(&'static str),
+    NotFound(&'static str),
+    Internal(String),
+}
+
+impl SendError {
+    pub fn status(&self) -> StatusCode {
+        match self {
+            SendError::Slowmode { .. } => StatusCode::TOO_MANY_REQUESTS,
+            SendError::RulesNotAccepted => StatusCode::FORBIDDEN,
+            SendError::NotMember => StatusCode::FORBIDDEN,
+            SendError::Invalid(_) => StatusCode::BAD_REQUEST,
+            SendError::NotFound(_) => StatusCode::NOT_FOUND,
+            SendError::Internal(_) => StatusCode::INTERNAL_SERVER_ERROR,
+        }
+    }
+
+    pub fn to_event(&self) -> RoomEvent {
+        match self {
+            SendError::Slowmode { retry_after } => RoomEvent::Error {
+                code: "slowmode",
+                message: self.to_string(),
+                retry_after: Some(*retry_after),
+            },
+            SendError::RulesNotAccepted => RoomEvent::Error {
+                code: "rules_not_accepted",
+                message: self.to_string(),
+                retry_after: None,
+            },
+            SendError::NotMember => RoomEvent::Error {
+                code: "not_member",
+                message: self.to_string(),
+                retry_after: None,
+            },
+            SendError::Invalid(_) => RoomEvent::Error {
+                code: "invalid",
+                message: self.to_string(),
+                retry_after: None,
+            },
+            SendError::NotFound(_) => RoomEvent::Error {
+                code: "not_found",
+                message: self.to_string(),
+                retry_after: None,
+            },
+            SendError::Internal(_) => RoomEvent::Error {
+                code: "internal",
+                message: "Failed to send message".into(),
+                retry_after: None,
+            },
+        }
+    }
+}
+
+impl std::fmt::Display for SendError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            SendError::Slowmode { retry_after } => {
+                write!(f, "Slowmode is enabled, you can send again in {retry_after}s")
+            }
+            SendError::RulesNotAccepted => f.write_str("Accept the room rules before posting"),
+            SendError::NotMember => f.write_str("You are not a member of this room"),
+            SendError::Invalid(reason) => f.write_str(reason),
+            SendError::NotFound(what) => write!(f, "{what} not found"),
+            SendError::Internal(e) => f.write_str(e),
+        }
+    }
+}
+
+impl IntoResponse for SendError {
+    fn into_response(self) -> Response {
+        match self {
+            SendError::Slowmode { retry_after } => (
+                self.status(),
+                [(header::RETRY_AFTER, retry_after.to_string())],
+                self.to_string(),
+            )
+                .into_response(),
+            _ => (self.status(), self.to_string()).into_response(),
+        }
+    }
+}
+
+impl From<sqlx::Error> for SendError {
+    fn from(e: sqlx::Error) -> Self {
+        SendError::Internal(e.to_string())
+    }
+}
+
+/// The single write path for room messages, shared by `POST /messages` and the
+/// room WebSocket so both enforce the same rules and both reach live subscribers.
+pub async fn send_room_message(
+    state: &AppState,
+    member: &RoomMember,
+    input: RoomMessageInput,
+) -> Result<RoomMessage, SendError> {
+    let RoomMessageInput { content, channel_id, reply_to, attachment_ids } = input;
+
+    if room_kind(state, member).await? == RoomKind::Forum {
+        return Err(SendError::Invalid("Forum rooms only accept posts"));
+    }
+    if attachment_ids.len() > MAX_ATTACHMENTS_PER_MESSAGE {
+        return Err(SendError::Invalid("A message can have at most 10 attachments"));
+    }
+    if let Some(channel_id) = channel_id {
+        check_channel(state, member, channel_id).await?;
+    }
+    if let Some(reply_to) = reply_to {
+        check_reply_target(state, member, reply_to).await?;
+    }
+    check_screening(state, member).await?;
+    let mentions = mentions::resolve(&state.pool, member.room_id, &content).await?;
+    let ast = markdown::parse(&content, &Refs::for_room(&state.pool, member.room_id, &content).await?);
+
+    let mut tx = state.pool.begin().await?;
+    check_slowmode(&mut tx, member).await?;
+
+    // The preview is rendered with the sender's history cutoff
+    let mut message = sqlx::query_as!(
+        RoomMessage,
+        r#"
+        INSERT INTO messages (
+            room_id, author_id, content, reply_to,
+            mentioned_user_ids, mentioned_role_ids, mentions_everyone, content_ast, channel_id
+        )
+        SELECT id, $2, $3, $4, $6, $7, $8, $9, $10 FROM rooms
+        WHERE id = $1 AND deleted_at IS NULL
+        RETURNING id, room_id, author_id, content, created_at, edited_at,
+                  thread_reply_count, thread_last_reply_at, kind, deleted_at, channel_id, reply_to,
+                  mentioned_user_ids, mentioned_role_ids, mentions_everyone,
+                  message_reply_preview(reply_to, $5) AS "reply_preview: Json<ReplyPreview>",
+                  '[]'::JSONB AS "attachments!: Json<Vec<Attachment>>",
+                  embeds AS "embeds: Json<Vec<Embed>>",
+                  content_ast AS "content_ast: Json<Vec<Block>>"
+        "#,
+        member.room_id,
+        member.user_id,
+        content,
+        reply_to,
+        member.history_cutoff,
+        &mentions.user_ids,
+        &mentions.role_ids,
+        mentions.everyone,
+        Json(&ast) as _,
+        channel_id
+    )
+    .fetch_optional(&mut *tx)
+    .await?
+    .ok_or(SendError::NotFound("Room"))?;
+
+    if !attachment_ids.is_empty() {
+        // Only the sender's own pending uploads to this room can be claimed
+        let attachments = sqlx::query_scalar!(
+            r#"
+            UPDATE attachments
+            SET message_id = $1
+            WHERE id = ANY($2) AND uploader_id = $3 AND room_id = $4
+              AND message_id IS NULL AND deleted_at IS NULL
+            RETURNING attachment_json(attachments) AS "attachment!: Json<Attachment>"
+            "#,
+            message.id,
+            &attachment_ids,
+            member.user_id,
+            member.room_id
+        )
+        .fetch_all(&mut *tx)
+        .await?;
+
+        if attachments.len() != unique_count(&attachment_ids) {
+            return Err(SendError::NotFound("Attachment"));
+        }
+        message.attachments = Json(attachments.into_iter().map(|a| a.0).collect());
+    }
+
+    tx.commit().await?;
+
+    state.broadcast(member.room_id, &RoomEvent::Message(message.clone())).await;
+    mentions::notify(state, member.room_id, message.id, None, member.user_id, &mentions).await;
+    link_previews::unfurl(state, Target::Room { room_id: member.room_id, message_id: message.id }, &message.content);
+
+    Ok(message)
+}
+
+/// Stores a DM, optionally replying to an earlier message between the same
+/// two users. Shared by `POST /dm/{user}` and the DM WebSocket.
+pub async fn send_direct_message(
+    state: &AppState,
+    sender_id: Uuid,
+    receiver_id: Uuid,
+    input: SendMessageInput,
+) -> Result<DirectMessage, SendError> {
+    let SendMessageInput { content, reply_to, attachment_ids } = input;
+
+    if attachment_ids.len() > MAX_ATTACHMENTS_PER_MESSAGE {
+        return Err(SendError::Invalid("A message can have at most 10 attachments"));
+    }
+    if let Some(reply_to) = reply_to {
+        let in_conversation = sqlx::query_scalar!(
+            r#"
+            SELECT EXISTS (
+                SELECT 1 FROM direct_messages
+                WHERE id = $1 AND deleted_at IS NULL
+                  AND ((sender_id = $2 AND receiver_id = $3) OR (sender_id = $3 AND receiver_id = $2))
+            ) AS "exists!"
+            "#,
+            reply_to,
+            sender_id,
+            receiver_id
+        )
+        .fetch_one(&state.pool)
+        .await?;
+
+        if !in_conversation {
+            return Err(SendError::NotFound("Replied-to message"));
+        }
+    }
+
+    let ast = markdown::parse(&content, &Refs::for_dm(&state.pool, [sender_id, receiver_id], &content).await?);
+
+    let mut tx = state.pool.begin().await?;
+
+    let mut message = sqlx::query_as!(
+        DirectMessage,
+        r#"
+        INSERT INTO direct_messages (sender_id, receiver_id, content, reply_to, content_ast)
+        VALUES ($1, $2, $3, $4, $5)
+        RETURNING id, sender_id, receiver_id, content, created_at, edited_at, deleted_at, reply_to,
+                  direct_message_reply_preview(reply_to) AS "reply_preview: Json<ReplyPreview>",
+                  '[]'::JSONB AS "attachments!: Json<Vec<Attachment>>",
+                  embeds AS "embeds: Json<Vec<Embed>>",
+                  content_ast AS "content_ast: Json<Vec<Block>>"
+        "#,
+        sender_id,
+        receiver_id,
+        content,
+        reply_to,
+        Json(&ast) as _
+    )
+    .fetch_one(&mut *tx)
+    .await?;
+
+    if !attachment_ids.is_empty() {
+        let attachments = sqlx::query_scalar!(
+            r#"
+            UPDATE attachments
+            SET direct_message_id = $1
+            WHERE id = ANY($2) AND uploader_id = $3 AND dm_peer_id = $4
+              AND direct_message_id IS NULL AND deleted_at IS NULL
+            RETURNING attachment_json(attachments) AS "attachment!: Json<Attachment>"
+            "#,
+            message.id,
+            &attachment_ids,
+            sender_id,
+            receiver_id
+        )
+        .fetch_all(&mut *tx)
+        .await?;
+
+        if attachments.len() != unique_count(&attachment_ids) {
+            return Err(SendError::NotFound("Attachment"));
+        }
+        message.attachments = Json(attachments.into_iter().map(|a| a.0).collect());
+    }
+
+    tx.commit().await?;
+
+    state.broadcast_dm(sender_id, receiver_id, &DmEvent::Message(message.clone())).await;
+    let target = Target::Dm { sender_id, receiver_id, message_id: message.id };
+    link_previews::unfurl(state, target, &message.content);
+
+    Ok(message)
+}
+
+/// Starts a new post in a forum room. Same screening and slowmode as a
+/// message; `title` and `tags` must already be validated.
+pub async fn create_post(
+    state: &AppState,
+    member: &RoomMember,
+    title: String,
+    content: String,
+    tags: Vec<String>,
+) -> Result<ForumPost, SendError> {
+    if room_kind(state, member).await? != RoomKind::Forum {
+        return Err(SendError::Invalid("Posts are only available in forum rooms"));
+    }
+    check_screening(state, member).await?;
+    let mentions = mentions::resolve(&state.pool, member.room_id, &content).await?;
+    let ast = markdown::parse(&content, &Refs::for_room(&state.pool, member.room_id, &content).await?);
+
+    let mut tx = state.pool.begin().await?;
+    check_slowmode(&mut tx, member).await?;
+
+    let post = sqlx::query_as!(
+        ForumPost,
+        r#"
+        INSERT INTO messages (
+            room_id, author_id, content, title, tags,
+            mentioned_user_ids, mentioned_role_ids, mentions_everyone, content_ast
+        )
+        SELECT id, $2, $3, $4, $5, $6, $7, $8, $9 FROM rooms
+        WHERE id = $1 AND deleted_at IS NULL
+        RETURNING id, room_id, author_id, title AS "title!", content, tags, created_at,
+                  created_at AS last_activity_at, thread_reply_count AS reply_count,
+                  solved_at, solved_by, solution_id
+        "#,
+        member.room_id,
+        member.user_id,
+        content,
+        title,
+        &tags,
+        &mentions.user_ids,
+        &mentions.role_ids,
+        mentions.everyone,
+        Json(&ast) as _
+    )
+    .fetch_optional(&mut *tx)
+    .await?
+    .ok_or(SendError::NotFound("Room"))?;
+
+    tx.commit().await?;
+
+    state.broadcast(member.room_id, &RoomEvent::ForumPost(post.clone())).await;
+    mentions::notify(state, member.room_id, post.id, None, member.user_id, &mentions).await;
+    link_previews::unfurl(state, Target::Room { room_id: member.room_id, message_id: post.id }, &post.content);
+
+    Ok(post)
+}
+
+/// Turns room messages into tombstones: content, title and tags are wiped along
+/// with their edit history, thread replies, pins, reactions, mentions and
+/// notifications, and their attachments are released for the cleanup job. The rows keep their
+/// place in the history. Returns the ids that weren't already deleted, with
+/// when each was sent.
+pub async fn tombstone_messages(
+    pool: &PgPool,
+    room_id: Uuid,
+    message_ids: &[Uuid],
+    deleted_by: Uuid,
+) -> Result<Vec<(Uuid, OffsetDateTime)>, sqlx::Error> {
+    let rows = sqlx::query!(
+        r#"
+        WITH gone AS (
+            UPDATE messages
+            SET content = '',
+                title = CASE WHEN title IS NULL THEN NULL ELSE '' END,
+                tags = '{}',
+                mentioned_user_ids = '{}',
+                mentioned_role_ids = '{}',
+                mentions_everyone = FALSE,
+                embeds = '[]',
+                content_ast = '[]',
+                thread_reply_count = 0,
+                thread_last_reply_at = NULL,
+                deleted_at = NOW(),
+                deleted_by = $3
+            WHERE room_id = $1 AND id = ANY($2) AND deleted_at IS NULL
+            RETURNING id, created_at
+        ), replies AS (
+            DELETE FROM thread_replies WHERE parent_id IN (SELECT id FROM gone)
+        ), revisions AS (
+            DELETE FROM message_revisions WHERE message_id IN (SELECT id FROM gone)
+        ), pins AS (
+            DELETE FROM room_pins WHERE message_id IN (SELECT id FROM gone)
+        ), reactions AS (
+            DELETE FROM message_reactions WHERE message_id IN (SELECT id FROM gone)
+        ), notifications AS (
+            DELETE FROM notifications WHERE message_id IN (SELECT id FROM gone)
+        ), attachments AS (
+            UPDATE attachments SET message_id = NULL, deleted_at = NOW()
+            WHERE message_id IN (SELECT id FROM gone)
+        )
+        SELECT id AS "id!", created_at AS "created_at!" FROM gone
+        "#,
+        room_id,
+        message_ids,
+        deleted_by
+    )
+    .fetch_all(pool)
+    .await?;
+
+    Ok(rows.into_iter().map(|row| (row.id, row.created_at)).collect())
+}
+
+/// Removes replies from a message's thread, along with their notifications,
+/// and refreshes the parent's thread summary. Subscribers of the thread and
+/// the room are told. Returns the ids that were removed.
+pub async fn delete_thread_replies(
+    state: &AppState,
+    room_id: Uuid,
+    parent_id: Uuid,
+    reply_ids: &[Uuid],
+) -> Result<Vec<Uuid>, sqlx::Error> {
+    let row = sqlx::query!(
+        r#"
+        WITH gone AS (
+            DELETE FROM thread_replies
+            WHERE parent_id = $1 AND room_id = $2 AND id = ANY($3)
+            RETURNING id
+        ), parent AS (
+            UPDATE messages
+            SET thread_reply_count = thread_reply_count - (SELECT COUNT(*) FROM gone)::INT,
+                thread_last_reply_at = (
+                    SELECT MAX(created_at) FROM thread_replies
+                    WHERE parent_id = $1 AND id NOT IN (SELECT id FROM gone)
+                )
+            WHERE id = $1
+            RETURNING thread_reply_count, thread_last_reply_at, created_at
+        )
+        SELECT ARRAY(SELECT id FROM gone) AS "reply_ids!",
+               parent.thread_reply_count, parent.thread_last_reply_at, parent.created_at
+        FROM parent
+        "#,
+        parent_id,
+        room_id,
+        reply_ids
+    )
+    .fetch_optional(&state.pool)
+    .await?;
+
+    let Some(row) = row.filter(|row| !row.reply_ids.is_empty()) else {
+        return Ok(Vec::new());
+    };
+
+    state
+        .broadcast(parent_id, &RoomEvent::ThreadRepliesDeleted { message_id: parent_id, reply_ids: row.reply_ids.clone() })
+        .await;
+    state
+        .broadcast(
+            room_id,
+            &RoomEvent::ThreadUpdated {
+                message_id: parent_id,
+                reply_count: row.thread_reply_count,
+                last_reply_at: row.thread_last_reply_at,
+                sent_at: row.created_at,
+            },
+        )
+        .await;
+
+    Ok(row.reply_ids)
+}
+
+/// Posts a server notice into the room, attributed to `author_id` but marked
+/// `system`. Skips screening and slowmode, which only apply to members.
+pub async fn post_system_message(
+    state: &AppState,
+    room_id: Uuid,
+    author_id: Uuid,
+    content: String,
+) -> Result<RoomMessage, sqlx::Error> {
+    let message = sqlx::query_as!(
+        RoomMessage,
+        r#"
+        INSERT INTO messages (room_id, author_id, content, kind, content_ast)
+        VALUES ($1, $2, $3, 'system', $4)
+        RETURNING id, room_id, author_id, content, created_at, edited_at,
+                  thread_reply_count, thread_last_reply_at, kind, deleted_at, channel_id, reply_to,
+                  mentioned_user_ids, mentioned_role_ids, mentions_everyone,
+                  NULL::JSONB AS "reply_preview: Json<ReplyPreview>",
+                  '[]'::JSONB AS "attachments!: Json<Vec<Attachment>>",
+                  embeds AS "embeds: Json<Vec<Embed>>",
+                  content_ast AS "content_ast: Json<Vec<Block>>"
+        "#,
+        room_id,
+        author_id,
+        content,
+        Json(markdown::parse(&content, &Refs::default())) as _
+    )
+    .fetch_one(&state.pool)
+    .await?;
+
+    state.broadcast(room_id, &RoomEvent::Message(message.clone())).await;
+
+    Ok(message)
+}
+
+/// Posts a reply into a message's thread. Replies count against slowmode like
+/// any other send; the full reply goes to thread subscribers and the updated
+/// summary to the whole room.
+pub async fn send_thread_reply(
+    state: &AppState,
+    member: &RoomMember,
+    parent_id: Uuid,
+    content: String,
+) -> Result<ThreadReply, SendError> {
+    let parent_exists = member.sees_thread(&state.pool, parent_id).await?;
+
+    if !parent_exists {
+        return Err(SendError::NotFound("Message"));
+    }
+
+    check_screening(state, member).await?;
+    let mentions = mentions::resolve(&state.pool, member.room_id, &content).await?;
+    let ast = markdown::parse(&content, &Refs::for_room(&state.pool, member.room_id, &content).await?);
+
+    let mut tx = state.pool.begin().await?;
+    check_slowmode(&mut tx, member).await?;
+
+    let row = sqlx::query!(
+        r#"
+        WITH reply AS (
+            INSERT INTO thread_replies (
+                parent_id, room_id, author_id, content,
+                mentioned_user_ids, mentioned_role_ids, mentions_everyone, content_ast
+            )
+            VALUES ($1, $2, $3, $4, $5, $6, $7, $8)
+            RETURNING id, parent_id, room_id, author_id, content, created_at, edited_at
+        ),
+        parent AS (
+            UPDATE messages
+            SET thread_reply_count = thread_reply_count + 1,
+                thread_last_reply_at = (SELECT created_at FROM reply)
+            WHERE id = $1
+            RETURNING thread_reply_count, created_at
+        )
+        SELECT
+            reply.id AS "id!",
+            reply.parent_id AS "parent_id!",
+            reply.room_id AS "room_id!",
+            reply.author_id AS "author_id!",
+            reply.content AS "content!",
+            reply.created_at AS "created_at!",
+            reply.edited_at,
+            parent.thread_reply_count AS "reply_count!",
+            parent.created_at AS "parent_sent_at!"
+        FROM reply, parent
+        "#,
+        parent_id,
+        member.room_id,
+        member.user_id,
+        content,
+        &mentions.user_ids,
+        &mentions.role_ids,
+        mentions.everyone,
+        Json(&ast) as _
+    )
+    .fetch_one(&mut *tx)
+    .await?;
+
+    tx.commit().await?;
+
+    let reply = ThreadReply {
+        id: row.id,
+        parent_id: row.parent_id,
+        room_id: row.room_id,
+        author_id: row.author_id,
+        content: row.content,
+        created_at: row.created_at,
+        edited_at: row.edited_at,
+        content_ast: Some(Json(ast)),
+    };
+
+    state.broadcast(parent_id, &RoomEvent::ThreadReply(reply.clone())).await;
+    state
+        .broadcast(
+            member.room_id,
+            &RoomEvent::ThreadUpdated {
+                message_id: parent_id,
+                reply_count: row.reply_count,
+                last_reply_at: Some(reply.created_at),
+                sent_at: row.parent_sent_at,
+            },
+        )
+        .await;
+    mentions::notify(state, member.room_id, parent_id, Some(reply.id), member.user_id, &mentions).await;
+
+    Ok(reply)
+}
+
+fn unique_count(ids: &[Uuid]) -> usize {
+    ids.iter().collect::<std::collections::HashSet<_>>().len()
+}
+
+/// Replies must quote a live message the sender can see in the same room.
+async fn check_reply_target(
+    state: &AppState,
+    member: &RoomMember,
+    reply_to: Uuid,
+) -> Result<(), SendError> {
+    let visible = sqlx::query_scalar!(
+        r#"
+        SELECT EXISTS (
+            SELECT 1 FROM messages
+            WHERE id = $1 AND room_id = $2 AND deleted_at IS NULL
+              AND ($3::TIMESTAMPTZ IS NULL OR created_at >= $3)
+        ) AS "exists!"
+        "#,
+        reply_to,
+        member.room_id,
+        member.history_cutoff
+    )
+    .fetch_one(&state.pool)
+    .await?;
+
+    if visible {
+        Ok(())
+    } else {
+        Err(SendError::NotFound("Replied-to message"))
+    }
+}
+
+/// Messages can only be posted to one of the room's own channels.
+async fn check_channel(state: &AppState, member: &RoomMember, channel_id: Uuid) -> Result<(), SendError> {
+    let in_room = sqlx::query_scalar!(
+        "SELECT EXISTS (SELECT 1 FROM room_channels WHERE id = $1 AND room_id = $2) AS \"exists!\"",
+        channel_id,
+        member.room_id
+    )
+    .fetch_one(&state.pool)
+    .await?;
+
+    if in_room {
+        Ok(())
+    } else {
+        Err(SendError::NotFound("Channel"))
+    }
+}
+
+async fn room_kind(state: &AppState, member: &RoomMember) -> Result<RoomKind, SendError> {
+    let kind = sqlx::query_scalar!("SELECT kind FROM rooms WHERE id = $1", member.room_id)
+        .fetch_one(&state.pool)
+        .await?;
+
+    Ok(if kind == RoomKind::Forum.as_str() { RoomKind::Forum } else { RoomKind::Chat })
+}
+
+/// Rejects members who haven't accepted the current rules. Read from the
+/// database on every send so an acceptance made over REST unblocks an
+/// already-open socket. The owner wrote the rules and is never screened.
+async fn check_screening(state: &AppState, member: &RoomMember) -> Result<(), SendError> {
+    let screened = sqlx::query_scalar!(
+        r#"
+        SELECT r.rules IS NULL OR r.owner_id = rm.user_id
+               OR COALESCE(rm.rules_accepted_version >= r.rules_required_version, FALSE) AS "screened!"
+        FROM room_members rm
+        JOIN rooms r ON r.id = rm.room_id
+        WHERE rm.room_id = $1 AND rm.user_id = $2
+        "#,
+        member.room_id,
+        member.user_id
+    )
+    .fetch_one(&state.pool)
+    .await?;
+
+    if screened {
+        Ok(())
+    } else {
+        Err(SendError::RulesNotAccepted)
+    }
+}
+
+/// Claims the member's next send slot, or reports how long until it opens.
+/// Members who can manage messages are exempt.
+///
+/// Runs in the send's own transaction: the member row stays locked until the
+/// message is stored, so racing sends queue up behind each other, and a send
+/// that fails later on rolls the claim back.
+async fn check_slowmode(conn: &mut PgConnection, member: &RoomMember) -> Result<(), SendError> {
+    let row = sqlx::query!(
+        r#"
+        SELECT r.slowmode_seconds, rm.last_message_at, NOW() AS "now!"
+        FROM room_members rm
+        JOIN rooms r ON r.id = rm.room_id
+        WHERE rm.room_id = $1 AND rm.user_id = $2
+        FOR UPDATE OF rm
+        "#,
+        member.room_id,
+        member.user_id
+    )
+    .fetch_optional(&mut *conn)
+    .await?
+    .ok_or(SendError::NotMember)?;
+
+    if !member.can(permissions::MANAGE_MESSAGES)
+        && row.slowmode_seconds > 0
+        && let Some(last) = row.last_message_at
+    {
+        let wait = last + time::Duration::seconds(row.slowmode_seconds.into()) - row.now;
+        if wait.is_positive() {
+            let retry_after = wait.as_seconds_f64().ceil() as i64;
+            return Err(SendError::Slowmode { retry_after: retry_after.max(1) });
+        }
+    }
+
+    sqlx::query!(
+        "UPDATE room_members SET last_message_at = NOW() WHERE room_id = $1 AND user_id = $2",
+        member.room_id,
+        member.user_id
+    )
+    .execute(&mut *conn)
+    .await?;
+
+    Ok(())
+}
@@ -0,0 +1,33 @@
+use uuid::Uuid;
+use serde::{Serialize, Deserialize};
+
+pub const MAX_ATTACHMENTS_PER_MESSAGE: usize = 10;
+
+/// A file on a message. `url` is the authorized download route; the bytes
+/// themselves live in the blob store.
+#[derive(Serialize, Deserialize, Clone)]
+pub struct Attachment {
+    pub id: Uuid,
+    pub filename: String,
+    /// Sniffed from the content, not taken from the client.
+    pub content_type: String,
+    pub size_bytes: i64,
+    pub url: String,
+    /// `none` for files that aren't images, otherwise `pending`,
+    /// `processing`, `ready` or `failed`.
+    pub processing: String,
+    pub width: Option<i32>,
+    pub height: Option<i32>,
+    /// Placeholder to paint while the image loads.
+    pub blurhash: Option<String>,
+    pub thumbnails: Vec<Thumbnail>,
+}
+
+/// A WebP rendition fitted into a `size` × `size` box.
+#[derive(Serialize, Deserialize, Clone)]
+pub struct Thumbnail {
+    pub size: i32,
+    pub width: i32,
+    pub height: i32,
+    pub url: String,
+}
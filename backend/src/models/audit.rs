@@ -0,0 +1,27 @@
+use serde::{Deserialize, Serialize};
+use uuid::Uuid;
+use time::OffsetDateTime;
+
+#[derive(Serialize)]
+pub struct AuditLogEntry {
+    pub id: i64,
+    pub room_id: Uuid,
+    pub actor_id: Option<Uuid>,
+    pub action: String,
+    pub target_id: Option<Uuid>,
+    pub before: Option<serde_json::Value>,
+    pub after: Option<serde_json::Value>,
+    #[serde(serialize_with = "time::serde::rfc3339::serialize")]
+    pub created_at: OffsetDateTime,
+}
+
+/// Filters for `GET /api/rooms/{id}/audit-log`. Entries come newest first;
+/// pass the last `id` you received as `before` to get the next page.
+#[derive(Deserialize)]
+pub struct AuditLogQuery {
+    pub action: Option<String>,
+    pub actor_id: Option<Uuid>,
+    pub target_id: Option<Uuid>,
+    pub before: Option<i64>,
+    pub limit: Option<i64>,
+}
@@ -0,0 +1,75 @@
+use serde::{Deserialize, Serialize};
+use uuid::Uuid;
+use crate::models::rooms::RoomInfo;
+
+#[derive(Serialize, Clone)]
+pub struct Channel {
+    pub id: Uuid,
+    pub category_id: Option<Uuid>,
+    pub name: String,
+    pub topic: Option<String>,
+    pub position: i32,
+}
+
+#[derive(Serialize, Clone)]
+pub struct Category {
+    pub id: Uuid,
+    pub name: String,
+    pub position: i32,
+    pub channels: Vec<Channel>,
+}
+
+/// The room's sidebar in render order.
+#[derive(Serialize, Clone)]
+pub struct RoomLayout {
+    pub uncategorized: Vec<Channel>,
+    pub categories: Vec<Category>,
+}
+
+/// `GET /api/rooms/{id}`: room settings plus the ordered sidebar tree.
+#[derive(Serialize)]
+pub struct RoomDetails {
+    #[serde(flatten)]
+    pub room: RoomInfo,
+    pub layout: RoomLayout,
+}
+
+/// `?channel_id=` on room history: only messages posted to that channel.
+#[derive(Deserialize)]
+pub struct ChannelFilter {
+    pub channel_id: Option<Uuid>,
+}
+
+#[derive(Deserialize)]
+pub struct CreateCategoryInput {
+    pub name: String,
+}
+
+#[derive(Deserialize)]
+pub struct CreateChannelInput {
+    pub name: String,
+    pub topic: Option<String>,
+    pub category_id: Option<Uuid>,
+}
+
+/// Omitted fields are left untouched; an empty `topic` clears it.
+#[derive(Deserialize)]
+pub struct UpdateChannelInput {
+    pub name: Option<String>,
+    pub topic: Option<String>,
+}
+
+#[derive(Deserialize)]
+pub struct CategoryOrder {
+    pub id: Uuid,
+    pub channels: Vec<Uuid>,
+}
+
+/// Complete new ordering for `PUT /api/rooms/{id}/layout`. Every category and
+/// channel of the room must appear exactly once; array order becomes position.
+#[derive(Deserialize)]
+pub struct LayoutInput {
+    #[serde(default)]
+    pub uncategorized: Vec<Uuid>,
+    pub categories: Vec<CategoryOrder>,
+}
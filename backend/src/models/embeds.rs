@@ -0,0 +1,13 @@
+use serde::{Serialize, Deserialize};
+
+/// Preview of a link in a message, built from the page's OpenGraph or
+/// Twitter-card tags.
+#[derive(Serialize, Deserialize, Clone, PartialEq)]
+pub struct Embed {
+    pub url: String,
+    pub title: Option<String>,
+    pub description: Option<String>,
+    /// Absolute `http(s)` URL of the page's preview image.
+    pub image_url: Option<String>,
+    pub site_name: Option<String>,
+}
@@ -0,0 +1,291 @@
+use serde::{Deserialize, Serialize};
+use std::borrow::Cow;
+use uuid::Uuid;
+use time::OffsetDateTime;
+use crate::models::rooms::{RoomInfo, RoomMessage};
+use crate::models::threads::ThreadReply;
+use crate::models::channels::RoomLayout;
+use crate::models::scheduled_events::ScheduledEvent;
+use crate::models::forum::ForumPost;
+use crate::models::messages::DirectMessage;
+use crate::models::notifications::Notification;
+use crate::models::attachments::Attachment;
+use crate::models::embeds::Embed;
+
+/// Events pushed to every socket subscribed to a room.
+///
+/// Events about a message carry when it was sent (`sent_at`, not serialized)
+/// so each socket can leave out what its member's history cutoff hides.
+#[derive(Serialize, Clone)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum RoomEvent {
+    Message(RoomMessage),
+    /// The message after its author edited it.
+    MessageEdited(RoomMessage),
+    /// Now a tombstone; clients drop its content.
+    MessageDeleted {
+        message_id: Uuid,
+        #[serde(skip)]
+        sent_at: OffsetDateTime,
+    },
+    /// A moderator purge turned these messages into tombstones.
+    MessagesPurged {
+        message_ids: Vec<Uuid>,
+        /// When each of `message_ids` was sent.
+        #[serde(skip)]
+        sent_at: Vec<OffsetDateTime>,
+    },
+    ReactionAdded {
+        message_id: Uuid,
+        user_id: Uuid,
+        emoji: String,
+        #[serde(skip)]
+        sent_at: OffsetDateTime,
+    },
+    ReactionRemoved {
+        message_id: Uuid,
+        user_id: Uuid,
+        emoji: String,
+        #[serde(skip)]
+        sent_at: OffsetDateTime,
+    },
+    /// An image on the message finished (or failed) processing.
+    AttachmentUpdated {
+        message_id: Uuid,
+        attachment: Attachment,
+        #[serde(skip)]
+        sent_at: OffsetDateTime,
+    },
+    /// Link previews on the message were fetched, or dropped by an edit.
+    EmbedsUpdated {
+        message_id: Uuid,
+        embeds: Vec<Embed>,
+        #[serde(skip)]
+        sent_at: OffsetDateTime,
+    },
+    RoomUpdate { room: RoomInfo },
+    RoomDeleted { room_id: Uuid },
+    LayoutUpdate { layout: RoomLayout },
+    MessagePinned {
+        message_id: Uuid,
+        pinned_by: Uuid,
+        #[serde(serialize_with = "time::serde::rfc3339::serialize")]
+        pinned_at: OffsetDateTime,
+        #[serde(skip)]
+        sent_at: OffsetDateTime,
+    },
+    MessageUnpinned {
+        message_id: Uuid,
+        #[serde(skip)]
+        sent_at: OffsetDateTime,
+    },
+    /// Only delivered to sockets subscribed to the thread.
+    ThreadReply(ThreadReply),
+    /// The reply after its author edited it. Thread subscribers only.
+    ThreadReplyEdited(ThreadReply),
+    /// Replies removed from the thread of `message_id`. Thread subscribers only.
+    ThreadRepliesDeleted { message_id: Uuid, reply_ids: Vec<Uuid> },
+    /// Summary change on the parent, delivered to the whole room.
+    ThreadUpdated {
+        message_id: Uuid,
+        reply_count: i32,
+        /// Unset once the last reply is deleted.
+        #[serde(serialize_with = "time::serde::rfc3339::option::serialize")]
+        last_reply_at: Option<OffsetDateTime>,
+        #[serde(skip)]
+        sent_at: OffsetDateTime,
+    },
+    /// The rules text changed; with `require_reacceptance` members must accept again.
+    RulesUpdate { version: i32, require_reacceptance: bool },
+    /// A scheduled event was created, edited or got an RSVP. `my_rsvp` is
+    /// always unset here.
+    ScheduledEvent { event: ScheduledEvent },
+    ScheduledEventDeleted { event_id: Uuid },
+    /// A forum post was created, retitled, retagged or (un)solved.
+    ForumPost(ForumPost),
+    /// Sent only to the socket whose request failed.
+    Error {
+        code: &'static str,
+        message: String,
+        #[serde(skip_serializing_if = "Option::is_none")]
+        retry_after: Option<i64>,
+    },
+}
+
+impl RoomEvent {
+    /// When the message this event is about was sent.
+    fn sent_at(&self) -> Option<OffsetDateTime> {
+        match self {
+            RoomEvent::Message(message) | RoomEvent::MessageEdited(message) => Some(message.created_at),
+            RoomEvent::ForumPost(post) => Some(post.created_at),
+            RoomEvent::MessageDeleted { sent_at, .. }
+            | RoomEvent::ReactionAdded { sent_at, .. }
+            | RoomEvent::ReactionRemoved { sent_at, .. }
+            | RoomEvent::AttachmentUpdated { sent_at, .. }
+            | RoomEvent::EmbedsUpdated { sent_at, .. }
+            | RoomEvent::MessagePinned { sent_at, .. }
+            | RoomEvent::MessageUnpinned { sent_at, .. }
+            | RoomEvent::ThreadUpdated { sent_at, .. } => Some(*sent_at),
+            _ => None,
+        }
+    }
+
+    /// The event as a member whose history starts at `cutoff` may see it.
+    /// Events about older messages are dropped, purges keep only the
+    /// messages the member could see, and replies quoting an older message
+    /// lose their preview.
+    pub fn visible_from(&self, cutoff: OffsetDateTime) -> Option<Cow<'_, RoomEvent>> {
+        if let RoomEvent::MessagesPurged { message_ids, sent_at } = self {
+            let (visible_ids, visible_sent_at): (Vec<Uuid>, Vec<OffsetDateTime>) = message_ids
+                .iter()
+                .zip(sent_at)
+                .filter(|(_, sent_at)| **sent_at >= cutoff)
+                .unzip();
+            return match visible_ids.len() {
+                0 => None,
+                n if n == message_ids.len() => Some(Cow::Borrowed(self)),
+                _ => Some(Cow::Owned(RoomEvent::MessagesPurged {
+                    message_ids: visible_ids,
+                    sent_at: visible_sent_at,
+                })),
+            };
+        }
+
+        match self.sent_at() {
+            Some(sent_at) if sent_at < cutoff => return None,
+            _ => {}
+        }
+
+        match self {
+            RoomEvent::Message(message) if message.quotes_before(cutoff) => {
+                Some(Cow::Owned(RoomEvent::Message(RoomMessage { reply_preview: None, ..message.clone() })))
+            }
+            RoomEvent::MessageEdited(message) if message.quotes_before(cutoff) => {
+                Some(Cow::Owned(RoomEvent::MessageEdited(RoomMessage { reply_preview: None, ..message.clone() })))
+            }
+            _ => Some(Cow::Borrowed(self)),
+        }
+    }
+
+    /// Room-wide changes after which sockets reload their membership: the
+    /// room may be gone, or its history visibility different.
+    pub fn changes_access(&self) -> bool {
+        matches!(self, RoomEvent::RoomUpdate { .. } | RoomEvent::RoomDeleted { .. })
+    }
+}
+
+/// Typed events on a DM socket.
+#[derive(Serialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum DmEvent {
+    Message(DirectMessage),
+    MessageEdited(DirectMessage),
+    MessageDeleted { message_id: Uuid },
+    ReactionAdded { message_id: Uuid, user_id: Uuid, emoji: String },
+    ReactionRemoved { message_id: Uuid, user_id: Uuid, emoji: String },
+    AttachmentUpdated { message_id: Uuid, attachment: Attachment },
+    EmbedsUpdated { message_id: Uuid, embeds: Vec<Embed> },
+}
+
+/// Events on a user's own notification socket, so every open client keeps
+/// the inbox in sync.
+#[derive(Serialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum UserEvent {
+    Notification(Notification),
+    NotificationRead { notification_id: Uuid },
+    NotificationsReadAll,
+}
+
+/// JSON frames a client may send on the room socket. Any frame that isn't one
+/// of these is posted as a plain message, as before.
+#[derive(Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum ClientCommand {
+    Message {
+        content: String,
+        channel_id: Option<Uuid>,
+        reply_to: Option<Uuid>,
+        #[serde(default)]
+        attachment_ids: Vec<Uuid>,
+    },
+    SubscribeThread { message_id: Uuid },
+    UnsubscribeThread { message_id: Uuid },
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use sqlx::types::Json;
+    use crate::models::messages::ReplyPreview;
+
+    fn at(seconds: i64) -> OffsetDateTime {
+        OffsetDateTime::UNIX_EPOCH + time::Duration::seconds(seconds)
+    }
+
+    #[test]
+    fn events_about_older_messages_are_dropped() {
+        let deleted = RoomEvent::MessageDeleted { message_id: Uuid::nil(), sent_at: at(10) };
+        assert!(matches!(deleted.visible_from(at(10)), Some(Cow::Borrowed(_))));
+        assert!(deleted.visible_from(at(11)).is_none());
+
+        // Not about a message: everyone gets it
+        let deleted_room = RoomEvent::RoomDeleted { room_id: Uuid::nil() };
+        assert!(deleted_room.visible_from(at(100)).is_some());
+    }
+
+    #[test]
+    fn purges_keep_only_visible_messages() {
+        let ids = [Uuid::from_u128(1), Uuid::from_u128(2), Uuid::from_u128(3)];
+        let purge = RoomEvent::MessagesPurged { message_ids: ids.to_vec(), sent_at: vec![at(30), at(10), at(20)] };
+
+        assert!(matches!(purge.visible_from(at(0)), Some(Cow::Borrowed(_))));
+        match purge.visible_from(at(20)).as_deref() {
+            Some(RoomEvent::MessagesPurged { message_ids, .. }) => assert_eq!(message_ids, &[ids[0], ids[2]]),
+            _ => panic!("expected a smaller purge"),
+        }
+        assert!(purge.visible_from(at(31)).is_none());
+    }
+
+    #[test]
+    fn replies_to_older_messages_lose_their_preview() {
+        let preview = ReplyPreview {
+            author_id: Uuid::nil(),
+            username: "alice".into(),
+            snippet: Some("hello".into()),
+            deleted: false,
+            created_at: at(10),
+        };
+        let reply = RoomEvent::Message(RoomMessage {
+            id: Uuid::from_u128(2),
+            room_id: Uuid::nil(),
+            author_id: Uuid::nil(),
+            content: "hi".into(),
+            created_at: at(20),
+            edited_at: None,
+            thread_reply_count: 0,
+            thread_last_reply_at: None,
+            kind: "user".into(),
+            deleted_at: None,
+            channel_id: None,
+            reply_to: Some(Uuid::from_u128(1)),
+            reply_preview: Some(Json(preview)),
+            mentioned_user_ids: Vec::new(),
+            mentioned_role_ids: Vec::new(),
+            mentions_everyone: false,
+            attachments: Json(Vec::new()),
+            embeds: Json(Vec::new()),
+            content_ast: None,
+        });
+
+        assert!(matches!(reply.visible_from(at(10)), Some(Cow::Borrowed(_))));
+        match reply.visible_from(at(15)).as_deref() {
+            Some(RoomEvent::Message(message)) => {
+                assert_eq!(message.reply_to, Some(Uuid::from_u128(1)));
+                assert!(message.reply_preview.is_none());
+            }
+            _ => panic!("expected the reply without its preview"),
+        }
+        assert!(reply.visible_from(at(21)).is_none());
+    }
+}
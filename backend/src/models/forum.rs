@@ -0,0 +1,74 @@
+use serde::{Deserialize, Serialize};
+use uuid::Uuid;
+use time::OffsetDateTime;
+
+pub const MAX_POST_TITLE_LENGTH: usize = 200;
+pub const MAX_TAGS_PER_POST: usize = 5;
+pub const MAX_TAG_LENGTH: usize = 30;
+
+/// A forum room's top-level message. Replies live in the message's thread.
+#[derive(Serialize, Clone)]
+pub struct ForumPost {
+    pub id: Uuid,
+    pub room_id: Uuid,
+    pub author_id: Uuid,
+    pub title: String,
+    pub content: String,
+    pub tags: Vec<String>,
+    #[serde(serialize_with = "time::serde::rfc3339::serialize")]
+    pub created_at: OffsetDateTime,
+    /// Latest reply, or creation when nobody replied yet.
+    #[serde(serialize_with = "time::serde::rfc3339::serialize")]
+    pub last_activity_at: OffsetDateTime,
+    pub reply_count: i32,
+    #[serde(serialize_with = "time::serde::rfc3339::option::serialize")]
+    pub solved_at: Option<OffsetDateTime>,
+    pub solved_by: Option<Uuid>,
+    pub solution_id: Option<Uuid>,
+}
+
+#[derive(Deserialize)]
+pub struct CreatePostInput {
+    pub title: String,
+    pub content: String,
+    #[serde(default)]
+    pub tags: Vec<String>,
+}
+
+/// Omitted fields are left untouched.
+#[derive(Deserialize)]
+pub struct UpdatePostInput {
+    pub title: Option<String>,
+    pub tags: Option<Vec<String>>,
+}
+
+#[derive(Deserialize, Clone, Copy, Default, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum PostSort {
+    #[default]
+    Activity,
+    Created,
+}
+
+/// `before` is the id of the last post of the previous page.
+#[derive(Deserialize)]
+pub struct PostListQuery {
+    #[serde(default)]
+    pub sort: PostSort,
+    pub tag: Option<String>,
+    pub solved: Option<bool>,
+    pub before: Option<Uuid>,
+    pub limit: Option<i64>,
+}
+
+#[derive(Deserialize)]
+pub struct SolvePostInput {
+    /// Reply in the post's thread that answered it.
+    pub reply_id: Option<Uuid>,
+}
+
+#[derive(Serialize)]
+pub struct TagCount {
+    pub tag: String,
+    pub count: i64,
+}
@@ -0,0 +1,47 @@
+use uuid::Uuid;
+use serde::{Serialize, Deserialize};
+
+/// Top-level structure of a message. Quotes hold paragraphs and code blocks
+/// but not further quotes.
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum Block {
+    Paragraph { children: Vec<Inline> },
+    /// Fenced with ```` ``` ````; `language` is lowercased and limited to
+    /// letters, digits and `_+#.-`.
+    #[serde(rename = "code_block")]
+    Code { language: Option<String>, code: String },
+    Quote { children: Vec<Block> },
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum Inline {
+    Text { text: String },
+    Bold { children: Vec<Inline> },
+    Italic { children: Vec<Inline> },
+    Strikethrough { children: Vec<Inline> },
+    Spoiler { children: Vec<Inline> },
+    Code { code: String },
+    LineBreak,
+    Link { url: String },
+    /// Only names that matched when the message was written become mentions;
+    /// the rest stay text.
+    UserMention { user_id: Uuid, name: String },
+    RoleMention { role_id: Uuid, name: String },
+    Everyone,
+    ChannelLink { channel_id: Uuid, name: String },
+}
+
+#[derive(Deserialize)]
+pub struct MarkdownInput {
+    pub content: String,
+}
+
+/// How a draft will look once sent.
+#[derive(Serialize)]
+pub struct MarkdownPreview {
+    pub ast: Vec<Block>,
+    /// Sanitized fragment for web previews and exports.
+    pub html: String,
+}
@@ -1,18 +1,85 @@
 use uuid::Uuid;
 use time::OffsetDateTime;
 use serde::{Serialize, Deserialize};
+use sqlx::types::Json;
+use crate::models::attachments::Attachment;
+use crate::models::embeds::Embed;
+use crate::models::markdown::Block;
 
 #[derive(Serialize,Deserialize)]
 pub struct SendMessageInput {
     pub content: String,
+    /// A message in the same conversation this one answers.
+    pub reply_to: Option<Uuid>,
+    /// Pending uploads from `POST /dm/{user}/attachments` to send with it.
+    #[serde(default)]
+    pub attachment_ids: Vec<Uuid>,
 }
 
-#[derive(Serialize, Deserialize)]
+/// What a reply shows of the message it quotes. Built by the
+/// `message_reply_preview` / `direct_message_reply_preview` SQL functions.
+#[derive(Serialize, Deserialize, Clone)]
+pub struct ReplyPreview {
+    pub author_id: Uuid,
+    pub username: String,
+    /// Start of the content, whitespace collapsed; unset once deleted.
+    pub snippet: Option<String>,
+    pub deleted: bool,
+    /// When the quoted message was sent.
+    #[serde(with = "time::serde::rfc3339")]
+    pub created_at: OffsetDateTime,
+}
+
+#[derive(Serialize, Deserialize, Clone)]
 pub struct DirectMessage {
     pub id: Uuid,
     pub sender_id: Uuid,
     pub receiver_id: Uuid,
     pub content: String,
     #[serde(serialize_with = "time::serde::rfc3339::serialize")]
-    pub created_at: OffsetDateTime
+    pub created_at: OffsetDateTime,
+    #[serde(serialize_with = "time::serde::rfc3339::option::serialize")]
+    pub edited_at: Option<OffsetDateTime>,
+    /// Set on tombstones; the content is empty.
+    #[serde(serialize_with = "time::serde::rfc3339::option::serialize")]
+    pub deleted_at: Option<OffsetDateTime>,
+    pub reply_to: Option<Uuid>,
+    pub reply_preview: Option<Json<ReplyPreview>>,
+    pub attachments: Json<Vec<Attachment>>,
+    /// Link previews, filled in shortly after the message is sent.
+    pub embeds: Json<Vec<Embed>>,
+    /// `content` parsed as markdown. Unset only for old messages the
+    /// background parser hasn't reached yet.
+    pub content_ast: Option<Json<Vec<Block>>>,
+}
+
+/// Paging for room and DM history. Pages come oldest first: pass the first
+/// message's id as `before` for older ones, or the last one's as `after` for
+/// newer ones. `around` centres the page on a message, and `at` on a moment,
+/// for jumping to a date. At most one of the four.
+#[derive(Deserialize)]
+pub struct HistoryQuery {
+    pub before: Option<Uuid>,
+    pub after: Option<Uuid>,
+    pub around: Option<Uuid>,
+    #[serde(default, with = "time::serde::rfc3339::option")]
+    pub at: Option<OffsetDateTime>,
+    pub limit: Option<i64>,
+}
+
+/// New content for a room message or DM; only its author may edit it.
+#[derive(Deserialize)]
+pub struct EditMessageInput {
+    pub content: String,
+}
+
+/// A message's content before one of its edits.
+#[derive(Serialize)]
+pub struct MessageRevision {
+    pub id: Uuid,
+    pub content: String,
+    pub edited_by: Option<Uuid>,
+    /// When this content was replaced.
+    #[serde(serialize_with = "time::serde::rfc3339::serialize")]
+    pub created_at: OffsetDateTime,
 }
@@ -1,4 +1,19 @@
 pub mod user;
 pub mod messages;
 pub mod rooms;
-pub mod relationships;
\ No newline at end of file
+pub mod relationships;
+pub mod events;
+pub mod roles;
+pub mod audit;
+pub mod pins;
+pub mod threads;
+pub mod channels;
+pub mod templates;
+pub mod rules;
+pub mod scheduled_events;
+pub mod forum;
+pub mod reactions;
+pub mod notifications;
+pub mod attachments;
+pub mod embeds;
+pub mod markdown;
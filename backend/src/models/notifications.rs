@@ -0,0 +1,34 @@
+use uuid::Uuid;
+use time::OffsetDateTime;
+use serde::{Serialize, Deserialize};
+
+/// An inbox entry. `kind` is `mention` for now: someone mentioned the user
+/// directly, through one of their roles or with `@everyone`.
+#[derive(Serialize, Clone)]
+pub struct Notification {
+    pub id: Uuid,
+    pub kind: String,
+    pub room_id: Uuid,
+    pub room_name: String,
+    pub message_id: Uuid,
+    /// Set when the mention is in a reply in `message_id`'s thread.
+    pub thread_reply_id: Option<Uuid>,
+    pub actor_id: Uuid,
+    pub actor_username: String,
+    /// First 100 characters of the message or reply.
+    pub snippet: String,
+    #[serde(serialize_with = "time::serde::rfc3339::serialize")]
+    pub created_at: OffsetDateTime,
+    #[serde(serialize_with = "time::serde::rfc3339::option::serialize")]
+    pub read_at: Option<OffsetDateTime>,
+}
+
+/// Newest first; `before` is the id of the last notification already loaded.
+#[derive(Deserialize)]
+pub struct NotificationQuery {
+    pub before: Option<Uuid>,
+    pub limit: Option<i64>,
+    /// Only unread notifications.
+    #[serde(default)]
+    pub unread: bool,
+}
@@ -0,0 +1,21 @@
+use serde::Serialize;
+use uuid::Uuid;
+use time::OffsetDateTime;
+
+/// Upper bound on pins per room.
+pub const MAX_PINS_PER_ROOM: i64 = 50;
+
+#[derive(Serialize)]
+pub struct PinnedMessage {
+    pub id: Uuid,
+    pub room_id: Uuid,
+    pub author_id: Uuid,
+    pub content: String,
+    #[serde(serialize_with = "time::serde::rfc3339::serialize")]
+    pub created_at: OffsetDateTime,
+    #[serde(serialize_with = "time::serde::rfc3339::option::serialize")]
+    pub edited_at: Option<OffsetDateTime>,
+    pub pinned_by: Option<Uuid>,
+    #[serde(serialize_with = "time::serde::rfc3339::serialize")]
+    pub pinned_at: OffsetDateTime,
+}
@@ -0,0 +1,38 @@
+use serde::{Deserialize, Serialize};
+use uuid::Uuid;
+use time::OffsetDateTime;
+
+/// Most distinct emoji a single message can carry.
+pub const MAX_DISTINCT_REACTIONS: i64 = 20;
+
+/// One emoji on a message, in the order it was first used.
+#[derive(Serialize, Clone)]
+pub struct ReactionSummary {
+    pub emoji: String,
+    pub count: i64,
+    /// Whether the caller is among those who reacted.
+    pub me: bool,
+}
+
+/// A history entry with its aggregated reactions.
+#[derive(Serialize)]
+pub struct WithReactions<T> {
+    #[serde(flatten)]
+    pub message: T,
+    pub reactions: Vec<ReactionSummary>,
+}
+
+#[derive(Serialize)]
+pub struct Reactor {
+    pub user_id: Uuid,
+    pub username: String,
+    #[serde(serialize_with = "time::serde::rfc3339::serialize")]
+    pub created_at: OffsetDateTime,
+}
+
+/// `after` is the `user_id` of the last entry of the previous page.
+#[derive(Deserialize)]
+pub struct ReactorQuery {
+    pub after: Option<Uuid>,
+    pub limit: Option<i64>,
+}
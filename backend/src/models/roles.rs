@@ -0,0 +1,38 @@
+use serde::{Deserialize, Serialize};
+use uuid::Uuid;
+use time::OffsetDateTime;
+
+/// Permission bits stored in `room_roles.permissions`. Room owners implicitly hold all of them.
+pub mod permissions {
+    pub const MANAGE_ROOM: i64 = 1 << 0;
+    pub const MANAGE_ROLES: i64 = 1 << 1;
+    pub const VIEW_AUDIT_LOG: i64 = 1 << 2;
+    /// Moderator powers over other members' messages; also exempts from slowmode.
+    pub const MANAGE_MESSAGES: i64 = 1 << 3;
+    pub const PIN_MESSAGES: i64 = 1 << 4;
+    /// Schedule events and edit anyone's; creators can always edit their own.
+    pub const MANAGE_EVENTS: i64 = 1 << 5;
+
+    pub const ALL: i64 =
+        MANAGE_ROOM | MANAGE_ROLES | VIEW_AUDIT_LOG | MANAGE_MESSAGES | PIN_MESSAGES | MANAGE_EVENTS;
+}
+
+#[derive(Serialize, Deserialize)]
+pub struct Role {
+    pub id: Uuid,
+    pub room_id: Uuid,
+    pub name: String,
+    pub permissions: i64,
+    pub position: i32,
+    #[serde(serialize_with = "time::serde::rfc3339::serialize")]
+    pub created_at: OffsetDateTime,
+}
+
+#[derive(Deserialize)]
+pub struct CreateRoleInput {
+    pub name: String,
+    #[serde(default)]
+    pub permissions: i64,
+    #[serde(default)]
+    pub position: i32,
+}
@@ -1,16 +1,41 @@
 use uuid::Uuid;
 use serde::{Serialize, Deserialize};
 use time::OffsetDateTime;
+use sqlx::types::Json;
+use crate::models::messages::ReplyPreview;
+use crate::models::attachments::Attachment;
+use crate::models::embeds::Embed;
+use crate::models::markdown::Block;
 
 #[derive(Deserialize)]
 pub struct CreateRoomInput {
     pub name: String,
+    /// Defaults to `chat`; fixed once the room exists.
+    pub kind: Option<RoomKind>,
+}
+
+#[derive(Serialize, Deserialize, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum RoomKind {
+    Chat,
+    /// Top-level messages are titled, tagged posts; see `models::forum`.
+    Forum,
+}
+
+impl RoomKind {
+    pub fn as_str(self) -> &'static str {
+        match self {
+            RoomKind::Chat => "chat",
+            RoomKind::Forum => "forum",
+        }
+    }
 }
 
 #[derive(Serialize)]
 pub struct Room {
     pub id: Uuid,
     pub name: String,
+    pub kind: String,
     pub owner_id: Uuid,
     pub created_at: OffsetDateTime,
 }
@@ -18,9 +43,16 @@ pub struct Room {
 #[derive(Deserialize)]
 pub struct RoomMessageInput {
     pub content: String,
+    /// One of the room's channels; unset posts to the room as a whole.
+    pub channel_id: Option<Uuid>,
+    /// A message in this room that this one answers.
+    pub reply_to: Option<Uuid>,
+    /// Pending uploads from `POST /rooms/{id}/attachments` to send with it.
+    #[serde(default)]
+    pub attachment_ids: Vec<Uuid>,
 }
 
-#[derive(Serialize , Deserialize)]
+#[derive(Serialize , Deserialize, Clone)]
 pub struct RoomMessage {
     pub id: Uuid,
     pub room_id: Uuid,
@@ -28,19 +60,188 @@ pub struct RoomMessage {
     pub content: String,
     #[serde(serialize_with = "time::serde::rfc3339::serialize")]
     pub created_at: OffsetDateTime,
+    #[serde(with = "time::serde::rfc3339::option")]
     pub edited_at: Option<OffsetDateTime>,
+    pub thread_reply_count: i32,
+    #[serde(with = "time::serde::rfc3339::option")]
+    pub thread_last_reply_at: Option<OffsetDateTime>,
+    /// `user`, or `system` for notices the server posts (e.g. event reminders).
+    pub kind: String,
+    /// Set on tombstones: the message was deleted and its content wiped.
+    #[serde(with = "time::serde::rfc3339::option")]
+    pub deleted_at: Option<OffsetDateTime>,
+    /// The channel it was posted to; unset for the room as a whole.
+    pub channel_id: Option<Uuid>,
+    pub reply_to: Option<Uuid>,
+    /// Unset when the quoted message is outside the reader's history.
+    pub reply_preview: Option<Json<ReplyPreview>>,
+    /// Members mentioned with `@username`.
+    pub mentioned_user_ids: Vec<Uuid>,
+    /// Room roles mentioned with `@role`.
+    pub mentioned_role_ids: Vec<Uuid>,
+    pub mentions_everyone: bool,
+    pub attachments: Json<Vec<Attachment>>,
+    /// Link previews, filled in shortly after the message is sent.
+    pub embeds: Json<Vec<Embed>>,
+    /// `content` parsed as markdown. Unset only for old messages the
+    /// background parser hasn't reached yet.
+    pub content_ast: Option<Json<Vec<Block>>>,
 }
 
-#[derive(Serialize , Deserialize)]
+impl RoomMessage {
+    /// Whether the quoted message predates `cutoff`. Readers whose history
+    /// starts there get `reply_to` without a preview, as in history.
+    pub fn quotes_before(&self, cutoff: OffsetDateTime) -> bool {
+        self.reply_preview.as_ref().is_some_and(|preview| preview.created_at < cutoff)
+    }
+}
+
+#[derive(Serialize , Deserialize, Clone)]
 pub struct RoomInfo {
     pub id: Uuid,
     pub name: String,
+    /// `chat` or `forum`
+    pub kind: String,
+    pub description: Option<String>,
+    pub icon_url: Option<String>,
+    pub welcome_message: Option<String>,
+    pub slowmode_seconds: i32,
+    /// `full`, `since_joined` or `last_days`
+    pub history_visibility: String,
+    pub history_days: Option<i32>,
     pub owner_id: Uuid,
     pub created_at: OffsetDateTime,
 }
 
+/// Partial update; omitted fields are left untouched, empty strings clear
+/// `description` / `icon_url` / `welcome_message`.
+#[derive(Deserialize)]
+pub struct UpdateRoomInput {
+    pub name: Option<String>,
+    pub description: Option<String>,
+    pub icon_url: Option<String>,
+    pub welcome_message: Option<String>,
+    /// Seconds a member must wait between messages; `0` disables slowmode.
+    pub slowmode_seconds: Option<i32>,
+    pub history_visibility: Option<HistoryVisibility>,
+    /// Window for `last_days`, in days.
+    pub history_days: Option<i32>,
+}
+
+pub const MAX_HISTORY_DAYS: i32 = 3650;
+
+/// How much of the room's past a member can read.
+#[derive(Serialize, Deserialize, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum HistoryVisibility {
+    /// Every message ever sent.
+    Full,
+    /// Only messages sent after the member joined.
+    SinceJoined,
+    /// Only messages from the last `history_days` days.
+    LastDays,
+}
+
+impl HistoryVisibility {
+    pub fn as_str(self) -> &'static str {
+        match self {
+            HistoryVisibility::Full => "full",
+            HistoryVisibility::SinceJoined => "since_joined",
+            HistoryVisibility::LastDays => "last_days",
+        }
+    }
+}
+
+#[derive(Deserialize)]
+pub struct DeleteRoomQuery {
+    /// Must repeat the room's current name to confirm the deletion.
+    pub confirm: String,
+}
+
+#[derive(Deserialize)]
+pub struct TransferRoomInput {
+    pub user_id: Uuid,
+}
+
 #[derive(serde::Serialize)]
 pub struct Member {
     pub id: Uuid,
     pub username: String,
-}
\ No newline at end of file
+    pub nickname: Option<String>,
+    #[serde(serialize_with = "time::serde::rfc3339::serialize")]
+    pub joined_at: OffsetDateTime,
+    pub role_ids: Vec<Uuid>,
+}
+
+/// Keyset pagination over members ordered by username. `after` is the id of the
+/// last member of the previous page; `q` filters by username/nickname prefix.
+#[derive(Deserialize)]
+pub struct MemberListQuery {
+    pub after: Option<Uuid>,
+    pub limit: Option<i64>,
+    pub q: Option<String>,
+}
+
+#[derive(Deserialize)]
+pub struct UpdateMemberInput {
+    pub nickname: Option<String>,
+}
+#[derive(Serialize)]
+pub struct DeletedRoom {
+    pub id: Uuid,
+    pub name: String,
+    #[serde(serialize_with = "time::serde::rfc3339::serialize")]
+    pub deleted_at: OffsetDateTime,
+    /// When the purge job will remove the room for good.
+    #[serde(serialize_with = "time::serde::rfc3339::serialize")]
+    pub purge_at: OffsetDateTime,
+}
+
+/// Bounds the per-room unread / mention counts; clients show e.g. "99+".
+pub const UNREAD_COUNT_CAP: i64 = 100;
+
+/// Entry of `GET /api/rooms`, enough to render the sidebar without loading history.
+#[derive(Serialize)]
+pub struct RoomSummary {
+    pub id: Uuid,
+    pub name: String,
+    pub owner_id: Uuid,
+    pub icon_url: Option<String>,
+    #[serde(serialize_with = "time::serde::rfc3339::serialize")]
+    pub created_at: OffsetDateTime,
+    pub member_count: i64,
+    pub last_message: Option<LastMessage>,
+    pub unread_count: i64,
+    pub mention_count: i64,
+}
+
+#[derive(Serialize)]
+pub struct LastMessage {
+    pub id: Uuid,
+    pub author_id: Uuid,
+    pub author_username: Option<String>,
+    pub snippet: String,
+    #[serde(serialize_with = "time::serde::rfc3339::serialize")]
+    pub created_at: OffsetDateTime,
+}
+
+/// Most messages a single purge may delete.
+pub const MAX_PURGE_MESSAGES: i64 = 1000;
+
+/// Bulk delete for moderators: the newest `last` messages, optionally only
+/// `author_id`'s and only inside `since..until`. Needs `last` or `author_id`.
+#[derive(Deserialize)]
+pub struct PurgeMessagesInput {
+    pub last: Option<i64>,
+    pub author_id: Option<Uuid>,
+    #[serde(default, with = "time::serde::rfc3339::option")]
+    pub since: Option<OffsetDateTime>,
+    #[serde(default, with = "time::serde::rfc3339::option")]
+    pub until: Option<OffsetDateTime>,
+}
+
+#[derive(Deserialize)]
+pub struct MarkReadInput {
+    /// Mark everything up to and including this message; defaults to "now".
+    pub message_id: Option<Uuid>,
+}
@@ -0,0 +1,33 @@
+use serde::{Deserialize, Serialize};
+use time::OffsetDateTime;
+
+pub const MAX_RULES_LENGTH: usize = 4000;
+
+/// The room's rules as seen by the caller.
+#[derive(Serialize)]
+pub struct RoomRules {
+    pub rules: Option<String>,
+    pub version: i32,
+    #[serde(serialize_with = "time::serde::rfc3339::option::serialize")]
+    pub updated_at: Option<OffsetDateTime>,
+    pub accepted_version: Option<i32>,
+    #[serde(serialize_with = "time::serde::rfc3339::option::serialize")]
+    pub accepted_at: Option<OffsetDateTime>,
+    /// Whether the caller has to accept before sending messages.
+    pub must_accept: bool,
+}
+
+/// Replaces the rules; an empty string removes them.
+#[derive(Deserialize)]
+pub struct UpdateRulesInput {
+    pub rules: String,
+    /// Make members who accepted an earlier version accept again.
+    #[serde(default)]
+    pub require_reacceptance: bool,
+}
+
+#[derive(Deserialize)]
+pub struct AcceptRulesInput {
+    /// The version the member was shown, so a concurrent edit isn't accepted blind.
+    pub version: i32,
+}
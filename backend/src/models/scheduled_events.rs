@@ -0,0 +1,99 @@
+use serde::{Deserialize, Serialize};
+use uuid::Uuid;
+use time::OffsetDateTime;
+
+pub const MAX_EVENT_TITLE_LENGTH: usize = 200;
+pub const MAX_EVENT_DESCRIPTION_LENGTH: usize = 4000;
+pub const MAX_EVENT_LOCATION_LENGTH: usize = 200;
+
+/// A scheduled room event with its RSVP tallies and the caller's own answer.
+#[derive(Serialize, Clone)]
+pub struct ScheduledEvent {
+    pub id: Uuid,
+    pub room_id: Uuid,
+    pub creator_id: Option<Uuid>,
+    pub title: String,
+    pub description: Option<String>,
+    pub location: Option<String>,
+    #[serde(serialize_with = "time::serde::rfc3339::serialize")]
+    pub starts_at: OffsetDateTime,
+    #[serde(serialize_with = "time::serde::rfc3339::serialize")]
+    pub ends_at: OffsetDateTime,
+    #[serde(serialize_with = "time::serde::rfc3339::serialize")]
+    pub created_at: OffsetDateTime,
+    #[serde(serialize_with = "time::serde::rfc3339::serialize")]
+    pub updated_at: OffsetDateTime,
+    pub going_count: i64,
+    pub maybe_count: i64,
+    pub not_going_count: i64,
+    pub my_rsvp: Option<String>,
+}
+
+#[derive(Deserialize)]
+pub struct CreateEventInput {
+    pub title: String,
+    pub description: Option<String>,
+    pub location: Option<String>,
+    #[serde(with = "time::serde::rfc3339")]
+    pub starts_at: OffsetDateTime,
+    #[serde(with = "time::serde::rfc3339")]
+    pub ends_at: OffsetDateTime,
+}
+
+/// Partial update; empty strings clear `description` / `location`.
+#[derive(Deserialize)]
+pub struct UpdateEventInput {
+    pub title: Option<String>,
+    pub description: Option<String>,
+    pub location: Option<String>,
+    #[serde(default, with = "time::serde::rfc3339::option")]
+    pub starts_at: Option<OffsetDateTime>,
+    #[serde(default, with = "time::serde::rfc3339::option")]
+    pub ends_at: Option<OffsetDateTime>,
+}
+
+/// `from` defaults to now, so the plain list shows upcoming and ongoing events.
+#[derive(Deserialize)]
+pub struct EventListQuery {
+    #[serde(default, with = "time::serde::rfc3339::option")]
+    pub from: Option<OffsetDateTime>,
+    #[serde(default, with = "time::serde::rfc3339::option")]
+    pub to: Option<OffsetDateTime>,
+}
+
+#[derive(Serialize, Deserialize, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum RsvpStatus {
+    Going,
+    Maybe,
+    NotGoing,
+}
+
+impl RsvpStatus {
+    pub fn as_str(self) -> &'static str {
+        match self {
+            RsvpStatus::Going => "going",
+            RsvpStatus::Maybe => "maybe",
+            RsvpStatus::NotGoing => "not_going",
+        }
+    }
+}
+
+#[derive(Deserialize)]
+pub struct RsvpInput {
+    pub status: RsvpStatus,
+}
+
+#[derive(Serialize)]
+pub struct Rsvp {
+    pub user_id: Uuid,
+    pub username: String,
+    pub status: String,
+    #[serde(serialize_with = "time::serde::rfc3339::serialize")]
+    pub updated_at: OffsetDateTime,
+}
+
+#[derive(Deserialize)]
+pub struct CalendarQuery {
+    pub token: Uuid,
+}
@@ -0,0 +1,75 @@
+use serde::{Deserialize, Serialize};
+use uuid::Uuid;
+use time::OffsetDateTime;
+
+/// Everything a template carries over to a new room. Messages and members are
+/// never part of it.
+#[derive(Serialize, Deserialize)]
+pub struct RoomConfig {
+    #[serde(default = "chat_room")]
+    pub kind: String,
+    pub description: Option<String>,
+    pub icon_url: Option<String>,
+    pub welcome_message: Option<String>,
+    pub slowmode_seconds: i32,
+    // Templates saved before history visibility existed read as full history
+    #[serde(default = "full_history")]
+    pub history_visibility: String,
+    #[serde(default)]
+    pub history_days: Option<i32>,
+    #[serde(default)]
+    pub rules: Option<String>,
+    pub roles: Vec<RoleConfig>,
+    pub uncategorized: Vec<ChannelConfig>,
+    pub categories: Vec<CategoryConfig>,
+}
+
+fn chat_room() -> String {
+    "chat".into()
+}
+
+fn full_history() -> String {
+    "full".into()
+}
+
+#[derive(Serialize, Deserialize)]
+pub struct RoleConfig {
+    pub name: String,
+    pub permissions: i64,
+    pub position: i32,
+}
+
+#[derive(Serialize, Deserialize)]
+pub struct CategoryConfig {
+    pub name: String,
+    pub channels: Vec<ChannelConfig>,
+}
+
+#[derive(Serialize, Deserialize)]
+pub struct ChannelConfig {
+    pub name: String,
+    pub topic: Option<String>,
+}
+
+#[derive(Serialize)]
+pub struct RoomTemplate {
+    pub id: Uuid,
+    pub code: String,
+    pub name: String,
+    pub creator_id: Uuid,
+    pub source_room_id: Option<Uuid>,
+    pub config: sqlx::types::Json<RoomConfig>,
+    #[serde(serialize_with = "time::serde::rfc3339::serialize")]
+    pub created_at: OffsetDateTime,
+}
+
+#[derive(Deserialize)]
+pub struct CreateTemplateInput {
+    pub name: String,
+}
+
+/// Name of the room created from a template or clone.
+#[derive(Deserialize)]
+pub struct NewRoomInput {
+    pub name: String,
+}
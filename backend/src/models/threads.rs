@@ -0,0 +1,35 @@
+use serde::{Deserialize, Serialize};
+use sqlx::types::Json;
+use uuid::Uuid;
+use time::OffsetDateTime;
+use crate::models::markdown::Block;
+
+#[derive(Serialize, Clone)]
+pub struct ThreadReply {
+    pub id: Uuid,
+    pub parent_id: Uuid,
+    pub room_id: Uuid,
+    pub author_id: Uuid,
+    pub content: String,
+    #[serde(serialize_with = "time::serde::rfc3339::serialize")]
+    pub created_at: OffsetDateTime,
+    #[serde(serialize_with = "time::serde::rfc3339::option::serialize")]
+    pub edited_at: Option<OffsetDateTime>,
+    /// `content` parsed as markdown. Unset only for old replies the
+    /// background parser hasn't reached yet.
+    pub content_ast: Option<Json<Vec<Block>>>,
+}
+
+#[derive(Deserialize)]
+pub struct ThreadReplyInput {
+    pub content: String,
+}
+
+/// Replies come oldest first. Pass the last reply's id as `after` for the next
+/// page, or the first reply's id as `before` for the previous one.
+#[derive(Deserialize)]
+pub struct ThreadQuery {
+    pub before: Option<Uuid>,
+    pub after: Option<Uuid>,
+    pub limit: Option<i64>,
+}
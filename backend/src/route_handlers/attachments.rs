@@ -0,0 +1,383 @@
+use axum::{
+    body::Bytes,
+    extract::{Extension, Multipart, Path, State},
+    http::{header, HeaderValue, StatusCode},
+    response::{IntoResponse, Redirect, Response},
+    Json,
+};
+use std::time::Duration;
+use uuid::Uuid;
+use crate::auth::membership::RoomMember;
+use crate::auth::middleware::CurrentUser;
+use crate::images;
+use crate::models::attachments::{Attachment, MAX_ATTACHMENTS_PER_MESSAGE};
+
+use crate::state::AppState;
+use std::sync::Arc;
+
+/// How long a presigned download link stays valid.
+const DOWNLOAD_URL_TTL: Duration = Duration::from_secs(5 * 60);
+
+struct Upload {
+    filename: String,
+    content_type: String,
+    data: Bytes,
+    /// EXIF orientation of an image, read before its metadata was stripped.
+    /// `None` for files that aren't processed.
+    orientation: Option<u8>,
+}
+
+/// Where a pending upload may be sent.
+enum UploadScope {
+    Room(Uuid),
+    Dm(Uuid),
+}
+
+/// Keeps the last path segment, drops control characters and caps the length.
+fn clean_filename(name: &str) -> String {
+    let name = name.rsplit(['/', '\\']).next().unwrap_or_default();
+    let name: String = name.chars().filter(|c| !c.is_control()).take(255).collect();
+    let name = name.trim();
+    if name.is_empty() || name == "." || name == ".." {
+        "file".into()
+    } else {
+        name.to_string()
+    }
+}
+
+/// The type comes from the file's magic bytes. Unrecognised UTF-8 is served
+/// as plain text and anything else as an opaque download, whatever the
+/// client claimed.
+fn sniff_content_type(data: &[u8]) -> String {
+    match infer::get(data) {
+        Some(kind) => kind.mime_type().to_string(),
+        None if std::str::from_utf8(data).is_ok() => "text/plain; charset=utf-8".into(),
+        None => "application/octet-stream".into(),
+    }
+}
+
+/// Reads every file part of the form, enforcing the per-file size limit while
+/// streaming so an oversized file is never buffered whole.
+async fn read_uploads(state: &AppState, mut multipart: Multipart) -> Result<Vec<Upload>, (StatusCode, String)> {
+    let max_bytes = state.attachment_max_bytes;
+    let mut uploads = Vec::new();
+
+    while let Some(mut field) = multipart
+        .next_field()
+        .await
+        .map_err(|e| (StatusCode::BAD_REQUEST, e.to_string()))?
+    {
+        let Some(filename) = field.file_name().map(clean_filename) else {
+            continue;
+        };
+        if uploads.len() == MAX_ATTACHMENTS_PER_MESSAGE {
+            return Err((StatusCode::BAD_REQUEST, "At most 10 files per upload".into()));
+        }
+
+        let mut data = Vec::new();
+        while let Some(chunk) = field
+            .chunk()
+            .await
+            .map_err(|e| (StatusCode::BAD_REQUEST, e.to_string()))?
+        {
+            if data.len() + chunk.len() > max_bytes {
+                return Err((
+                    StatusCode::PAYLOAD_TOO_LARGE,
+                    format!("Files can be at most {} bytes", max_bytes),
+                ));
+            }
+            data.extend_from_slice(&chunk);
+        }
+        if data.is_empty() {
+            return Err((StatusCode::BAD_REQUEST, format!("{filename} is empty")));
+        }
+
+        let content_type = sniff_content_type(&data);
+        let mut orientation = None;
+        if images::is_processable(&content_type) {
+            // Camera metadata (GPS position included) never reaches storage
+            let (stripped, exif_orientation) = images::strip_metadata(&content_type, &data)
+                .map_err(|_| (StatusCode::BAD_REQUEST, format!("{filename} isn't a valid image")))?;
+            data = stripped;
+            orientation = Some(exif_orientation);
+        }
+
+        uploads.push(Upload {
+            content_type,
+            filename,
+            data: Bytes::from(data),
+            orientation,
+        });
+    }
+
+    if uploads.is_empty() {
+        return Err((StatusCode::BAD_REQUEST, "No files in the upload".into()));
+    }
+    Ok(uploads)
+}
+
+/// Writes each blob, then its row. A blob whose row can't be written is
+/// removed again rather than left behind. Images are queued for the
+/// processing worker, so the response doesn't wait on decoding.
+async fn store_uploads(
+    state: &AppState,
+    uploader_id: Uuid,
+    scope: UploadScope,
+    uploads: Vec<Upload>,
+) -> Result<Vec<Attachment>, (StatusCode, String)> {
+    let (room_id, dm_peer_id) = match scope {
+        UploadScope::Room(room_id) => (Some(room_id), None),
+        UploadScope::Dm(peer_id) => (None, Some(peer_id)),
+    };
+    let mut attachments = Vec::with_capacity(uploads.len());
+
+    for upload in uploads {
+        let id = Uuid::new_v4();
+        let key = format!("attachments/{id}");
+
+        state
+            .blobs
+            .put(&key, upload.data.clone(), &upload.content_type)
+            .await
+            .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))?;
+
+        let attachment = sqlx::query_scalar!(
+            r#"
+            INSERT INTO attachments
+                (id, uploader_id, room_id, dm_peer_id, storage_key, filename, content_type, size_bytes,
+                 processing, orientation)
+            VALUES ($1, $2, $3, $4, $5, $6, $7, $8, $9, $10)
+            RETURNING attachment_json(attachments) AS "attachment!: sqlx::types::Json<Attachment>"
+            "#,
+            id,
+            uploader_id,
+            room_id,
+            dm_peer_id,
+            key,
+            upload.filename,
+            upload.content_type,
+            upload.data.len() as i64,
+            if upload.orientation.is_some() { "pending" } else { "none" },
+            upload.orientation.unwrap_or(1) as i16
+        )
+        .fetch_one(&state.pool)
+        .await;
+
+        match attachment {
+            Ok(attachment) => attachments.push(attachment.0),
+            Err(e) => {
+                let _ = state.blobs.delete(&key).await;
+                return Err((StatusCode::INTERNAL_SERVER_ERROR, e.to_string()));
+            }
+        }
+    }
+
+    state.image_jobs.notify_one();
+    Ok(attachments)
+}
+
+/// Uploads files to attach to the caller's next message in the room. They
+/// stay pending until a message claims them through `attachment_ids`.
+pub async fn upload_room_attachments(
+    member: RoomMember,
+    State(state): State<Arc<AppState>>,
+    multipart: Multipart,
+) -> Result<Json<Vec<Attachment>>, (StatusCode, String)> {
+    let uploads = read_uploads(&state, multipart).await?;
+    let attachments = store_uploads(&state, member.user_id, UploadScope::Room(member.room_id), uploads).await?;
+
+    Ok(Json(attachments))
+}
+
+pub async fn upload_dm_attachments(
+    Path(other_user_id): Path<Uuid>,
+    Extension(CurrentUser { id: my_id, .. }): Extension<CurrentUser>,
+    State(state): State<Arc<AppState>>,
+    multipart: Multipart,
+) -> Result<Json<Vec<Attachment>>, (StatusCode, String)> {
+    if my_id == other_user_id {
+        return Err((StatusCode::BAD_REQUEST, "Cannot message yourself".into()));
+    }
+
+    let exists = sqlx::query_scalar!(
+        r#"SELECT EXISTS (SELECT 1 FROM users WHERE id = $1) AS "exists!""#,
+        other_user_id
+    )
+    .fetch_one(&state.pool)
+    .await
+    .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))?;
+    if !exists {
+        return Err((StatusCode::NOT_FOUND, "User not found".into()));
+    }
+
+    let uploads = read_uploads(&state, multipart).await?;
+    let attachments = store_uploads(&state, my_id, UploadScope::Dm(other_user_id), uploads).await?;
+
+    Ok(Json(attachments))
+}
+
+struct StoredFile {
+    storage_key: String,
+    filename: String,
+    content_type: String,
+}
+
+/// Looks up an attachment the user may read: room members who can see the
+/// message it is on, either side of a DM, or the uploader while it's still
+/// pending.
+async fn readable_attachment(
+    state: &AppState,
+    attachment_id: Uuid,
+    user_id: Uuid,
+) -> Result<StoredFile, (StatusCode, String)> {
+    sqlx::query_as!(
+        StoredFile,
+        r#"
+        SELECT a.storage_key, a.filename, a.content_type
+        FROM attachments a
+        WHERE a.id = $1 AND a.deleted_at IS NULL
+          AND (
+            (a.message_id IS NULL AND a.direct_message_id IS NULL AND a.uploader_id = $2)
+            OR EXISTS (
+                SELECT 1 FROM messages m
+                JOIN rooms r ON r.id = m.room_id
+                JOIN room_members rm ON rm.room_id = m.room_id AND rm.user_id = $2
+                WHERE m.id = a.message_id AND m.deleted_at IS NULL AND r.deleted_at IS NULL
+                  AND m.created_at >= COALESCE(
+                        room_history_cutoff(r.history_visibility, r.history_days, rm.joined_at),
+                        '-infinity'
+                      )
+            )
+            OR EXISTS (
+                SELECT 1 FROM direct_messages dm
+                WHERE dm.id = a.direct_message_id AND dm.deleted_at IS NULL
+                  AND $2 IN (dm.sender_id, dm.receiver_id)
+            )
+          )
+        "#,
+        attachment_id,
+        user_id
+    )
+    .fetch_optional(&state.pool)
+    .await
+    .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))?
+    .ok_or((StatusCode::NOT_FOUND, "Attachment not found".to_string()))
+}
+
+/// Serves an attachment to whoever may read the message it is on. Stores
+/// that can sign URLs get a redirect; otherwise the bytes are streamed from
+/// here.
+pub async fn download_attachment(
+    Path(attachment_id): Path<Uuid>,
+    Extension(CurrentUser { id: user_id, .. }): Extension<CurrentUser>,
+    State(state): State<Arc<AppState>>,
+) -> Result<Response, (StatusCode, String)> {
+    let attachment = readable_attachment(&state, attachment_id, user_id).await?;
+
+    // Images render inline; everything else downloads
+    let disposition = if attachment.content_type.starts_with("image/") { "inline" } else { "attachment" };
+    let disposition = format!(
+        "{disposition}; filename*=UTF-8''{}",
+        percent_encode(&attachment.filename)
+    );
+
+    serve_blob(&state, &attachment.storage_key, &attachment.content_type, &disposition).await
+}
+
+/// Serves one of an image's WebP thumbnails, with the same access rules as
+/// the original.
+pub async fn download_thumbnail(
+    Path((attachment_id, size)): Path<(Uuid, i32)>,
+    Extension(CurrentUser { id: user_id, .. }): Extension<CurrentUser>,
+    State(state): State<Arc<AppState>>,
+) -> Result<Response, (StatusCode, String)> {
+    let attachment = readable_attachment(&state, attachment_id, user_id).await?;
+
+    let storage_key = sqlx::query_scalar!(
+        "SELECT storage_key FROM attachment_thumbnails WHERE attachment_id = $1 AND size = $2",
+        attachment_id,
+        size
+    )
+    .fetch_optional(&state.pool)
+    .await
+    .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))?
+    .ok_or((StatusCode::NOT_FOUND, "Thumbnail not found".to_string()))?;
+
+    let stem = attachment.filename.rsplit_once('.').map_or(attachment.filename.as_str(), |(stem, _)| stem);
+    let disposition = format!("inline; filename*=UTF-8''{}", percent_encode(&format!("{stem}_{size}.webp")));
+
+    serve_blob(&state, &storage_key, "image/webp", &disposition).await
+}
+
+async fn serve_blob(
+    state: &AppState,
+    key: &str,
+    content_type: &str,
+    disposition: &str,
+) -> Result<Response, (StatusCode, String)> {
+    let presigned = state
+        .blobs
+        .presigned_get(key, DOWNLOAD_URL_TTL, disposition)
+        .await
+        .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))?;
+    if let Some(url) = presigned {
+        return Ok(Redirect::temporary(&url).into_response());
+    }
+
+    let data = state
+        .blobs
+        .get(key)
+        .await
+        .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))?;
+
+    let headers = [
+        (header::CONTENT_TYPE, HeaderValue::from_str(content_type)),
+        (header::CONTENT_DISPOSITION, HeaderValue::from_str(disposition)),
+        (header::X_CONTENT_TYPE_OPTIONS, Ok(HeaderValue::from_static("nosniff"))),
+        (header::CACHE_CONTROL, Ok(HeaderValue::from_static("private, max-age=300"))),
+    ];
+    let mut response = data.into_response();
+    for (name, value) in headers {
+        let value = value.map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))?;
+        response.headers_mut().insert(name, value);
+    }
+
+    Ok(response)
+}
+
+/// RFC 5987 encoding for the `filename*` parameter.
+fn percent_encode(value: &str) -> String {
+    let mut out = String::with_capacity(value.len());
+    for byte in value.bytes() {
+        if byte.is_ascii_alphanumeric() || b"!#$&+-.^_`|~".contains(&byte) {
+            out.push(byte as char);
+        } else {
+            out.push_str(&format!("%{byte:02X}"));
+        }
+    }
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn filenames_lose_paths_and_control_characters() {
+        assert_eq!(clean_filename("../../etc/passwd"), "passwd");
+        assert_eq!(clean_filename("C:\\Users\\me\\photo.jpg"), "photo.jpg");
+        assert_eq!(clean_filename("re\u{0}port\n.pdf"), "report.pdf");
+        assert_eq!(clean_filename("  notes.txt "), "notes.txt");
+        assert_eq!(clean_filename(""), "file");
+        assert_eq!(clean_filename("uploads/.."), "file");
+        assert_eq!(clean_filename(&"a".repeat(300)).len(), 255);
+    }
+
+    #[test]
+    fn content_type_comes_from_the_bytes() {
+        assert_eq!(sniff_content_type(b"\x89PNG\r\n\x1a\n\0\0\0\rIHDR"), "image/png");
+        assert_eq!(sniff_content_type(&[0xFF, 0xD8, 0xFF, 0xE0]), "image/jpeg");
+        assert_eq!(sniff_content_type("plain text, ünïcode".as_bytes()), "text/plain; charset=utf-8");
+        assert_eq!(sniff_content_type(&[0x00, 0x9F, 0x92, 0x96]), "application/octet-stream");
+    }
+}
@@ -0,0 +1,47 @@
+use axum::{
+    extract::{Query, State},
+    Json,
+    http::StatusCode,
+};
+use crate::auth::membership::RoomMember;
+use crate::models::audit::{AuditLogEntry, AuditLogQuery};
+use crate::models::roles::permissions;
+
+use crate::state::AppState;
+use std::sync::Arc;
+
+pub async fn get_audit_log(
+    member: RoomMember,
+    Query(query): Query<AuditLogQuery>,
+    State(state): State<Arc<AppState>>,
+) -> Result<Json<Vec<AuditLogEntry>>, (StatusCode, String)> {
+    member.require(permissions::VIEW_AUDIT_LOG)?;
+
+    let limit = query.limit.unwrap_or(50).clamp(1, 200);
+
+    let entries = sqlx::query_as!(
+        AuditLogEntry,
+        r#"
+        SELECT id, room_id, actor_id, action, target_id, before, after, created_at
+        FROM room_audit_log
+        WHERE room_id = $1
+          AND ($2::TEXT IS NULL OR action = $2)
+          AND ($3::UUID IS NULL OR actor_id = $3)
+          AND ($4::UUID IS NULL OR target_id = $4)
+          AND ($5::BIGINT IS NULL OR id < $5)
+        ORDER BY id DESC
+        LIMIT $6
+        "#,
+        member.room_id,
+        query.action,
+        query.actor_id,
+        query.target_id,
+        query.before,
+        limit
+    )
+    .fetch_all(&state.pool)
+    .await
+    .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))?;
+
+    Ok(Json(entries))
+}
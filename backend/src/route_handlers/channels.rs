@@ -0,0 +1,431 @@
+use axum::{
+    extract::{Path, State},
+    Json,
+    http::StatusCode,
+};
+use std::collections::HashSet;
+use uuid::Uuid;
+use serde_json::json;
+use sqlx::PgPool;
+use crate::audit::{self, AuditAction};
+use crate::auth::membership::RoomMember;
+use crate::models::channels::{
+    Category, Channel, CreateCategoryInput, CreateChannelInput, LayoutInput, RoomLayout,
+    UpdateChannelInput,
+};
+use crate::models::events::RoomEvent;
+use crate::models::roles::permissions;
+
+use crate::state::AppState;
+use std::sync::Arc;
+
+/// Builds the room's sidebar tree, ordered by position.
+pub async fn load_layout(pool: &PgPool, room_id: Uuid) -> Result<RoomLayout, sqlx::Error> {
+    let categories = sqlx::query!(
+        r#"
+        SELECT id, name, position
+        FROM room_categories
+        WHERE room_id = $1
+        ORDER BY position, created_at
+        "#,
+        room_id
+    )
+    .fetch_all(pool)
+    .await?;
+
+    let channels = sqlx::query_as!(
+        Channel,
+        r#"
+        SELECT id, category_id, name, topic, position
+        FROM room_channels
+        WHERE room_id = $1
+        ORDER BY position, created_at
+        "#,
+        room_id
+    )
+    .fetch_all(pool)
+    .await?;
+
+    let uncategorized = channels.iter().filter(|c| c.category_id.is_none()).cloned().collect();
+    let categories = categories
+        .into_iter()
+        .map(|cat| Category {
+            channels: channels.iter().filter(|c| c.category_id == Some(cat.id)).cloned().collect(),
+            id: cat.id,
+            name: cat.name,
+            position: cat.position,
+        })
+        .collect();
+
+    Ok(RoomLayout { uncategorized, categories })
+}
+
+async fn broadcast_layout(state: &AppState, room_id: Uuid) -> Result<RoomLayout, (StatusCode, String)> {
+    let layout = load_layout(&state.pool, room_id)
+        .await
+        .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))?;
+
+    state.broadcast(room_id, &RoomEvent::LayoutUpdate { layout: layout.clone() }).await;
+
+    Ok(layout)
+}
+
+pub fn clean_name(name: &str) -> Result<String, (StatusCode, String)> {
+    let name = name.trim();
+    if name.is_empty() || name.chars().count() > 100 {
+        return Err((StatusCode::BAD_REQUEST, "Name must be 1-100 characters".into()));
+    }
+    Ok(name.to_string())
+}
+
+pub async fn create_category(
+    member: RoomMember,
+    State(state): State<Arc<AppState>>,
+    Json(payload): Json<CreateCategoryInput>,
+) -> Result<Json<RoomLayout>, (StatusCode, String)> {
+    member.require(permissions::MANAGE_ROOM)?;
+    let name = clean_name(&payload.name)?;
+
+    let id = sqlx::query_scalar!(
+        r#"
+        INSERT INTO room_categories (room_id, name, position)
+        SELECT $1, $2, COALESCE(MAX(position) + 1, 0)
+        FROM room_categories WHERE room_id = $1
+        RETURNING id
+        "#,
+        member.room_id,
+        name
+    )
+    .fetch_one(&state.pool)
+    .await
+    .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))?;
+
+    audit::record(
+        &state.pool,
+        member.room_id,
+        member.user_id,
+        AuditAction::CategoryCreate,
+        Some(id),
+        None,
+        Some(json!({ "name": name })),
+    )
+    .await;
+
+    Ok(Json(broadcast_layout(&state, member.room_id).await?))
+}
+
+pub async fn update_category(
+    member: RoomMember,
+    Path((_, category_id)): Path<(Uuid, Uuid)>,
+    State(state): State<Arc<AppState>>,
+    Json(payload): Json<CreateCategoryInput>,
+) -> Result<Json<RoomLayout>, (StatusCode, String)> {
+    member.require(permissions::MANAGE_ROOM)?;
+    let name = clean_name(&payload.name)?;
+
+    let previous = sqlx::query_scalar!(
+        r#"
+        WITH old AS (
+            SELECT name FROM room_categories
+            WHERE id = $1 AND room_id = $2
+        )
+        UPDATE room_categories
+        SET name = $3
+        WHERE id = $1 AND room_id = $2
+        RETURNING (SELECT name FROM old) AS "name!"
+        "#,
+        category_id,
+        member.room_id,
+        name
+    )
+    .fetch_optional(&state.pool)
+    .await
+    .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))?
+    .ok_or((StatusCode::NOT_FOUND, "Category not found".to_string()))?;
+
+    audit::record(
+        &state.pool,
+        member.room_id,
+        member.user_id,
+        AuditAction::CategoryUpdate,
+        Some(category_id),
+        Some(json!({ "name": previous })),
+        Some(json!({ "name": name })),
+    )
+    .await;
+
+    Ok(Json(broadcast_layout(&state, member.room_id).await?))
+}
+
+pub async fn delete_category(
+    member: RoomMember,
+    Path((_, category_id)): Path<(Uuid, Uuid)>,
+    State(state): State<Arc<AppState>>,
+) -> Result<Json<RoomLayout>, (StatusCode, String)> {
+    member.require(permissions::MANAGE_ROOM)?;
+
+    // Its channels fall back to uncategorized (ON DELETE SET NULL)
+    let name = sqlx::query_scalar!(
+        r#"
+        DELETE FROM room_categories
+        WHERE id = $1 AND room_id = $2
+        RETURNING name
+        "#,
+        category_id,
+        member.room_id
+    )
+    .fetch_optional(&state.pool)
+    .await
+    .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))?
+    .ok_or((StatusCode::NOT_FOUND, "Category not found".to_string()))?;
+
+    audit::record(
+        &state.pool,
+        member.room_id,
+        member.user_id,
+        AuditAction::CategoryDelete,
+        Some(category_id),
+        Some(json!({ "name": name })),
+        None,
+    )
+    .await;
+
+    Ok(Json(broadcast_layout(&state, member.room_id).await?))
+}
+
+pub async fn create_channel(
+    member: RoomMember,
+    State(state): State<Arc<AppState>>,
+    Json(payload): Json<CreateChannelInput>,
+) -> Result<Json<RoomLayout>, (StatusCode, String)> {
+    member.require(permissions::MANAGE_ROOM)?;
+    let name = clean_name(&payload.name)?;
+    let topic = payload.topic.filter(|t| !t.trim().is_empty());
+
+    // New channels go to the end of their category
+    let id = sqlx::query_scalar!(
+        r#"
+        INSERT INTO room_channels (room_id, category_id, name, topic, position)
+        SELECT $1, $2, $3, $4, COALESCE(MAX(position) + 1, 0)
+        FROM room_channels
+        WHERE room_id = $1 AND category_id IS NOT DISTINCT FROM $2
+        HAVING $2::UUID IS NULL
+            OR EXISTS (SELECT 1 FROM room_categories WHERE id = $2 AND room_id = $1)
+        RETURNING id
+        "#,
+        member.room_id,
+        payload.category_id,
+        name,
+        topic
+    )
+    .fetch_optional(&state.pool)
+    .await
+    .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))?
+    .ok_or((StatusCode::BAD_REQUEST, "Category not found".to_string()))?;
+
+    audit::record(
+        &state.pool,
+        member.room_id,
+        member.user_id,
+        AuditAction::ChannelCreate,
+        Some(id),
+        None,
+        Some(json!({ "name": name, "topic": topic, "category_id": payload.category_id })),
+    )
+    .await;
+
+    Ok(Json(broadcast_layout(&state, member.room_id).await?))
+}
+
+pub async fn update_channel(
+    member: RoomMember,
+    Path((_, channel_id)): Path<(Uuid, Uuid)>,
+    State(state): State<Arc<AppState>>,
+    Json(payload): Json<UpdateChannelInput>,
+) -> Result<Json<RoomLayout>, (StatusCode, String)> {
+    member.require(permissions::MANAGE_ROOM)?;
+    let name = payload.name.as_deref().map(clean_name).transpose()?;
+
+    let before = sqlx::query!(
+        r#"
+        SELECT name, topic FROM room_channels
+        WHERE id = $1 AND room_id = $2
+        "#,
+        channel_id,
+        member.room_id
+    )
+    .fetch_optional(&state.pool)
+    .await
+    .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))?
+    .ok_or((StatusCode::NOT_FOUND, "Channel not found".to_string()))?;
+
+    let after = sqlx::query!(
+        r#"
+        UPDATE room_channels
+        SET name = COALESCE($3, name),
+            topic = CASE WHEN $4::TEXT IS NULL THEN topic ELSE NULLIF($4, '') END
+        WHERE id = $1 AND room_id = $2
+        RETURNING name, topic
+        "#,
+        channel_id,
+        member.room_id,
+        name,
+        payload.topic
+    )
+    .fetch_one(&state.pool)
+    .await
+    .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))?;
+
+    audit::record(
+        &state.pool,
+        member.room_id,
+        member.user_id,
+        AuditAction::ChannelUpdate,
+        Some(channel_id),
+        Some(json!({ "name": before.name, "topic": before.topic })),
+        Some(json!({ "name": after.name, "topic": after.topic })),
+    )
+    .await;
+
+    Ok(Json(broadcast_layout(&state, member.room_id).await?))
+}
+
+pub async fn delete_channel(
+    member: RoomMember,
+    Path((_, channel_id)): Path<(Uuid, Uuid)>,
+    State(state): State<Arc<AppState>>,
+) -> Result<Json<RoomLayout>, (StatusCode, String)> {
+    member.require(permissions::MANAGE_ROOM)?;
+
+    let name = sqlx::query_scalar!(
+        r#"
+        DELETE FROM room_channels
+        WHERE id = $1 AND room_id = $2
+        RETURNING name
+        "#,
+        channel_id,
+        member.room_id
+    )
+    .fetch_optional(&state.pool)
+    .await
+    .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))?
+    .ok_or((StatusCode::NOT_FOUND, "Channel not found".to_string()))?;
+
+    audit::record(
+        &state.pool,
+        member.room_id,
+        member.user_id,
+        AuditAction::ChannelDelete,
+        Some(channel_id),
+        Some(json!({ "name": name })),
+        None,
+    )
+    .await;
+
+    Ok(Json(broadcast_layout(&state, member.room_id).await?))
+}
+
+/// Replaces the whole ordering in one transaction so clients never observe a
+/// half-applied reorder.
+pub async fn update_layout(
+    member: RoomMember,
+    State(state): State<Arc<AppState>>,
+    Json(payload): Json<LayoutInput>,
+) -> Result<Json<RoomLayout>, (StatusCode, String)> {
+    member.require(permissions::MANAGE_ROOM)?;
+
+    let mut tx = state
+        .pool
+        .begin()
+        .await
+        .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))?;
+
+    // Lock the current structure so concurrent edits can't slip in between
+    let existing_categories: HashSet<Uuid> = sqlx::query_scalar!(
+        "SELECT id FROM room_categories WHERE room_id = $1 FOR UPDATE",
+        member.room_id
+    )
+    .fetch_all(&mut *tx)
+    .await
+    .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))?
+    .into_iter()
+    .collect();
+
+    let existing_channels: HashSet<Uuid> = sqlx::query_scalar!(
+        "SELECT id FROM room_channels WHERE room_id = $1 FOR UPDATE",
+        member.room_id
+    )
+    .fetch_all(&mut *tx)
+    .await
+    .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))?
+    .into_iter()
+    .collect();
+
+    let category_ids: Vec<Uuid> = payload.categories.iter().map(|c| c.id).collect();
+    let mut channel_ids = Vec::new();
+    let mut channel_categories = Vec::new();
+    let mut channel_positions = Vec::new();
+    for (pos, id) in payload.uncategorized.iter().enumerate() {
+        channel_ids.push(*id);
+        channel_categories.push(None);
+        channel_positions.push(pos as i32);
+    }
+    for category in &payload.categories {
+        for (pos, id) in category.channels.iter().enumerate() {
+            channel_ids.push(*id);
+            channel_categories.push(Some(category.id));
+            channel_positions.push(pos as i32);
+        }
+    }
+
+    let same_set = |ids: &[Uuid], existing: &HashSet<Uuid>| {
+        ids.len() == existing.len() && ids.iter().collect::<HashSet<_>>().len() == ids.len()
+            && ids.iter().all(|id| existing.contains(id))
+    };
+    if !same_set(&category_ids, &existing_categories) || !same_set(&channel_ids, &existing_channels) {
+        return Err((
+            StatusCode::BAD_REQUEST,
+            "Layout must list every category and channel of the room exactly once".into(),
+        ));
+    }
+
+    let category_positions: Vec<i32> = (0..category_ids.len() as i32).collect();
+    sqlx::query!(
+        r#"
+        UPDATE room_categories c
+        SET position = v.position
+        FROM UNNEST($2::UUID[], $3::INT[]) AS v(id, position)
+        WHERE c.id = v.id AND c.room_id = $1
+        "#,
+        member.room_id,
+        &category_ids,
+        &category_positions
+    )
+    .execute(&mut *tx)
+    .await
+    .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))?;
+
+    sqlx::query!(
+        r#"
+        UPDATE room_channels ch
+        SET category_id = v.category_id, position = v.position
+        FROM UNNEST($2::UUID[], $3::UUID[], $4::INT[]) AS v(id, category_id, position)
+        WHERE ch.id = v.id AND ch.room_id = $1
+        "#,
+        member.room_id,
+        &channel_ids,
+        &channel_categories as &[Option<Uuid>],
+        &channel_positions
+    )
+    .execute(&mut *tx)
+    .await
+    .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))?;
+
+    tx.commit()
+        .await
+        .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))?;
+
+    audit::record(&state.pool, member.room_id, member.user_id, AuditAction::LayoutUpdate, None, None, None).await;
+
+    Ok(Json(broadcast_layout(&state, member.room_id).await?))
+}
@@ -0,0 +1,303 @@
+use axum::{
+    extract::{Path, Query, State},
+    Json,
+    http::StatusCode,
+};
+use uuid::Uuid;
+use sqlx::PgPool;
+use crate::auth::membership::RoomMember;
+use crate::messaging::{self, SendError};
+use crate::models::events::RoomEvent;
+use crate::models::forum::{
+    CreatePostInput, ForumPost, PostListQuery, PostSort, SolvePostInput, TagCount, UpdatePostInput,
+    MAX_POST_TITLE_LENGTH, MAX_TAGS_PER_POST, MAX_TAG_LENGTH,
+};
+use crate::models::roles::permissions;
+
+use crate::state::AppState;
+use std::sync::Arc;
+
+fn clean_title(title: &str) -> Result<String, &'static str> {
+    let title = title.trim();
+    if title.is_empty() || title.chars().count() > MAX_POST_TITLE_LENGTH {
+        return Err("Post title must be 1-200 characters");
+    }
+    Ok(title.to_string())
+}
+
+/// Lowercases, strips a leading `#` and drops duplicates. Tags are short
+/// slugs of letters, digits, `-` and `_`.
+fn clean_tags(tags: Vec<String>) -> Result<Vec<String>, &'static str> {
+    let mut cleaned: Vec<String> = Vec::new();
+    for tag in tags {
+        let tag = tag.trim().trim_start_matches('#').to_lowercase();
+        let valid = !tag.is_empty()
+            && tag.chars().count() <= MAX_TAG_LENGTH
+            && tag.chars().all(|c| c.is_alphanumeric() || c == '-' || c == '_');
+        if !valid {
+            return Err("Tags must be 1-30 letters, digits, '-' or '_'");
+        }
+        if !cleaned.contains(&tag) {
+            cleaned.push(tag);
+        }
+    }
+    if cleaned.len() > MAX_TAGS_PER_POST {
+        return Err("A post can have at most 5 tags");
+    }
+    Ok(cleaned)
+}
+
+async fn load_post(
+    pool: &PgPool,
+    member: &RoomMember,
+    post_id: Uuid,
+) -> Result<ForumPost, (StatusCode, String)> {
+    sqlx::query_as!(
+        ForumPost,
+        r#"
+        SELECT id, room_id, author_id, title AS "title!", content, tags, created_at,
+               COALESCE(thread_last_reply_at, created_at) AS "last_activity_at!",
+               thread_reply_count AS reply_count, solved_at, solved_by, solution_id
+        FROM messages
+        WHERE id = $1 AND room_id = $2 AND title IS NOT NULL AND deleted_at IS NULL
+          AND ($3::TIMESTAMPTZ IS NULL OR created_at >= $3)
+        "#,
+        post_id,
+        member.room_id,
+        member.history_cutoff
+    )
+    .fetch_optional(pool)
+    .await
+    .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))?
+    .ok_or((StatusCode::NOT_FOUND, "Post not found".to_string()))
+}
+
+/// Authors manage their own posts; moderators manage everyone's.
+fn require_post_editor(member: &RoomMember, post: &ForumPost) -> Result<(), (StatusCode, String)> {
+    if post.author_id == member.user_id {
+        Ok(())
+    } else {
+        member.require(permissions::MANAGE_MESSAGES)
+    }
+}
+
+pub async fn list_posts(
+    member: RoomMember,
+    Query(query): Query<PostListQuery>,
+    State(state): State<Arc<AppState>>,
+) -> Result<Json<Vec<ForumPost>>, (StatusCode, String)> {
+    let limit = query.limit.unwrap_or(25).clamp(1, 100);
+    let by_created = query.sort == PostSort::Created;
+    let tag = query.tag.map(|t| t.trim().trim_start_matches('#').to_lowercase());
+
+    let cursor = match query.before {
+        Some(id) => Some(
+            sqlx::query!(
+                r#"
+                SELECT CASE WHEN $4 THEN created_at ELSE COALESCE(thread_last_reply_at, created_at) END
+                       AS "sort_at!", id
+                FROM messages
+                WHERE id = $1 AND room_id = $2 AND title IS NOT NULL
+                  AND ($3::TIMESTAMPTZ IS NULL OR created_at >= $3)
+                "#,
+                id,
+                member.room_id,
+                member.history_cutoff,
+                by_created
+            )
+            .fetch_optional(&state.pool)
+            .await
+            .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))?
+            .map(|row| (row.sort_at, row.id))
+            .ok_or((StatusCode::NOT_FOUND, "Post not found".to_string()))?,
+        ),
+        None => None,
+    };
+
+    let posts = sqlx::query_as!(
+        ForumPost,
+        r#"
+        SELECT id, room_id, author_id, title AS "title!", content, tags, created_at,
+               COALESCE(thread_last_reply_at, created_at) AS "last_activity_at!",
+               thread_reply_count AS reply_count, solved_at, solved_by, solution_id
+        FROM messages
+        WHERE room_id = $1 AND title IS NOT NULL AND deleted_at IS NULL
+          AND ($2::TEXT IS NULL OR tags @> ARRAY[$2::TEXT])
+          AND ($3::BOOL IS NULL OR (solved_at IS NOT NULL) = $3)
+          AND ($4::TIMESTAMPTZ IS NULL OR created_at >= $4)
+          AND ($5::TIMESTAMPTZ IS NULL OR (
+                CASE WHEN $7 THEN created_at ELSE COALESCE(thread_last_reply_at, created_at) END, id
+              ) < ($5, $6))
+        ORDER BY
+            CASE WHEN $7 THEN created_at ELSE COALESCE(thread_last_reply_at, created_at) END DESC,
+            id DESC
+        LIMIT $8
+        "#,
+        member.room_id,
+        tag,
+        query.solved,
+        member.history_cutoff,
+        cursor.map(|(sort_at, _)| sort_at),
+        cursor.map(|(_, id)| id),
+        by_created,
+        limit
+    )
+    .fetch_all(&state.pool)
+    .await
+    .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))?;
+
+    Ok(Json(posts))
+}
+
+pub async fn get_post(
+    member: RoomMember,
+    Path((_, post_id)): Path<(Uuid, Uuid)>,
+    State(state): State<Arc<AppState>>,
+) -> Result<Json<ForumPost>, (StatusCode, String)> {
+    let post = load_post(&state.pool, &member, post_id).await?;
+
+    Ok(Json(post))
+}
+
+pub async fn create_post(
+    member: RoomMember,
+    State(state): State<Arc<AppState>>,
+    Json(payload): Json<CreatePostInput>,
+) -> Result<Json<ForumPost>, SendError> {
+    if payload.content.trim().is_empty() {
+        return Err(SendError::Invalid("Message content can't be empty"));
+    }
+    let title = clean_title(&payload.title).map_err(SendError::Invalid)?;
+    let tags = clean_tags(payload.tags).map_err(SendError::Invalid)?;
+
+    let post = messaging::create_post(&state, &member, title, payload.content, tags).await?;
+
+    Ok(Json(post))
+}
+
+pub async fn update_post(
+    member: RoomMember,
+    Path((_, post_id)): Path<(Uuid, Uuid)>,
+    State(state): State<Arc<AppState>>,
+    Json(payload): Json<UpdatePostInput>,
+) -> Result<Json<ForumPost>, (StatusCode, String)> {
+    let post = load_post(&state.pool, &member, post_id).await?;
+    require_post_editor(&member, &post)?;
+
+    let title = payload
+        .title
+        .as_deref()
+        .map(clean_title)
+        .transpose()
+        .map_err(|e| (StatusCode::BAD_REQUEST, e.to_string()))?;
+    let tags = payload
+        .tags
+        .map(clean_tags)
+        .transpose()
+        .map_err(|e| (StatusCode::BAD_REQUEST, e.to_string()))?;
+
+    sqlx::query!(
+        r#"
+        UPDATE messages
+        SET title = COALESCE($2, title),
+            tags = COALESCE($3, tags)
+        WHERE id = $1
+        "#,
+        post_id,
+        title,
+        tags.as_deref()
+    )
+    .execute(&state.pool)
+    .await
+    .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))?;
+
+    let post = load_post(&state.pool, &member, post_id).await?;
+    state.broadcast(member.room_id, &RoomEvent::ForumPost(post.clone())).await;
+
+    Ok(Json(post))
+}
+
+pub async fn solve_post(
+    member: RoomMember,
+    Path((_, post_id)): Path<(Uuid, Uuid)>,
+    State(state): State<Arc<AppState>>,
+    Json(payload): Json<SolvePostInput>,
+) -> Result<Json<ForumPost>, (StatusCode, String)> {
+    let post = load_post(&state.pool, &member, post_id).await?;
+    require_post_editor(&member, &post)?;
+
+    // The solution has to be one of this post's own replies
+    let solved = sqlx::query!(
+        r#"
+        UPDATE messages
+        SET solved_at = NOW(), solved_by = $2, solution_id = $3
+        WHERE id = $1
+          AND ($3::UUID IS NULL OR EXISTS (
+                SELECT 1 FROM thread_replies WHERE id = $3 AND parent_id = $1
+              ))
+        "#,
+        post_id,
+        member.user_id,
+        payload.reply_id
+    )
+    .execute(&state.pool)
+    .await
+    .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))?;
+
+    if solved.rows_affected() == 0 {
+        return Err((StatusCode::BAD_REQUEST, "That reply isn't part of this post".into()));
+    }
+
+    let post = load_post(&state.pool, &member, post_id).await?;
+    state.broadcast(member.room_id, &RoomEvent::ForumPost(post.clone())).await;
+
+    Ok(Json(post))
+}
+
+pub async fn unsolve_post(
+    member: RoomMember,
+    Path((_, post_id)): Path<(Uuid, Uuid)>,
+    State(state): State<Arc<AppState>>,
+) -> Result<Json<ForumPost>, (StatusCode, String)> {
+    let post = load_post(&state.pool, &member, post_id).await?;
+    require_post_editor(&member, &post)?;
+
+    sqlx::query!(
+        "UPDATE messages SET solved_at = NULL, solved_by = NULL, solution_id = NULL WHERE id = $1",
+        post_id
+    )
+    .execute(&state.pool)
+    .await
+    .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))?;
+
+    let post = load_post(&state.pool, &member, post_id).await?;
+    state.broadcast(member.room_id, &RoomEvent::ForumPost(post.clone())).await;
+
+    Ok(Json(post))
+}
+
+/// Tags in use in the room, most used first, for building filters.
+pub async fn list_tags(
+    member: RoomMember,
+    State(state): State<Arc<AppState>>,
+) -> Result<Json<Vec<TagCount>>, (StatusCode, String)> {
+    let tags = sqlx::query_as!(
+        TagCount,
+        r#"
+        SELECT tag AS "tag!", COUNT(*) AS "count!"
+        FROM messages, unnest(tags) AS tag
+        WHERE room_id = $1 AND title IS NOT NULL
+          AND ($2::TIMESTAMPTZ IS NULL OR created_at >= $2)
+        GROUP BY tag
+        ORDER BY COUNT(*) DESC, tag ASC
+        LIMIT 100
+        "#,
+        member.room_id,
+        member.history_cutoff
+    )
+    .fetch_all(&state.pool)
+    .await
+    .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))?;
+
+    Ok(Json(tags))
+}
@@ -0,0 +1,85 @@
+use axum::{
+    extract::{Extension, Path, State},
+    http::{header, StatusCode},
+    response::IntoResponse,
+    Json,
+};
+use uuid::Uuid;
+use crate::auth::membership::RoomMember;
+use crate::auth::middleware::CurrentUser;
+use crate::markdown::{self, Refs};
+use crate::models::markdown::{Block, MarkdownInput, MarkdownPreview};
+
+use crate::state::AppState;
+use std::sync::Arc;
+
+/// Parses a draft the way it would be stored if sent to this room.
+pub async fn preview_room_markdown(
+    member: RoomMember,
+    State(state): State<Arc<AppState>>,
+    Json(payload): Json<MarkdownInput>,
+) -> Result<Json<MarkdownPreview>, (StatusCode, String)> {
+    let refs = Refs::for_room(&state.pool, member.room_id, &payload.content)
+        .await
+        .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))?;
+    let ast = markdown::parse(&payload.content, &refs);
+
+    Ok(Json(MarkdownPreview { html: markdown::to_html(&ast), ast }))
+}
+
+pub async fn preview_dm_markdown(
+    Path(other_user_id): Path<Uuid>,
+    Extension(CurrentUser { id: my_id, .. }): Extension<CurrentUser>,
+    State(state): State<Arc<AppState>>,
+    Json(payload): Json<MarkdownInput>,
+) -> Result<Json<MarkdownPreview>, (StatusCode, String)> {
+    let refs = Refs::for_dm(&state.pool, [my_id, other_user_id], &payload.content)
+        .await
+        .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))?;
+    let ast = markdown::parse(&payload.content, &refs);
+
+    Ok(Json(MarkdownPreview { html: markdown::to_html(&ast), ast }))
+}
+
+/// A stored message as a sanitized HTML fragment, for exports. Same
+/// visibility as the history.
+pub async fn get_room_message_html(
+    member: RoomMember,
+    Path((_, message_id)): Path<(Uuid, Uuid)>,
+    State(state): State<Arc<AppState>>,
+) -> Result<impl IntoResponse, (StatusCode, String)> {
+    let message = sqlx::query!(
+        r#"
+        SELECT content, content_ast AS "content_ast: sqlx::types::Json<Vec<Block>>"
+        FROM messages
+        WHERE id = $1 AND room_id = $2
+          AND ($3::TIMESTAMPTZ IS NULL OR created_at >= $3)
+        "#,
+        message_id,
+        member.room_id,
+        member.history_cutoff
+    )
+    .fetch_optional(&state.pool)
+    .await
+    .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))?
+    .ok_or((StatusCode::NOT_FOUND, "Message not found".to_string()))?;
+
+    // Not reached by the background parser yet
+    let ast = match message.content_ast {
+        Some(ast) => ast.0,
+        None => {
+            let refs = Refs::for_room(&state.pool, member.room_id, &message.content)
+                .await
+                .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))?;
+            markdown::parse(&message.content, &refs)
+        }
+    };
+
+    Ok((
+        [
+            (header::CONTENT_TYPE, "text/html; charset=utf-8"),
+            (header::X_CONTENT_TYPE_OPTIONS, "nosniff"),
+        ],
+        markdown::to_html(&ast),
+    ))
+}
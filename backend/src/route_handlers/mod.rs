@@ -2,4 +2,17 @@ pub mod me;
 pub mod ws;
 pub mod users;
 pub mod room;
-pub mod relationships;
\ No newline at end of file
+pub mod relationships;
+pub mod roles;
+pub mod audit;
+pub mod pins;
+pub mod threads;
+pub mod channels;
+pub mod templates;
+pub mod rules;
+pub mod scheduled_events;
+pub mod forum;
+pub mod reactions;
+pub mod notifications;
+pub mod attachments;
+pub mod markdown;
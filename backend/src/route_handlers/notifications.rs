@@ -0,0 +1,101 @@
+use axum::{
+    extract::{Extension, Path, Query, State},
+    Json,
+    http::StatusCode,
+};
+use serde_json::json;
+use uuid::Uuid;
+use crate::auth::middleware::CurrentUser;
+use crate::models::events::UserEvent;
+use crate::models::notifications::{Notification, NotificationQuery};
+
+use crate::state::AppState;
+use std::sync::Arc;
+
+/// The caller's inbox, newest first. Notifications from rooms they have left
+/// or that were deleted are hidden.
+pub async fn list_notifications(
+    Extension(CurrentUser { id: user_id, .. }): Extension<CurrentUser>,
+    Query(query): Query<NotificationQuery>,
+    State(state): State<Arc<AppState>>,
+) -> Result<Json<Vec<Notification>>, (StatusCode, String)> {
+    let limit = query.limit.unwrap_or(50).clamp(1, 100);
+
+    let notifications = sqlx::query_as!(
+        Notification,
+        r#"
+        SELECT n.id, n.kind, n.room_id, r.name AS room_name, n.message_id, n.thread_reply_id,
+               n.actor_id, a.username AS actor_username,
+               LEFT(COALESCE(tr.content, m.content), 100) AS "snippet!",
+               n.created_at, n.read_at
+        FROM notifications n
+        JOIN rooms r ON r.id = n.room_id
+        JOIN users a ON a.id = n.actor_id
+        JOIN messages m ON m.id = n.message_id
+        LEFT JOIN thread_replies tr ON tr.id = n.thread_reply_id
+        WHERE n.user_id = $1 AND r.deleted_at IS NULL
+          AND EXISTS (
+                SELECT 1 FROM room_members rm WHERE rm.room_id = n.room_id AND rm.user_id = n.user_id
+              )
+          AND (NOT $2 OR n.read_at IS NULL)
+          AND ($3::UUID IS NULL OR (n.created_at, n.id) < (
+                SELECT created_at, id FROM notifications WHERE id = $3 AND user_id = $1
+              ))
+        ORDER BY n.created_at DESC, n.id DESC
+        LIMIT $4
+        "#,
+        user_id,
+        query.unread,
+        query.before,
+        limit
+    )
+    .fetch_all(&state.pool)
+    .await
+    .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))?;
+
+    Ok(Json(notifications))
+}
+
+pub async fn mark_notification_read(
+    Path(notification_id): Path<Uuid>,
+    Extension(CurrentUser { id: user_id, .. }): Extension<CurrentUser>,
+    State(state): State<Arc<AppState>>,
+) -> Result<Json<serde_json::Value>, (StatusCode, String)> {
+    sqlx::query_scalar!(
+        r#"
+        UPDATE notifications SET read_at = COALESCE(read_at, NOW())
+        WHERE id = $1 AND user_id = $2
+        RETURNING id
+        "#,
+        notification_id,
+        user_id
+    )
+    .fetch_optional(&state.pool)
+    .await
+    .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))?
+    .ok_or((StatusCode::NOT_FOUND, "Notification not found".to_string()))?;
+
+    state.notify_user(user_id, &UserEvent::NotificationRead { notification_id }).await;
+
+    Ok(Json(json!({ "result": "read" })))
+}
+
+pub async fn mark_all_notifications_read(
+    Extension(CurrentUser { id: user_id, .. }): Extension<CurrentUser>,
+    State(state): State<Arc<AppState>>,
+) -> Result<Json<serde_json::Value>, (StatusCode, String)> {
+    let updated = sqlx::query!(
+        "UPDATE notifications SET read_at = NOW() WHERE user_id = $1 AND read_at IS NULL",
+        user_id
+    )
+    .execute(&state.pool)
+    .await
+    .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))?
+    .rows_affected();
+
+    if updated > 0 {
+        state.notify_user(user_id, &UserEvent::NotificationsReadAll).await;
+    }
+
+    Ok(Json(json!({ "updated": updated })))
+}
@@ -0,0 +1,145 @@
+use axum::{
+    extract::{Path, State},
+    Json,
+    http::StatusCode,
+};
+use uuid::Uuid;
+use serde_json::json;
+use crate::auth::membership::RoomMember;
+use crate::models::events::RoomEvent;
+use crate::models::pins::{PinnedMessage, MAX_PINS_PER_ROOM};
+use crate::models::roles::permissions;
+
+use crate::state::AppState;
+use std::sync::Arc;
+
+pub async fn list_pins(
+    member: RoomMember,
+    State(state): State<Arc<AppState>>,
+) -> Result<Json<Vec<PinnedMessage>>, (StatusCode, String)> {
+    let pins = sqlx::query_as!(
+        PinnedMessage,
+        r#"
+        SELECT m.id, m.room_id, m.author_id, m.content, m.created_at, m.edited_at,
+               p.pinned_by, p.pinned_at
+        FROM room_pins p
+        JOIN messages m ON m.id = p.message_id
+        WHERE p.room_id = $1
+          AND ($2::TIMESTAMPTZ IS NULL OR m.created_at >= $2)
+        ORDER BY p.pinned_at ASC
+        "#,
+        member.room_id,
+        member.history_cutoff
+    )
+    .fetch_all(&state.pool)
+    .await
+    .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))?;
+
+    Ok(Json(pins))
+}
+
+pub async fn pin_message(
+    member: RoomMember,
+    Path((_, message_id)): Path<(Uuid, Uuid)>,
+    State(state): State<Arc<AppState>>,
+) -> Result<Json<serde_json::Value>, (StatusCode, String)> {
+    member.require(permissions::PIN_MESSAGES)?;
+
+    let sent_at = sqlx::query_scalar!(
+        "SELECT created_at FROM messages WHERE id = $1 AND room_id = $2",
+        message_id,
+        member.room_id
+    )
+    .fetch_optional(&state.pool)
+    .await
+    .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))?
+    .ok_or((StatusCode::NOT_FOUND, "Message not found".to_string()))?;
+
+    let mut tx = state
+        .pool
+        .begin()
+        .await
+        .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))?;
+
+    // Concurrent pins in the room wait here, so each one counts the others
+    sqlx::query!("SELECT id FROM rooms WHERE id = $1 FOR UPDATE", member.room_id)
+        .fetch_one(&mut *tx)
+        .await
+        .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))?;
+
+    let pinned_at = sqlx::query_scalar!(
+        r#"
+        INSERT INTO room_pins (message_id, room_id, pinned_by)
+        SELECT $1, $2, $3
+        WHERE (SELECT COUNT(*) FROM room_pins WHERE room_id = $2) < $4
+        ON CONFLICT (message_id) DO NOTHING
+        RETURNING pinned_at
+        "#,
+        message_id,
+        member.room_id,
+        member.user_id,
+        MAX_PINS_PER_ROOM
+    )
+    .fetch_optional(&mut *tx)
+    .await
+    .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))?;
+
+    tx.commit()
+        .await
+        .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))?;
+
+    let Some(pinned_at) = pinned_at else {
+        // Either already pinned (idempotent) or the room is at its cap
+        let already = sqlx::query_scalar!(
+            r#"SELECT EXISTS (SELECT 1 FROM room_pins WHERE message_id = $1) AS "exists!""#,
+            message_id
+        )
+        .fetch_one(&state.pool)
+        .await
+        .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))?;
+
+        if already {
+            return Ok(Json(json!({ "result": "pinned" })));
+        }
+        return Err((
+            StatusCode::CONFLICT,
+            format!("A room can have at most {MAX_PINS_PER_ROOM} pinned messages"),
+        ));
+    };
+
+    state
+        .broadcast(
+            member.room_id,
+            &RoomEvent::MessagePinned { message_id, pinned_by: member.user_id, pinned_at, sent_at },
+        )
+        .await;
+
+    Ok(Json(json!({ "result": "pinned" })))
+}
+
+pub async fn unpin_message(
+    member: RoomMember,
+    Path((_, message_id)): Path<(Uuid, Uuid)>,
+    State(state): State<Arc<AppState>>,
+) -> Result<Json<serde_json::Value>, (StatusCode, String)> {
+    member.require(permissions::PIN_MESSAGES)?;
+
+    let sent_at = sqlx::query_scalar!(
+        r#"
+        DELETE FROM room_pins p
+        USING messages m
+        WHERE p.message_id = $1 AND p.room_id = $2 AND m.id = p.message_id
+        RETURNING m.created_at
+        "#,
+        message_id,
+        member.room_id
+    )
+    .fetch_optional(&state.pool)
+    .await
+    .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))?
+    .ok_or((StatusCode::NOT_FOUND, "Message is not pinned".to_string()))?;
+
+    state.broadcast(member.room_id, &RoomEvent::MessageUnpinned { message_id, sent_at }).await;
+
+    Ok(Json(json!({ "result": "unpinned" })))
+}
@@ -0,0 +1,383 @@
+use axum::{
+    extract::{Extension, Path, Query, State},
+    Json,
+    http::StatusCode,
+};
+use std::collections::HashMap;
+use uuid::Uuid;
+use sqlx::PgPool;
+use crate::auth::membership::RoomMember;
+use crate::auth::middleware::CurrentUser;
+use crate::models::events::{DmEvent, RoomEvent};
+use crate::models::reactions::{ReactionSummary, Reactor, ReactorQuery, MAX_DISTINCT_REACTIONS};
+
+use crate::state::AppState;
+use std::sync::Arc;
+
+/// Accepts a single Unicode emoji (skin tones and ZWJ sequences included)
+/// and returns its canonical form, so "❤" and "❤️" count as one reaction.
+fn clean_emoji(emoji: &str) -> Result<String, (StatusCode, String)> {
+    emojis::get(emoji.trim())
+        .map(|e| e.as_str().to_string())
+        .ok_or((StatusCode::BAD_REQUEST, "Reactions must be a single emoji".into()))
+}
+
+fn too_many_reactions() -> (StatusCode, String) {
+    (
+        StatusCode::BAD_REQUEST,
+        format!("A message can have at most {MAX_DISTINCT_REACTIONS} different reactions"),
+    )
+}
+
+/// Reaction summaries for a page of room messages, keyed by message id.
+pub async fn room_reaction_summaries(
+    pool: &PgPool,
+    message_ids: &[Uuid],
+    viewer: Uuid,
+) -> Result<HashMap<Uuid, Vec<ReactionSummary>>, sqlx::Error> {
+    let rows = sqlx::query!(
+        r#"
+        SELECT message_id, emoji, COUNT(*) AS "count!", bool_or(user_id = $2) AS "me!"
+        FROM message_reactions
+        WHERE message_id = ANY($1)
+        GROUP BY message_id, emoji
+        ORDER BY MIN(created_at) ASC
+        "#,
+        message_ids,
+        viewer
+    )
+    .fetch_all(pool)
+    .await?;
+
+    let mut summaries: HashMap<Uuid, Vec<ReactionSummary>> = HashMap::new();
+    for row in rows {
+        summaries.entry(row.message_id).or_default().push(ReactionSummary {
+            emoji: row.emoji,
+            count: row.count,
+            me: row.me,
+        });
+    }
+    Ok(summaries)
+}
+
+/// Same as [`room_reaction_summaries`] for direct messages.
+pub async fn dm_reaction_summaries(
+    pool: &PgPool,
+    message_ids: &[Uuid],
+    viewer: Uuid,
+) -> Result<HashMap<Uuid, Vec<ReactionSummary>>, sqlx::Error> {
+    let rows = sqlx::query!(
+        r#"
+        SELECT direct_message_id, emoji, COUNT(*) AS "count!", bool_or(user_id = $2) AS "me!"
+        FROM direct_message_reactions
+        WHERE direct_message_id = ANY($1)
+        GROUP BY direct_message_id, emoji
+        ORDER BY MIN(created_at) ASC
+        "#,
+        message_ids,
+        viewer
+    )
+    .fetch_all(pool)
+    .await?;
+
+    let mut summaries: HashMap<Uuid, Vec<ReactionSummary>> = HashMap::new();
+    for row in rows {
+        summaries.entry(row.direct_message_id).or_default().push(ReactionSummary {
+            emoji: row.emoji,
+            count: row.count,
+            me: row.me,
+        });
+    }
+    Ok(summaries)
+}
+
+async fn room_message_reactions(
+    pool: &PgPool,
+    message_id: Uuid,
+    viewer: Uuid,
+) -> Result<Vec<ReactionSummary>, (StatusCode, String)> {
+    room_reaction_summaries(pool, &[message_id], viewer)
+        .await
+        .map(|mut all| all.remove(&message_id).unwrap_or_default())
+        .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))
+}
+
+async fn dm_message_reactions(
+    pool: &PgPool,
+    message_id: Uuid,
+    viewer: Uuid,
+) -> Result<Vec<ReactionSummary>, (StatusCode, String)> {
+    dm_reaction_summaries(pool, &[message_id], viewer)
+        .await
+        .map(|mut all| all.remove(&message_id).unwrap_or_default())
+        .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))
+}
+
+pub async fn add_room_reaction(
+    member: RoomMember,
+    Path((_, message_id, emoji)): Path<(Uuid, Uuid, String)>,
+    State(state): State<Arc<AppState>>,
+) -> Result<Json<Vec<ReactionSummary>>, (StatusCode, String)> {
+    let emoji = clean_emoji(&emoji)?;
+
+    let mut tx = state
+        .pool
+        .begin()
+        .await
+        .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))?;
+
+    // Locking the message serializes concurrent reactions, which keeps the cap exact
+    let sent_at = sqlx::query_scalar!(
+        r#"
+        SELECT created_at FROM messages
+        WHERE id = $1 AND room_id = $2 AND deleted_at IS NULL
+          AND ($3::TIMESTAMPTZ IS NULL OR created_at >= $3)
+        FOR UPDATE
+        "#,
+        message_id,
+        member.room_id,
+        member.history_cutoff
+    )
+    .fetch_optional(&mut *tx)
+    .await
+    .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))?
+    .ok_or((StatusCode::NOT_FOUND, "Message not found".to_string()))?;
+
+    let existing = sqlx::query!(
+        r#"
+        SELECT COUNT(DISTINCT emoji) AS "distinct!", COALESCE(bool_or(emoji = $2), FALSE) AS "present!"
+        FROM message_reactions
+        WHERE message_id = $1
+        "#,
+        message_id,
+        emoji
+    )
+    .fetch_one(&mut *tx)
+    .await
+    .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))?;
+
+    if !existing.present && existing.distinct >= MAX_DISTINCT_REACTIONS {
+        return Err(too_many_reactions());
+    }
+
+    let added = sqlx::query!(
+        r#"
+        INSERT INTO message_reactions (message_id, user_id, emoji)
+        VALUES ($1, $2, $3)
+        ON CONFLICT DO NOTHING
+        "#,
+        message_id,
+        member.user_id,
+        emoji
+    )
+    .execute(&mut *tx)
+    .await
+    .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))?;
+
+    tx.commit()
+        .await
+        .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))?;
+
+    if added.rows_affected() > 0 {
+        let event = RoomEvent::ReactionAdded { message_id, user_id: member.user_id, emoji, sent_at };
+        state.broadcast(member.room_id, &event).await;
+    }
+
+    Ok(Json(room_message_reactions(&state.pool, message_id, member.user_id).await?))
+}
+
+pub async fn remove_room_reaction(
+    member: RoomMember,
+    Path((_, message_id, emoji)): Path<(Uuid, Uuid, String)>,
+    State(state): State<Arc<AppState>>,
+) -> Result<Json<Vec<ReactionSummary>>, (StatusCode, String)> {
+    let emoji = clean_emoji(&emoji)?;
+
+    let removed = sqlx::query_scalar!(
+        r#"
+        DELETE FROM message_reactions r
+        USING messages m
+        WHERE m.id = r.message_id AND m.room_id = $2
+          AND r.message_id = $1 AND r.user_id = $3 AND r.emoji = $4
+        RETURNING m.created_at
+        "#,
+        message_id,
+        member.room_id,
+        member.user_id,
+        emoji
+    )
+    .fetch_optional(&state.pool)
+    .await
+    .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))?;
+
+    if let Some(sent_at) = removed {
+        let event = RoomEvent::ReactionRemoved { message_id, user_id: member.user_id, emoji, sent_at };
+        state.broadcast(member.room_id, &event).await;
+    }
+
+    Ok(Json(room_message_reactions(&state.pool, message_id, member.user_id).await?))
+}
+
+/// Who reacted with one emoji, earliest first.
+pub async fn list_room_reactors(
+    member: RoomMember,
+    Path((_, message_id, emoji)): Path<(Uuid, Uuid, String)>,
+    Query(query): Query<ReactorQuery>,
+    State(state): State<Arc<AppState>>,
+) -> Result<Json<Vec<Reactor>>, (StatusCode, String)> {
+    let emoji = clean_emoji(&emoji)?;
+    let limit = query.limit.unwrap_or(50).clamp(1, 100);
+
+    // A cursor whose reaction was since removed would otherwise match nothing
+    let cursor = match query.after {
+        Some(user_id) => Some(
+            sqlx::query!(
+                r#"
+                SELECT created_at, user_id FROM message_reactions
+                WHERE message_id = $1 AND emoji = $2 AND user_id = $3
+                "#,
+                message_id,
+                emoji,
+                user_id
+            )
+            .fetch_optional(&state.pool)
+            .await
+            .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))?
+            .map(|row| (row.created_at, row.user_id))
+            .ok_or((StatusCode::NOT_FOUND, "Reaction not found".to_string()))?,
+        ),
+        None => None,
+    };
+    let (after_at, after_user) = cursor.unzip();
+
+    let reactors = sqlx::query_as!(
+        Reactor,
+        r#"
+        SELECT r.user_id, u.username, r.created_at
+        FROM message_reactions r
+        JOIN messages m ON m.id = r.message_id
+        JOIN users u ON u.id = r.user_id
+        WHERE r.message_id = $1 AND m.room_id = $2 AND r.emoji = $3
+          AND ($7::TIMESTAMPTZ IS NULL OR m.created_at >= $7)
+          AND ($4::TIMESTAMPTZ IS NULL OR (r.created_at, r.user_id) > ($4, $5))
+        ORDER BY r.created_at ASC, r.user_id ASC
+        LIMIT $6
+        "#,
+        message_id,
+        member.room_id,
+        emoji,
+        after_at,
+        after_user,
+        limit,
+        member.history_cutoff
+    )
+    .fetch_all(&state.pool)
+    .await
+    .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))?;
+
+    Ok(Json(reactors))
+}
+
+pub async fn add_dm_reaction(
+    Path((other_user_id, message_id, emoji)): Path<(Uuid, Uuid, String)>,
+    Extension(CurrentUser { id: my_id, .. }): Extension<CurrentUser>,
+    State(state): State<Arc<AppState>>,
+) -> Result<Json<Vec<ReactionSummary>>, (StatusCode, String)> {
+    let emoji = clean_emoji(&emoji)?;
+
+    let mut tx = state
+        .pool
+        .begin()
+        .await
+        .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))?;
+
+    sqlx::query_scalar!(
+        r#"
+        SELECT id FROM direct_messages
+        WHERE id = $1 AND deleted_at IS NULL
+          AND ((sender_id = $2 AND receiver_id = $3) OR (sender_id = $3 AND receiver_id = $2))
+        FOR UPDATE
+        "#,
+        message_id,
+        my_id,
+        other_user_id
+    )
+    .fetch_optional(&mut *tx)
+    .await
+    .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))?
+    .ok_or((StatusCode::NOT_FOUND, "Message not found".to_string()))?;
+
+    let existing = sqlx::query!(
+        r#"
+        SELECT COUNT(DISTINCT emoji) AS "distinct!", COALESCE(bool_or(emoji = $2), FALSE) AS "present!"
+        FROM direct_message_reactions
+        WHERE direct_message_id = $1
+        "#,
+        message_id,
+        emoji
+    )
+    .fetch_one(&mut *tx)
+    .await
+    .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))?;
+
+    if !existing.present && existing.distinct >= MAX_DISTINCT_REACTIONS {
+        return Err(too_many_reactions());
+    }
+
+    let added = sqlx::query!(
+        r#"
+        INSERT INTO direct_message_reactions (direct_message_id, user_id, emoji)
+        VALUES ($1, $2, $3)
+        ON CONFLICT DO NOTHING
+        "#,
+        message_id,
+        my_id,
+        emoji
+    )
+    .execute(&mut *tx)
+    .await
+    .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))?;
+
+    tx.commit()
+        .await
+        .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))?;
+
+    if added.rows_affected() > 0 {
+        let event = DmEvent::ReactionAdded { message_id, user_id: my_id, emoji };
+        state.broadcast_dm(my_id, other_user_id, &event).await;
+    }
+
+    Ok(Json(dm_message_reactions(&state.pool, message_id, my_id).await?))
+}
+
+pub async fn remove_dm_reaction(
+    Path((other_user_id, message_id, emoji)): Path<(Uuid, Uuid, String)>,
+    Extension(CurrentUser { id: my_id, .. }): Extension<CurrentUser>,
+    State(state): State<Arc<AppState>>,
+) -> Result<Json<Vec<ReactionSummary>>, (StatusCode, String)> {
+    let emoji = clean_emoji(&emoji)?;
+
+    let removed = sqlx::query!(
+        r#"
+        DELETE FROM direct_message_reactions r
+        USING direct_messages dm
+        WHERE dm.id = r.direct_message_id
+          AND ((dm.sender_id = $2 AND dm.receiver_id = $3) OR (dm.sender_id = $3 AND dm.receiver_id = $2))
+          AND r.direct_message_id = $1 AND r.user_id = $2 AND r.emoji = $4
+        "#,
+        message_id,
+        my_id,
+        other_user_id,
+        emoji
+    )
+    .execute(&state.pool)
+    .await
+    .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))?;
+
+    if removed.rows_affected() > 0 {
+        let event = DmEvent::ReactionRemoved { message_id, user_id: my_id, emoji };
+        state.broadcast_dm(my_id, other_user_id, &event).await;
+    }
+
+    Ok(Json(dm_message_reactions(&state.pool, message_id, my_id).await?))
+}
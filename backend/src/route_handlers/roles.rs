@@ -0,0 +1,218 @@
+use axum::{
+    extract::{Path, State},
+    Json,
+    http::StatusCode,
+};
+use uuid::Uuid;
+use serde_json::json;
+use crate::auth::membership::RoomMember;
+use crate::models::roles::{permissions, CreateRoleInput, Role};
+use crate::audit::{self, AuditAction};
+
+use crate::state::AppState;
+use std::sync::Arc;
+
+pub async fn list_roles(
+    member: RoomMember,
+    State(state): State<Arc<AppState>>,
+) -> Result<Json<Vec<Role>>, (StatusCode, String)> {
+    let roles = sqlx::query_as!(
+        Role,
+        r#"
+        SELECT id, room_id, name, permissions, position, created_at
+        FROM room_roles
+        WHERE room_id = $1
+        ORDER BY position DESC, created_at ASC
+        "#,
+        member.room_id
+    )
+    .fetch_all(&state.pool)
+    .await
+    .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))?;
+
+    Ok(Json(roles))
+}
+
+pub async fn create_role(
+    member: RoomMember,
+    State(state): State<Arc<AppState>>,
+    Json(payload): Json<CreateRoleInput>,
+) -> Result<Json<Role>, (StatusCode, String)> {
+    member.require(permissions::MANAGE_ROLES)?;
+
+    let name = payload.name.trim();
+    if name.is_empty() || name.chars().count() > 32 {
+        return Err((StatusCode::BAD_REQUEST, "Role name must be 1-32 characters".into()));
+    }
+    if payload.permissions & !permissions::ALL != 0 {
+        return Err((StatusCode::BAD_REQUEST, "Unknown permission bits".into()));
+    }
+    // Nobody can hand out permissions they don't hold themselves
+    member.require(payload.permissions)?;
+
+    let role = sqlx::query_as!(
+        Role,
+        r#"
+        INSERT INTO room_roles (room_id, name, permissions, position)
+        VALUES ($1, $2, $3, $4)
+        RETURNING id, room_id, name, permissions, position, created_at
+        "#,
+        member.room_id,
+        name,
+        payload.permissions,
+        payload.position
+    )
+    .fetch_one(&state.pool)
+    .await
+    .map_err(|e| match e {
+        sqlx::Error::Database(e) if e.is_unique_violation() => {
+            (StatusCode::CONFLICT, "A role with that name already exists".to_string())
+        }
+        e => (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()),
+    })?;
+
+    audit::record(
+        &state.pool,
+        member.room_id,
+        member.user_id,
+        AuditAction::RoleCreate,
+        Some(role.id),
+        None,
+        Some(json!({ "name": role.name, "permissions": role.permissions, "position": role.position })),
+    )
+    .await;
+
+    Ok(Json(role))
+}
+
+pub async fn delete_role(
+    member: RoomMember,
+    Path((_, role_id)): Path<(Uuid, Uuid)>,
+    State(state): State<Arc<AppState>>,
+) -> Result<Json<serde_json::Value>, (StatusCode, String)> {
+    member.require(permissions::MANAGE_ROLES)?;
+    // Same rule as handing the role out: only for roles within your own permissions
+    member.require(role_permissions(&state, member.room_id, role_id).await?)?;
+
+    let deleted = sqlx::query!(
+        r#"
+        DELETE FROM room_roles
+        WHERE id = $1 AND room_id = $2
+        RETURNING name, permissions
+        "#,
+        role_id,
+        member.room_id
+    )
+    .fetch_optional(&state.pool)
+    .await
+    .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))?
+    .ok_or((StatusCode::NOT_FOUND, "Role not found".to_string()))?;
+
+    audit::record(
+        &state.pool,
+        member.room_id,
+        member.user_id,
+        AuditAction::RoleDelete,
+        Some(role_id),
+        Some(json!({ "name": deleted.name, "permissions": deleted.permissions })),
+        None,
+    )
+    .await;
+
+    Ok(Json(json!({ "result": "deleted" })))
+}
+
+async fn role_permissions(
+    state: &AppState,
+    room_id: Uuid,
+    role_id: Uuid,
+) -> Result<i64, (StatusCode, String)> {
+    sqlx::query_scalar!(
+        r#"
+        SELECT permissions FROM room_roles
+        WHERE id = $1 AND room_id = $2
+        "#,
+        role_id,
+        room_id
+    )
+    .fetch_optional(&state.pool)
+    .await
+    .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))?
+    .ok_or((StatusCode::NOT_FOUND, "Role not found".to_string()))
+}
+
+pub async fn assign_role(
+    member: RoomMember,
+    Path((_, user_id, role_id)): Path<(Uuid, Uuid, Uuid)>,
+    State(state): State<Arc<AppState>>,
+) -> Result<Json<serde_json::Value>, (StatusCode, String)> {
+    member.require(permissions::MANAGE_ROLES)?;
+    member.require(role_permissions(&state, member.room_id, role_id).await?)?;
+
+    // The composite FK on room_members rejects users outside the room
+    let assigned = sqlx::query!(
+        r#"
+        INSERT INTO room_member_roles (user_id, room_id, role_id)
+        VALUES ($1, $2, $3)
+        ON CONFLICT DO NOTHING
+        "#,
+        user_id,
+        member.room_id,
+        role_id
+    )
+    .execute(&state.pool)
+    .await
+    .map_err(|_| (StatusCode::BAD_REQUEST, "User is not a member of this room".to_string()))?;
+
+    if assigned.rows_affected() > 0 {
+        audit::record(
+            &state.pool,
+            member.room_id,
+            member.user_id,
+            AuditAction::RoleAssign,
+            Some(user_id),
+            None,
+            Some(json!({ "role_id": role_id })),
+        )
+        .await;
+    }
+
+    Ok(Json(json!({ "result": "assigned" })))
+}
+
+pub async fn unassign_role(
+    member: RoomMember,
+    Path((_, user_id, role_id)): Path<(Uuid, Uuid, Uuid)>,
+    State(state): State<Arc<AppState>>,
+) -> Result<Json<serde_json::Value>, (StatusCode, String)> {
+    member.require(permissions::MANAGE_ROLES)?;
+    member.require(role_permissions(&state, member.room_id, role_id).await?)?;
+
+    let removed = sqlx::query!(
+        r#"
+        DELETE FROM room_member_roles
+        WHERE user_id = $1 AND room_id = $2 AND role_id = $3
+        "#,
+        user_id,
+        member.room_id,
+        role_id
+    )
+    .execute(&state.pool)
+    .await
+    .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))?;
+
+    if removed.rows_affected() > 0 {
+        audit::record(
+            &state.pool,
+            member.room_id,
+            member.user_id,
+            AuditAction::RoleUnassign,
+            Some(user_id),
+            Some(json!({ "role_id": role_id })),
+            None,
+        )
+        .await;
+    }
+
+    Ok(Json(json!({ "result": "unassigned" })))
+}
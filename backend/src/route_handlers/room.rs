@@ -1,25 +1,98 @@
 use axum::{
-    extract::{Path, State,Extension},
+    extract::{Path, Query, State,Extension},
     Json,
     http::StatusCode,
 };
 use uuid::Uuid;
+use sqlx::PgPool;
+use time::OffsetDateTime;
+use time::format_description::well_known::Rfc3339;
 use serde_json::json;
 use crate::auth::middleware::CurrentUser;
-use crate::models::rooms::{CreateRoomInput,Room,RoomMessage,RoomMessageInput , RoomInfo , Member};
+use crate::auth::membership::RoomMember;
+use crate::models::roles::permissions;
+use crate::models::rooms::{
+    CreateRoomInput, Room, RoomMessage, RoomMessageInput, RoomInfo, Member,
+    UpdateRoomInput, DeleteRoomQuery, TransferRoomInput, DeletedRoom,
+    MemberListQuery, UpdateMemberInput, RoomSummary, LastMessage, MarkReadInput,
+    UNREAD_COUNT_CAP, HistoryVisibility, MAX_HISTORY_DAYS, RoomKind,
+    PurgeMessagesInput, MAX_PURGE_MESSAGES,
+};
+use crate::models::events::RoomEvent;
+use crate::models::messages::{EditMessageInput, MessageRevision, ReplyPreview, HistoryQuery};
+use crate::models::attachments::Attachment;
+use crate::models::embeds::Embed;
+use crate::models::markdown::Block;
+use crate::models::reactions::WithReactions;
+use crate::route_handlers::reactions::room_reaction_summaries;
+use crate::models::channels::{ChannelFilter, RoomDetails};
+use crate::route_handlers::channels::load_layout;
+use crate::audit::{self, AuditAction};
+use crate::link_previews::{self, Target};
+use crate::history;
+use crate::markdown::{self, Refs};
+use crate::mentions;
+use crate::messaging::{self, SendError, MAX_SLOWMODE_SECONDS};
 
 
 use crate::state::AppState;
 use std::sync::Arc;
 
 pub async fn get_room(
-    Path(room_id): Path<Uuid>,
+    member: RoomMember,
     State(state): State<Arc<AppState>>,
-) -> Result<Json<RoomInfo>, (StatusCode, String)> {
+) -> Result<Json<RoomDetails>, (StatusCode, String)> {
     let room = sqlx::query_as!(
         RoomInfo,
         r#"
-        SELECT id, name, owner_id, created_at
+        SELECT id, name, kind, description, icon_url, welcome_message, slowmode_seconds,
+               history_visibility, history_days, owner_id, created_at
+        FROM rooms
+        WHERE id = $1 AND deleted_at IS NULL
+        "#,
+        member.room_id
+    )
+    .fetch_one(&state.pool)
+    .await
+    .map_err(|e| (StatusCode::NOT_FOUND, e.to_string()))?;
+
+    let layout = load_layout(&state.pool, member.room_id)
+        .await
+        .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))?;
+
+    Ok(Json(RoomDetails { room, layout }))
+}
+
+pub async fn update_room(
+    member: RoomMember,
+    State(state): State<Arc<AppState>>,
+    Json(payload): Json<UpdateRoomInput>,
+) -> Result<Json<RoomInfo>, (StatusCode, String)> {
+    member.require(permissions::MANAGE_ROOM)?;
+    let room_id = member.room_id;
+
+    let name = payload.name.map(|n| n.trim().to_string());
+    if name.as_ref().is_some_and(|n| n.is_empty() || n.chars().count() > 100) {
+        return Err((StatusCode::BAD_REQUEST, "Room name must be 1-100 characters".into()));
+    }
+    if payload.description.as_ref().is_some_and(|d| d.chars().count() > 1024) {
+        return Err((StatusCode::BAD_REQUEST, "Description is too long".into()));
+    }
+    if payload.welcome_message.as_ref().is_some_and(|w| w.chars().count() > 2000) {
+        return Err((StatusCode::BAD_REQUEST, "Welcome message is too long".into()));
+    }
+    if payload.slowmode_seconds.is_some_and(|s| !(0..=MAX_SLOWMODE_SECONDS).contains(&s)) {
+        return Err((StatusCode::BAD_REQUEST, "Slowmode must be between 0 and 21600 seconds".into()));
+    }
+    if payload.history_days.is_some_and(|d| !(1..=MAX_HISTORY_DAYS).contains(&d)) {
+        return Err((StatusCode::BAD_REQUEST, "History window must be between 1 and 3650 days".into()));
+    }
+
+    let before = sqlx::query_as!(
+        RoomInfo,
+        r#"
+        SELECT id, name, kind, description, icon_url, welcome_message, slowmode_seconds,
+               history_visibility, history_days, owner_id, created_at
         FROM rooms
         WHERE id = $1
         "#,
@@ -27,7 +100,237 @@ pub async fn get_room(
     )
     .fetch_one(&state.pool)
     .await
-    .map_err(|e| (StatusCode::NOT_FOUND, e.to_string()))?;
+    .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))?;
+
+    let last_days = match payload.history_visibility {
+        Some(visibility) => visibility == HistoryVisibility::LastDays,
+        None => before.history_visibility == HistoryVisibility::LastDays.as_str(),
+    };
+    if last_days && payload.history_days.is_none() && before.history_days.is_none() {
+        return Err((StatusCode::BAD_REQUEST, "history_days is required for last_days".into()));
+    }
+
+    let room = sqlx::query_as!(
+        RoomInfo,
+        r#"
+        UPDATE rooms
+        SET name = COALESCE($2, name),
+            description = CASE WHEN $3::TEXT IS NULL THEN description ELSE NULLIF($3, '') END,
+            icon_url = CASE WHEN $4::TEXT IS NULL THEN icon_url ELSE NULLIF($4, '') END,
+            slowmode_seconds = COALESCE($5, slowmode_seconds),
+            welcome_message = CASE WHEN $6::TEXT IS NULL THEN welcome_message ELSE NULLIF($6, '') END,
+            history_visibility = COALESCE($7, history_visibility),
+            history_days = COALESCE($8, history_days)
+        WHERE id = $1
+        RETURNING id, name, kind, description, icon_url, welcome_message, slowmode_seconds,
+               history_visibility, history_days, owner_id, created_at
+        "#,
+        room_id,
+        name,
+        payload.description,
+        payload.icon_url,
+        payload.slowmode_seconds,
+        payload.welcome_message,
+        payload.history_visibility.map(HistoryVisibility::as_str),
+        payload.history_days
+    )
+    .fetch_one(&state.pool)
+    .await
+    .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))?;
+
+    audit::record(
+        &state.pool,
+        room_id,
+        member.user_id,
+        AuditAction::RoomUpdate,
+        None,
+        Some(settings_snapshot(&before)),
+        Some(settings_snapshot(&room)),
+    )
+    .await;
+
+    state.broadcast(room_id, &RoomEvent::RoomUpdate { room: room.clone() }).await;
+
+    Ok(Json(room))
+}
+
+fn settings_snapshot(room: &RoomInfo) -> serde_json::Value {
+    json!({
+        "name": room.name,
+        "description": room.description,
+        "icon_url": room.icon_url,
+        "welcome_message": room.welcome_message,
+        "slowmode_seconds": room.slowmode_seconds,
+        "history_visibility": room.history_visibility,
+        "history_days": room.history_days,
+    })
+}
+
+pub async fn delete_room(
+    member: RoomMember,
+    Query(query): Query<DeleteRoomQuery>,
+    State(state): State<Arc<AppState>>,
+) -> Result<Json<serde_json::Value>, (StatusCode, String)> {
+    member.require_owner()?;
+    let room_id = member.room_id;
+
+    let name = sqlx::query_scalar!(
+        r#"
+        SELECT name FROM rooms
+        WHERE id = $1
+        "#,
+        room_id
+    )
+    .fetch_one(&state.pool)
+    .await
+    .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))?;
+
+    if query.confirm != name {
+        return Err((StatusCode::BAD_REQUEST, "Confirmation does not match the room name".into()));
+    }
+
+    // Soft delete: the room stays restorable until the purge job removes it
+    let deleted_at = sqlx::query_scalar!(
+        r#"
+        UPDATE rooms
+        SET deleted_at = NOW()
+        WHERE id = $1
+        RETURNING deleted_at AS "deleted_at!"
+        "#,
+        room_id
+    )
+    .fetch_one(&state.pool)
+    .await
+    .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))?;
+
+    audit::record(
+        &state.pool,
+        room_id,
+        member.user_id,
+        AuditAction::RoomDelete,
+        None,
+        Some(json!({ "name": name })),
+        None,
+    )
+    .await;
+
+    state.broadcast(room_id, &RoomEvent::RoomDeleted { room_id }).await;
+    state.rooms.write().await.remove(&room_id);
+
+    Ok(Json(json!({
+        "result": "deleted",
+        "restorable_until": (deleted_at + state.room_restore_window).format(&Rfc3339).ok(),
+    })))
+}
+
+pub async fn list_deleted_rooms(
+    Extension(CurrentUser { id: user_id, .. }): Extension<CurrentUser>,
+    State(state): State<Arc<AppState>>,
+) -> Result<Json<Vec<DeletedRoom>>, (StatusCode, String)> {
+    let cutoff = OffsetDateTime::now_utc() - state.room_restore_window;
+
+    let rooms = sqlx::query!(
+        r#"
+        SELECT id, name, deleted_at AS "deleted_at!"
+        FROM rooms
+        WHERE owner_id = $1 AND deleted_at > $2
+        ORDER BY deleted_at DESC
+        "#,
+        user_id,
+        cutoff
+    )
+    .fetch_all(&state.pool)
+    .await
+    .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))?;
+
+    Ok(Json(
+        rooms
+            .into_iter()
+            .map(|r| DeletedRoom {
+                id: r.id,
+                name: r.name,
+                deleted_at: r.deleted_at,
+                purge_at: r.deleted_at + state.room_restore_window,
+            })
+            .collect(),
+    ))
+}
+
+pub async fn restore_room(
+    Path(room_id): Path<Uuid>,
+    Extension(CurrentUser { id: user_id, .. }): Extension<CurrentUser>,
+    State(state): State<Arc<AppState>>,
+) -> Result<Json<RoomInfo>, (StatusCode, String)> {
+    let cutoff = OffsetDateTime::now_utc() - state.room_restore_window;
+
+    let room = sqlx::query_as!(
+        RoomInfo,
+        r#"
+        UPDATE rooms
+        SET deleted_at = NULL
+        WHERE id = $1 AND owner_id = $2 AND deleted_at > $3
+        RETURNING id, name, kind, description, icon_url, welcome_message, slowmode_seconds,
+               history_visibility, history_days, owner_id, created_at
+        "#,
+        room_id,
+        user_id,
+        cutoff
+    )
+    .fetch_optional(&state.pool)
+    .await
+    .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))?
+    .ok_or((StatusCode::NOT_FOUND, "No restorable room found".to_string()))?;
+
+    audit::record(&state.pool, room_id, user_id, AuditAction::RoomRestore, None, None, None).await;
+
+    Ok(Json(room))
+}
+
+pub async fn transfer_room(
+    member: RoomMember,
+    State(state): State<Arc<AppState>>,
+    Json(payload): Json<TransferRoomInput>,
+) -> Result<Json<RoomInfo>, (StatusCode, String)> {
+    member.require_owner()?;
+    let room_id = member.room_id;
+
+    if payload.user_id == member.user_id {
+        return Err((StatusCode::BAD_REQUEST, "You already own this room".into()));
+    }
+
+    let room = sqlx::query_as!(
+        RoomInfo,
+        r#"
+        UPDATE rooms
+        SET owner_id = $2
+        WHERE id = $1
+          AND EXISTS (
+            SELECT 1 FROM room_members
+            WHERE room_id = $1 AND user_id = $2
+          )
+        RETURNING id, name, kind, description, icon_url, welcome_message, slowmode_seconds,
+               history_visibility, history_days, owner_id, created_at
+        "#,
+        room_id,
+        payload.user_id
+    )
+    .fetch_optional(&state.pool)
+    .await
+    .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))?
+    .ok_or((StatusCode::BAD_REQUEST, "New owner must be a member of the room".to_string()))?;
+
+    audit::record(
+        &state.pool,
+        room_id,
+        member.user_id,
+        AuditAction::OwnerTransfer,
+        Some(payload.user_id),
+        Some(json!({ "owner_id": member.user_id })),
+        Some(json!({ "owner_id": payload.user_id })),
+    )
+    .await;
+
+    state.broadcast(room_id, &RoomEvent::RoomUpdate { room: room.clone() }).await;
 
     Ok(Json(room))
 }
@@ -41,12 +344,13 @@ pub async fn create_room(
     let room = sqlx::query_as!(
         Room,
         r#"
-        INSERT INTO rooms (name, owner_id)
-        VALUES ($1, $2)
-        RETURNING id, name, owner_id, created_at
+        INSERT INTO rooms (name, owner_id, kind)
+        VALUES ($1, $2, $3)
+        RETURNING id, name, kind, owner_id, created_at
         "#,
         payload.name,
-        owner_id
+        owner_id,
+        payload.kind.unwrap_or(RoomKind::Chat).as_str()
     )
     .fetch_one(&state.pool)
     .await
@@ -73,7 +377,19 @@ pub async fn join_room(
     Extension(CurrentUser { id: user_id, .. }): Extension<CurrentUser>,
     State(state): State<Arc<AppState>>,
 ) -> Result<Json<serde_json::Value>, (StatusCode, String)> {
-    sqlx::query!(
+    let room = sqlx::query!(
+        r#"
+        SELECT welcome_message, rules, rules_version FROM rooms
+        WHERE id = $1 AND deleted_at IS NULL
+        "#,
+        room_id
+    )
+    .fetch_optional(&state.pool)
+    .await
+    .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))?
+    .ok_or((StatusCode::NOT_FOUND, "Room not found".to_string()))?;
+
+    let joined = sqlx::query!(
         r#"
         INSERT INTO room_members (user_id, room_id)
         VALUES ($1, $2)
@@ -86,113 +402,665 @@ pub async fn join_room(
     .await
     .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))?;
 
-    Ok(Json(json!({ "result": "joined" })))
+    if joined.rows_affected() > 0 {
+        audit::record(&state.pool, room_id, user_id, AuditAction::MemberJoin, Some(user_id), None, None).await;
+    }
+
+    Ok(Json(json!({
+        "result": "joined",
+        "welcome_message": room.welcome_message,
+        "rules": room.rules,
+        "rules_version": room.rules_version,
+    })))
 }
 
 pub async fn list_my_rooms(
     Extension(CurrentUser { id: user_id, .. }): Extension<CurrentUser>,
     State(state): State<Arc<AppState>>,
-) -> Result<Json<Vec<Room>>, (StatusCode, String)> {
-    let rooms = sqlx::query_as!(
-        Room,
+) -> Result<Json<Vec<RoomSummary>>, (StatusCode, String)> {
+    // Every lateral below is an index probe on messages(room_id, created_at) or
+    // room_members(room_id), and the unread and mention counts each stop at
+    // the cap, so the cost stays proportional to the number of rooms rather
+    // than their history. Mentions are counted on their own so a long unread
+    // backlog can't push them out of the sample.
+    let rows = sqlx::query!(
         r#"
-        SELECT r.id, r.name, r.owner_id, r.created_at
-        FROM rooms r
-        JOIN room_members m ON r.id = m.room_id
-        WHERE m.user_id = $1
+        SELECT r.id, r.name, r.owner_id, r.icon_url, r.created_at,
+               mc.count AS "member_count!",
+               lm.id AS "last_id?", lm.author_id AS "last_author_id?",
+               lm.username AS "last_author_username?", lm.snippet AS "last_snippet?",
+               lm.created_at AS "last_created_at?",
+               unread.count AS "unread_count!", mentioned.count AS "mention_count!"
+        FROM room_members rm
+        JOIN rooms r ON r.id = rm.room_id
+        CROSS JOIN LATERAL (
+            SELECT COUNT(*) AS count FROM room_members WHERE room_id = r.id
+        ) mc
+        CROSS JOIN LATERAL (
+            SELECT room_history_cutoff(r.history_visibility, r.history_days, rm.joined_at) AS at
+        ) cutoff
+        LEFT JOIN LATERAL (
+            SELECT m.id, m.author_id, u.username, LEFT(m.content, 100) AS snippet, m.created_at
+            FROM messages m
+            LEFT JOIN users u ON u.id = m.author_id
+            WHERE m.room_id = r.id AND m.deleted_at IS NULL
+              AND m.created_at >= COALESCE(cutoff.at, '-infinity')
+            ORDER BY m.created_at DESC
+            LIMIT 1
+        ) lm ON TRUE
+        CROSS JOIN LATERAL (
+            SELECT COUNT(*) AS count
+            FROM (
+                SELECT 1
+                FROM messages m
+                WHERE m.room_id = r.id
+                  AND m.created_at > GREATEST(rm.last_read_at, cutoff.at)
+                  AND m.author_id <> rm.user_id AND m.deleted_at IS NULL
+                LIMIT $2
+            ) recent
+        ) unread
+        CROSS JOIN LATERAL (
+            SELECT COUNT(*) AS count
+            FROM (
+                SELECT 1
+                FROM messages m
+                WHERE m.room_id = r.id
+                  AND m.created_at > GREATEST(rm.last_read_at, cutoff.at)
+                  AND m.author_id <> rm.user_id AND m.deleted_at IS NULL
+                  AND (
+                      m.mentions_everyone
+                      OR rm.user_id = ANY(m.mentioned_user_ids)
+                      OR m.mentioned_role_ids && ARRAY(
+                            SELECT role_id FROM room_member_roles
+                            WHERE room_id = r.id AND user_id = rm.user_id
+                         )
+                  )
+                LIMIT $2
+            ) recent
+        ) mentioned
+        WHERE rm.user_id = $1 AND r.deleted_at IS NULL
+        ORDER BY COALESCE(lm.created_at, r.created_at) DESC
         "#,
-        user_id
+        user_id,
+        UNREAD_COUNT_CAP
     )
     .fetch_all(&state.pool)
     .await
     .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))?;
 
+    let rooms = rows
+        .into_iter()
+        .map(|r| RoomSummary {
+            id: r.id,
+            name: r.name,
+            owner_id: r.owner_id,
+            icon_url: r.icon_url,
+            created_at: r.created_at,
+            member_count: r.member_count,
+            last_message: match (r.last_id, r.last_author_id, r.last_created_at) {
+                (Some(id), Some(author_id), Some(created_at)) => Some(LastMessage {
+                    id,
+                    author_id,
+                    author_username: r.last_author_username,
+                    snippet: r.last_snippet.unwrap_or_default(),
+                    created_at,
+                }),
+                _ => None,
+            },
+            unread_count: r.unread_count,
+            mention_count: r.mention_count,
+        })
+        .collect();
+
     Ok(Json(rooms))
 }
 
-pub async fn send_room_message(
-    Path(room_id): Path<Uuid>,
-    Extension(CurrentUser { id: author_id, .. }): Extension<CurrentUser>,
+pub async fn mark_room_read(
+    member: RoomMember,
     State(state): State<Arc<AppState>>,
-    Json(payload): Json<RoomMessageInput>,
-) -> Result<Json<RoomMessage>, (StatusCode, String)> {
-    let message = sqlx::query_as!(
-        RoomMessage,
+    Json(payload): Json<MarkReadInput>,
+) -> Result<Json<serde_json::Value>, (StatusCode, String)> {
+    let read_at = match payload.message_id {
+        Some(message_id) => sqlx::query_scalar!(
+            "SELECT created_at FROM messages WHERE id = $1 AND room_id = $2",
+            message_id,
+            member.room_id
+        )
+        .fetch_optional(&state.pool)
+        .await
+        .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))?
+        .ok_or((StatusCode::NOT_FOUND, "Message not found".to_string()))?,
+        None => OffsetDateTime::now_utc(),
+    };
+
+    // The marker only moves forward, so a stale client can't resurrect unreads.
+    let last_read_at = sqlx::query_scalar!(
         r#"
-        INSERT INTO messages (room_id, author_id, content)
-        VALUES ($1, $2, $3)
-        RETURNING id, room_id, author_id, content, created_at, edited_at
+        UPDATE room_members SET last_read_at = GREATEST(last_read_at, $3)
+        WHERE room_id = $1 AND user_id = $2
+        RETURNING last_read_at
         "#,
-        room_id,
-        author_id,
-        payload.content
+        member.room_id,
+        member.user_id,
+        read_at
     )
     .fetch_one(&state.pool)
     .await
     .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))?;
 
+    let last_read_at = last_read_at
+        .format(&Rfc3339)
+        .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))?;
+
+    Ok(Json(json!({ "last_read_at": last_read_at })))
+}
+
+pub async fn send_room_message(
+    member: RoomMember,
+    State(state): State<Arc<AppState>>,
+    Json(payload): Json<RoomMessageInput>,
+) -> Result<Json<RoomMessage>, SendError> {
+    let message = messaging::send_room_message(&state, &member, payload).await?;
+
     Ok(Json(message))
 }
 
 
 pub async fn get_room_messages(
-    Path(room_id): Path<Uuid>,
-    Extension(CurrentUser { id: user_id, .. }): Extension<CurrentUser>,
+    member: RoomMember,
+    Query(query): Query<HistoryQuery>,
+    Query(ChannelFilter { channel_id }): Query<ChannelFilter>,
     State(state): State<Arc<AppState>>,
-) -> Result<Json<Vec<RoomMessage>>, (StatusCode, String)> {
-    // Optional: verify user is in the room
-    let authorized = sqlx::query_scalar!(
+) -> Result<Json<Vec<WithReactions<RoomMessage>>>, (StatusCode, String)> {
+    let cursor = query.cursor()?;
+    if let Some(channel_id) = channel_id {
+        let in_room = sqlx::query_scalar!(
+            "SELECT EXISTS (SELECT 1 FROM room_channels WHERE id = $1 AND room_id = $2) AS \"exists!\"",
+            channel_id,
+            member.room_id
+        )
+        .fetch_one(&state.pool)
+        .await
+        .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))?;
+        if !in_room {
+            return Err((StatusCode::NOT_FOUND, "Channel not found".into()));
+        }
+    }
+    let key = match cursor.message_id() {
+        Some(id) => Some(
+            sqlx::query!(
+                r#"
+                SELECT created_at, id FROM messages
+                WHERE id = $1 AND room_id = $2
+                  AND ($3::TIMESTAMPTZ IS NULL OR created_at >= $3)
+                "#,
+                id,
+                member.room_id,
+                member.history_cutoff
+            )
+            .fetch_optional(&state.pool)
+            .await
+            .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))?
+            .map(|row| (row.created_at, row.id))
+            .ok_or((StatusCode::NOT_FOUND, "Message not found".to_string()))?,
+        ),
+        None => None,
+    };
+    let floor = member.history_cutoff.map(|at| (at, Uuid::nil()));
+
+    let messages = history::load(cursor, key, floor, query.limit(), |range, newest_first, limit| {
+        room_history_page(&state.pool, member.room_id, channel_id, member.history_cutoff, range, newest_first, limit)
+    })
+    .await
+    .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))?;
+
+    let ids: Vec<Uuid> = messages.iter().map(|m| m.id).collect();
+    let mut reactions = room_reaction_summaries(&state.pool, &ids, member.user_id)
+        .await
+        .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))?;
+
+    let messages = messages
+        .into_iter()
+        .map(|message| WithReactions {
+            reactions: reactions.remove(&message.id).unwrap_or_default(),
+            message,
+        })
+        .collect();
+
+    Ok(Json(messages))
+}
+
+/// Up to `limit` messages of the room (or of one of its channels) in `range`,
+/// from whichever end `newest_first` says. The order is fixed in each query so
+/// the `(room_id, created_at, id)` and `(channel_id, created_at, id)` indexes
+/// serve both.
+async fn room_history_page(
+    pool: &PgPool,
+    room_id: Uuid,
+    channel_id: Option<Uuid>,
+    history_cutoff: Option<OffsetDateTime>,
+    range: history::Range,
+    newest_first: bool,
+    limit: i64,
+) -> Result<Vec<RoomMessage>, sqlx::Error> {
+    let (from_at, from_id) = range.from.unzip();
+    let (to_at, to_id) = range.to.unzip();
+
+    if newest_first {
+        sqlx::query_as!(
+            RoomMessage,
+            r#"
+            SELECT id, room_id, author_id, content, created_at, edited_at,
+                   thread_reply_count, thread_last_reply_at, kind, deleted_at, channel_id, reply_to,
+                   mentioned_user_ids, mentioned_role_ids, mentions_everyone,
+                   message_reply_preview(reply_to, $2) AS "reply_preview: sqlx::types::Json<ReplyPreview>",
+                   message_attachments(id) AS "attachments!: sqlx::types::Json<Vec<Attachment>>",
+                   embeds AS "embeds: sqlx::types::Json<Vec<Embed>>",
+                   content_ast AS "content_ast: sqlx::types::Json<Vec<Block>>"
+            FROM messages
+            WHERE room_id = $1
+              AND (created_at, id) >= (COALESCE($3::TIMESTAMPTZ, '-infinity'), COALESCE($4::UUID, '00000000-0000-0000-0000-000000000000'))
+              AND (created_at, id) < (COALESCE($5::TIMESTAMPTZ, 'infinity'), COALESCE($6::UUID, '00000000-0000-0000-0000-000000000000'))
+              AND ($8::UUID IS NULL OR channel_id = $8)
+            ORDER BY created_at DESC, id DESC
+            LIMIT $7
+            "#,
+            room_id,
+            history_cutoff,
+            from_at,
+            from_id,
+            to_at,
+            to_id,
+            limit,
+            channel_id
+        )
+        .fetch_all(pool)
+        .await
+    } else {
+        sqlx::query_as!(
+            RoomMessage,
+            r#"
+            SELECT id, room_id, author_id, content, created_at, edited_at,
+                   thread_reply_count, thread_last_reply_at, kind, deleted_at, channel_id, reply_to,
+                   mentioned_user_ids, mentioned_role_ids, mentions_everyone,
+                   message_reply_preview(reply_to, $2) AS "reply_preview: sqlx::types::Json<ReplyPreview>",
+                   message_attachments(id) AS "attachments!: sqlx::types::Json<Vec<Attachment>>",
+                   embeds AS "embeds: sqlx::types::Json<Vec<Embed>>",
+                   content_ast AS "content_ast: sqlx::types::Json<Vec<Block>>"
+            FROM messages
+            WHERE room_id = $1
+              AND (created_at, id) >= (COALESCE($3::TIMESTAMPTZ, '-infinity'), COALESCE($4::UUID, '00000000-0000-0000-0000-000000000000'))
+              AND (created_at, id) < (COALESCE($5::TIMESTAMPTZ, 'infinity'), COALESCE($6::UUID, '00000000-0000-0000-0000-000000000000'))
+              AND ($8::UUID IS NULL OR channel_id = $8)
+            ORDER BY created_at ASC, id ASC
+            LIMIT $7
+            "#,
+            room_id,
+            history_cutoff,
+            from_at,
+            from_id,
+            to_at,
+            to_id,
+            limit,
+            channel_id
+        )
+        .fetch_all(pool)
+        .await
+    }
+}
+
+pub async fn list_room_members(
+    member: RoomMember,
+    Query(query): Query<MemberListQuery>,
+    State(state): State<Arc<AppState>>,
+) -> Result<Json<Vec<Member>>, (StatusCode, String)> {
+    let limit = query.limit.unwrap_or(100).clamp(1, 1000);
+    // Escape LIKE wildcards so the search is a literal prefix match
+    let prefix = query.q.map(|q| {
+        q.to_lowercase()
+            .replace('\\', "\\\\")
+            .replace('%', "\\%")
+            .replace('_', "\\_")
+            + "%"
+    });
+
+    let members = sqlx::query_as!(
+        Member,
         r#"
-        SELECT EXISTS (
-            SELECT 1 FROM room_members
-            WHERE user_id = $1 AND room_id = $2
-        ) AS "exists!"
+        SELECT
+            u.id,
+            u.username,
+            rm.nickname,
+            rm.joined_at,
+            ARRAY(
+                SELECT mr.role_id FROM room_member_roles mr
+                WHERE mr.room_id = rm.room_id AND mr.user_id = rm.user_id
+            ) AS "role_ids!"
+        FROM room_members rm
+        JOIN users u ON rm.user_id = u.id
+        WHERE rm.room_id = $1
+          AND ($2::UUID IS NULL OR u.username > (SELECT username FROM users WHERE id = $2))
+          AND ($3::TEXT IS NULL OR lower(u.username) LIKE $3 OR lower(rm.nickname) LIKE $3)
+        ORDER BY u.username
+        LIMIT $4
         "#,
-        user_id,
-        room_id
+        member.room_id,
+        query.after,
+        prefix,
+        limit
+    )
+    .fetch_all(&state.pool)
+    .await
+    .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))?;
+
+    Ok(Json(members))
+}
+
+pub async fn update_my_membership(
+    member: RoomMember,
+    State(state): State<Arc<AppState>>,
+    Json(payload): Json<UpdateMemberInput>,
+) -> Result<Json<serde_json::Value>, (StatusCode, String)> {
+    let nickname = payload
+        .nickname
+        .map(|n| n.trim().to_string())
+        .filter(|n| !n.is_empty());
+    if nickname.as_ref().is_some_and(|n| n.chars().count() > 32) {
+        return Err((StatusCode::BAD_REQUEST, "Nickname must be at most 32 characters".into()));
+    }
+
+    let previous = sqlx::query_scalar!(
+        r#"
+        WITH old AS (
+            SELECT nickname FROM room_members
+            WHERE room_id = $1 AND user_id = $2
+        )
+        UPDATE room_members
+        SET nickname = $3
+        WHERE room_id = $1 AND user_id = $2
+        RETURNING (SELECT nickname FROM old)
+        "#,
+        member.room_id,
+        member.user_id,
+        nickname
     )
     .fetch_one(&state.pool)
     .await
-    .map_err(|_| (StatusCode::INTERNAL_SERVER_ERROR, "Authorization check failed".to_string()))?;
+    .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))?;
+
+    if previous != nickname {
+        audit::record(
+            &state.pool,
+            member.room_id,
+            member.user_id,
+            AuditAction::NicknameUpdate,
+            Some(member.user_id),
+            Some(json!({ "nickname": previous })),
+            Some(json!({ "nickname": nickname })),
+        )
+        .await;
+    }
+
+    Ok(Json(json!({ "nickname": nickname })))
+}
+
+pub async fn edit_room_message(
+    member: RoomMember,
+    Path((_, message_id)): Path<(Uuid, Uuid)>,
+    State(state): State<Arc<AppState>>,
+    Json(payload): Json<EditMessageInput>,
+) -> Result<Json<RoomMessage>, (StatusCode, String)> {
+    if payload.content.trim().is_empty() {
+        return Err((StatusCode::BAD_REQUEST, "Message content can't be empty".into()));
+    }
+
+    let mut tx = state
+        .pool
+        .begin()
+        .await
+        .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))?;
+
+    let current = sqlx::query!(
+        r#"
+        SELECT author_id, content, kind FROM messages
+        WHERE id = $1 AND room_id = $2 AND deleted_at IS NULL
+        FOR UPDATE
+        "#,
+        message_id,
+        member.room_id
+    )
+    .fetch_optional(&mut *tx)
+    .await
+    .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))?
+    .ok_or((StatusCode::NOT_FOUND, "Message not found".to_string()))?;
 
-    if !authorized {
-        return Err((StatusCode::UNAUTHORIZED, "You are not in this room".to_string()));
+    if current.author_id != member.user_id || current.kind != "user" {
+        return Err((StatusCode::FORBIDDEN, "You can only edit your own messages".into()));
     }
 
-    let messages = sqlx::query_as!(
+    // Keep what's being replaced so moderators can review the edit
+    sqlx::query!(
+        r#"
+        INSERT INTO message_revisions (message_id, content, edited_by)
+        VALUES ($1, $2, $3)
+        "#,
+        message_id,
+        current.content,
+        member.user_id
+    )
+    .execute(&mut *tx)
+    .await
+    .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))?;
+
+    let mentions = mentions::resolve(&state.pool, member.room_id, &payload.content)
+        .await
+        .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))?;
+    let refs = Refs::for_room(&state.pool, member.room_id, &payload.content)
+        .await
+        .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))?;
+    let ast = markdown::parse(&payload.content, &refs);
+
+    // The preview is rendered in full for the broadcast; each socket hides
+    // it from members who can't see the quoted message
+    let mut message = sqlx::query_as!(
         RoomMessage,
         r#"
-        SELECT id, room_id, author_id, content, created_at, edited_at
-        FROM messages
-        WHERE room_id = $1
-        ORDER BY created_at ASC
+        UPDATE messages
+        SET content = $2, edited_at = NOW(),
+            mentioned_user_ids = $3, mentioned_role_ids = $4, mentions_everyone = $5, content_ast = $6
+        WHERE id = $1
+        RETURNING id, room_id, author_id, content, created_at, edited_at,
+                  thread_reply_count, thread_last_reply_at, kind, deleted_at, channel_id, reply_to,
+                  mentioned_user_ids, mentioned_role_ids, mentions_everyone,
+                  message_reply_preview(reply_to, NULL) AS "reply_preview: sqlx::types::Json<ReplyPreview>",
+                  message_attachments(id) AS "attachments!: sqlx::types::Json<Vec<Attachment>>",
+                  embeds AS "embeds: sqlx::types::Json<Vec<Embed>>",
+                  content_ast AS "content_ast: sqlx::types::Json<Vec<Block>>"
         "#,
-        room_id
+        message_id,
+        payload.content,
+        &mentions.user_ids,
+        &mentions.role_ids,
+        mentions.everyone,
+        sqlx::types::Json(&ast) as _
+    )
+    .fetch_one(&mut *tx)
+    .await
+    .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))?;
+
+    tx.commit()
+        .await
+        .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))?;
+
+    state.broadcast(member.room_id, &RoomEvent::MessageEdited(message.clone())).await;
+    // Only members newly mentioned by the edit get a notification
+    mentions::notify(&state, member.room_id, message_id, None, member.user_id, &mentions).await;
+    link_previews::refresh(&state, Target::Room { room_id: member.room_id, message_id }, &message.content);
+
+    if let Some(cutoff) = member.history_cutoff
+        && message.quotes_before(cutoff)
+    {
+        message.reply_preview = None;
+    }
+
+    Ok(Json(message))
+}
+
+/// Authors can delete their own messages, moderators anyone's. Moderator
+/// deletions are audited together with the removed content.
+pub async fn delete_room_message(
+    member: RoomMember,
+    Path((_, message_id)): Path<(Uuid, Uuid)>,
+    State(state): State<Arc<AppState>>,
+) -> Result<Json<serde_json::Value>, (StatusCode, String)> {
+    let message = sqlx::query!(
+        r#"
+        SELECT author_id, content, kind FROM messages
+        WHERE id = $1 AND room_id = $2 AND deleted_at IS NULL
+        "#,
+        message_id,
+        member.room_id
+    )
+    .fetch_optional(&state.pool)
+    .await
+    .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))?
+    .ok_or((StatusCode::NOT_FOUND, "Message not found".to_string()))?;
+
+    let own = message.author_id == member.user_id && message.kind == "user";
+    if !own {
+        member.require(permissions::MANAGE_MESSAGES)?;
+    }
+
+    let deleted = messaging::tombstone_messages(&state.pool, member.room_id, &[message_id], member.user_id)
+        .await
+        .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))?;
+    let Some(&(_, sent_at)) = deleted.first() else {
+        return Err((StatusCode::NOT_FOUND, "Message not found".into()));
+    };
+
+    if !own {
+        audit::record(
+            &state.pool,
+            member.room_id,
+            member.user_id,
+            AuditAction::MessageDelete,
+            Some(message.author_id),
+            Some(json!({ "message_id": message_id, "content": message.content })),
+            None,
+        )
+        .await;
+    }
+
+    state.broadcast(member.room_id, &RoomEvent::MessageDeleted { message_id, sent_at }).await;
+
+    Ok(Json(json!({ "result": "deleted" })))
+}
+
+pub async fn purge_room_messages(
+    member: RoomMember,
+    State(state): State<Arc<AppState>>,
+    Json(payload): Json<PurgeMessagesInput>,
+) -> Result<Json<serde_json::Value>, (StatusCode, String)> {
+    member.require(permissions::MANAGE_MESSAGES)?;
+
+    if payload.last.is_none() && payload.author_id.is_none() {
+        return Err((StatusCode::BAD_REQUEST, "Give `last` or `author_id` to purge".into()));
+    }
+    if payload.last.is_some_and(|n| !(1..=MAX_PURGE_MESSAGES).contains(&n)) {
+        return Err((StatusCode::BAD_REQUEST, "`last` must be between 1 and 1000".into()));
+    }
+    let limit = payload.last.unwrap_or(MAX_PURGE_MESSAGES);
+
+    let targets = sqlx::query_scalar!(
+        r#"
+        SELECT id FROM messages
+        WHERE room_id = $1 AND deleted_at IS NULL
+          AND ($2::UUID IS NULL OR author_id = $2)
+          AND ($3::TIMESTAMPTZ IS NULL OR created_at >= $3)
+          AND ($4::TIMESTAMPTZ IS NULL OR created_at <= $4)
+        ORDER BY created_at DESC
+        LIMIT $5
+        "#,
+        member.room_id,
+        payload.author_id,
+        payload.since,
+        payload.until,
+        limit
     )
     .fetch_all(&state.pool)
     .await
     .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))?;
 
-    Ok(Json(messages))
+    let purged = messaging::tombstone_messages(&state.pool, member.room_id, &targets, member.user_id)
+        .await
+        .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))?;
+    let (message_ids, sent_at): (Vec<Uuid>, Vec<OffsetDateTime>) = purged.into_iter().unzip();
+
+    if !message_ids.is_empty() {
+        let fmt = |t: Option<OffsetDateTime>| t.and_then(|t| t.format(&Rfc3339).ok());
+        audit::record(
+            &state.pool,
+            member.room_id,
+            member.user_id,
+            AuditAction::MessagePurge,
+            payload.author_id,
+            None,
+            Some(json!({
+                "count": message_ids.len(),
+                "last": payload.last,
+                "since": fmt(payload.since),
+                "until": fmt(payload.until),
+            })),
+        )
+        .await;
+
+        state
+            .broadcast(member.room_id, &RoomEvent::MessagesPurged { message_ids: message_ids.clone(), sent_at })
+            .await;
+    }
+
+    Ok(Json(json!({ "deleted": message_ids.len(), "message_ids": message_ids })))
 }
 
-pub async fn list_room_members(
-    Path(room_id): Path<Uuid>,
+/// Earlier versions of a message, oldest first. Moderators only.
+pub async fn get_message_revisions(
+    member: RoomMember,
+    Path((_, message_id)): Path<(Uuid, Uuid)>,
     State(state): State<Arc<AppState>>,
-) -> Result<Json<Vec<Member>>, (StatusCode, String)> {
-    let members = sqlx::query_as!(
-        Member,
+) -> Result<Json<Vec<MessageRevision>>, (StatusCode, String)> {
+    member.require(permissions::MANAGE_MESSAGES)?;
+
+    let in_room = sqlx::query_scalar!(
         r#"
-        SELECT u.id, u.username
-        FROM room_members rm
-        JOIN users u ON rm.user_id = u.id
-        WHERE rm.room_id = $1
+        SELECT EXISTS (
+            SELECT 1 FROM messages
+            WHERE id = $1 AND room_id = $2
+        ) AS "exists!"
         "#,
-        room_id
+        message_id,
+        member.room_id
+    )
+    .fetch_one(&state.pool)
+    .await
+    .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))?;
+
+    if !in_room {
+        return Err((StatusCode::NOT_FOUND, "Message not found".into()));
+    }
+
+    let revisions = sqlx::query_as!(
+        MessageRevision,
+        r#"
+        SELECT id, content, edited_by, created_at
+        FROM message_revisions
+        WHERE message_id = $1
+        ORDER BY created_at ASC
+        "#,
+        message_id
     )
     .fetch_all(&state.pool)
     .await
     .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))?;
 
-    Ok(Json(members))
-}
\ No newline at end of file
+    Ok(Json(revisions))
+}
@@ -0,0 +1,143 @@
+use axum::{
+    extract::State,
+    Json,
+    http::StatusCode,
+};
+use serde_json::json;
+use crate::audit::{self, AuditAction};
+use crate::auth::membership::RoomMember;
+use crate::models::events::RoomEvent;
+use crate::models::roles::permissions;
+use crate::models::rules::{AcceptRulesInput, RoomRules, UpdateRulesInput, MAX_RULES_LENGTH};
+
+use crate::state::AppState;
+use std::sync::Arc;
+
+pub async fn get_rules(
+    member: RoomMember,
+    State(state): State<Arc<AppState>>,
+) -> Result<Json<RoomRules>, (StatusCode, String)> {
+    let rules = sqlx::query_as!(
+        RoomRules,
+        r#"
+        SELECT r.rules, r.rules_version AS version, r.rules_updated_at AS updated_at,
+               rm.rules_accepted_version AS accepted_version, rm.rules_accepted_at AS accepted_at,
+               NOT (r.rules IS NULL OR r.owner_id = rm.user_id
+                    OR COALESCE(rm.rules_accepted_version >= r.rules_required_version, FALSE)) AS "must_accept!"
+        FROM room_members rm
+        JOIN rooms r ON r.id = rm.room_id
+        WHERE rm.room_id = $1 AND rm.user_id = $2
+        "#,
+        member.room_id,
+        member.user_id
+    )
+    .fetch_one(&state.pool)
+    .await
+    .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))?;
+
+    Ok(Json(rules))
+}
+
+pub async fn update_rules(
+    member: RoomMember,
+    State(state): State<Arc<AppState>>,
+    Json(payload): Json<UpdateRulesInput>,
+) -> Result<Json<serde_json::Value>, (StatusCode, String)> {
+    member.require(permissions::MANAGE_ROOM)?;
+
+    let text = payload.rules.trim();
+    if text.chars().count() > MAX_RULES_LENGTH {
+        return Err((StatusCode::BAD_REQUEST, "Rules are too long".into()));
+    }
+    let rules = (!text.is_empty()).then_some(text);
+
+    let before = sqlx::query_scalar!("SELECT rules FROM rooms WHERE id = $1", member.room_id)
+        .fetch_one(&state.pool)
+        .await
+        .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))?;
+
+    // Members have accepted nothing yet when rules first appear, so everyone
+    // is screened; after that only an explicit request raises the bar.
+    let version = sqlx::query_scalar!(
+        r#"
+        UPDATE rooms
+        SET rules = $2,
+            rules_version = rules_version + 1,
+            rules_required_version = CASE WHEN $3 OR rules IS NULL THEN rules_version + 1
+                                          ELSE rules_required_version END,
+            rules_updated_at = NOW()
+        WHERE id = $1
+        RETURNING rules_version
+        "#,
+        member.room_id,
+        rules,
+        payload.require_reacceptance
+    )
+    .fetch_one(&state.pool)
+    .await
+    .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))?;
+
+    audit::record(
+        &state.pool,
+        member.room_id,
+        member.user_id,
+        AuditAction::RulesUpdate,
+        None,
+        Some(json!({ "rules": before })),
+        Some(json!({ "rules": rules, "version": version, "require_reacceptance": payload.require_reacceptance })),
+    )
+    .await;
+
+    state
+        .broadcast(
+            member.room_id,
+            &RoomEvent::RulesUpdate { version, require_reacceptance: payload.require_reacceptance },
+        )
+        .await;
+
+    Ok(Json(json!({ "version": version })))
+}
+
+pub async fn accept_rules(
+    member: RoomMember,
+    State(state): State<Arc<AppState>>,
+    Json(payload): Json<AcceptRulesInput>,
+) -> Result<Json<serde_json::Value>, (StatusCode, String)> {
+    let current = sqlx::query!(
+        "SELECT rules IS NOT NULL AS \"has_rules!\", rules_version FROM rooms WHERE id = $1",
+        member.room_id
+    )
+    .fetch_one(&state.pool)
+    .await
+    .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))?;
+
+    if !current.has_rules {
+        return Err((StatusCode::BAD_REQUEST, "This room has no rules".into()));
+    }
+    if payload.version != current.rules_version {
+        return Err((StatusCode::CONFLICT, "The rules have changed, review them again".into()));
+    }
+
+    // Re-checked in the update in case the rules change in between
+    let accepted = sqlx::query!(
+        r#"
+        UPDATE room_members rm
+        SET rules_accepted_version = $3, rules_accepted_at = NOW()
+        FROM rooms r
+        WHERE r.id = rm.room_id AND r.rules_version = $3
+          AND rm.room_id = $1 AND rm.user_id = $2
+        "#,
+        member.room_id,
+        member.user_id,
+        payload.version
+    )
+    .execute(&state.pool)
+    .await
+    .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))?;
+
+    if accepted.rows_affected() == 0 {
+        return Err((StatusCode::CONFLICT, "The rules have changed, review them again".into()));
+    }
+
+    Ok(Json(json!({ "result": "accepted", "version": payload.version })))
+}
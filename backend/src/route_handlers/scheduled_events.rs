@@ -0,0 +1,405 @@
+use axum::{
+    extract::{Path, Query, State},
+    Json,
+    http::{header, StatusCode},
+    response::IntoResponse,
+};
+use uuid::Uuid;
+use serde_json::json;
+use sqlx::PgPool;
+use time::OffsetDateTime;
+use crate::auth::membership::RoomMember;
+use crate::ical;
+use crate::models::events::RoomEvent;
+use crate::models::roles::permissions;
+use crate::models::scheduled_events::{
+    CalendarQuery, CreateEventInput, EventListQuery, Rsvp, RsvpInput, ScheduledEvent,
+    UpdateEventInput, MAX_EVENT_DESCRIPTION_LENGTH, MAX_EVENT_LOCATION_LENGTH, MAX_EVENT_TITLE_LENGTH,
+};
+
+use crate::state::AppState;
+use std::sync::Arc;
+
+/// Upper bound on events returned by one list or feed request.
+const MAX_EVENTS_PER_REQUEST: i64 = 100;
+
+/// Events of a room overlapping `[from, to]`, soonest first, with RSVP tallies
+/// and `viewer`'s own answer.
+async fn load_events(
+    pool: &PgPool,
+    room_id: Uuid,
+    viewer: Option<Uuid>,
+    event_id: Option<Uuid>,
+    from: Option<OffsetDateTime>,
+    to: Option<OffsetDateTime>,
+) -> Result<Vec<ScheduledEvent>, sqlx::Error> {
+    sqlx::query_as!(
+        ScheduledEvent,
+        r#"
+        SELECT e.id, e.room_id, e.creator_id, e.title, e.description, e.location,
+               e.starts_at, e.ends_at, e.created_at, e.updated_at,
+               COUNT(r.user_id) FILTER (WHERE r.status = 'going') AS "going_count!",
+               COUNT(r.user_id) FILTER (WHERE r.status = 'maybe') AS "maybe_count!",
+               COUNT(r.user_id) FILTER (WHERE r.status = 'not_going') AS "not_going_count!",
+               MAX(r.status) FILTER (WHERE r.user_id = $2) AS my_rsvp
+        FROM room_events e
+        LEFT JOIN room_event_rsvps r ON r.event_id = e.id
+        WHERE e.room_id = $1
+          AND ($3::UUID IS NULL OR e.id = $3)
+          AND ($4::TIMESTAMPTZ IS NULL OR e.ends_at >= $4)
+          AND ($5::TIMESTAMPTZ IS NULL OR e.starts_at <= $5)
+        GROUP BY e.id
+        ORDER BY e.starts_at ASC, e.id ASC
+        LIMIT $6
+        "#,
+        room_id,
+        viewer,
+        event_id,
+        from,
+        to,
+        MAX_EVENTS_PER_REQUEST
+    )
+    .fetch_all(pool)
+    .await
+}
+
+async fn load_event(
+    pool: &PgPool,
+    room_id: Uuid,
+    viewer: Option<Uuid>,
+    event_id: Uuid,
+) -> Result<ScheduledEvent, (StatusCode, String)> {
+    load_events(pool, room_id, viewer, Some(event_id), None, None)
+        .await
+        .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))?
+        .pop()
+        .ok_or((StatusCode::NOT_FOUND, "Event not found".to_string()))
+}
+
+/// Pushes the event's current state to the room.
+async fn broadcast_event(state: &AppState, room_id: Uuid, event_id: Uuid) {
+    if let Ok(event) = load_event(&state.pool, room_id, None, event_id).await {
+        state.broadcast(room_id, &RoomEvent::ScheduledEvent { event }).await;
+    }
+}
+
+/// Creators may always change their own events, everyone else needs `MANAGE_EVENTS`.
+fn require_editor(member: &RoomMember, event: &ScheduledEvent) -> Result<(), (StatusCode, String)> {
+    if event.creator_id == Some(member.user_id) {
+        Ok(())
+    } else {
+        member.require(permissions::MANAGE_EVENTS)
+    }
+}
+
+fn validate_details(
+    title: Option<&str>,
+    description: Option<&str>,
+    location: Option<&str>,
+) -> Result<(), (StatusCode, String)> {
+    if title.is_some_and(|t| t.is_empty() || t.chars().count() > MAX_EVENT_TITLE_LENGTH) {
+        return Err((
+            StatusCode::BAD_REQUEST,
+            format!("Event title must be 1-{MAX_EVENT_TITLE_LENGTH} characters"),
+        ));
+    }
+    if description.is_some_and(|d| d.chars().count() > MAX_EVENT_DESCRIPTION_LENGTH) {
+        return Err((
+            StatusCode::BAD_REQUEST,
+            format!("Description can be at most {MAX_EVENT_DESCRIPTION_LENGTH} characters"),
+        ));
+    }
+    if location.is_some_and(|l| l.chars().count() > MAX_EVENT_LOCATION_LENGTH) {
+        return Err((
+            StatusCode::BAD_REQUEST,
+            format!("Location can be at most {MAX_EVENT_LOCATION_LENGTH} characters"),
+        ));
+    }
+    Ok(())
+}
+
+pub async fn list_events(
+    member: RoomMember,
+    Query(query): Query<EventListQuery>,
+    State(state): State<Arc<AppState>>,
+) -> Result<Json<Vec<ScheduledEvent>>, (StatusCode, String)> {
+    let from = query.from.unwrap_or_else(OffsetDateTime::now_utc);
+
+    let events = load_events(&state.pool, member.room_id, Some(member.user_id), None, Some(from), query.to)
+        .await
+        .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))?;
+
+    Ok(Json(events))
+}
+
+pub async fn get_event(
+    member: RoomMember,
+    Path((_, event_id)): Path<(Uuid, Uuid)>,
+    State(state): State<Arc<AppState>>,
+) -> Result<Json<ScheduledEvent>, (StatusCode, String)> {
+    let event = load_event(&state.pool, member.room_id, Some(member.user_id), event_id).await?;
+
+    Ok(Json(event))
+}
+
+pub async fn create_event(
+    member: RoomMember,
+    State(state): State<Arc<AppState>>,
+    Json(payload): Json<CreateEventInput>,
+) -> Result<Json<ScheduledEvent>, (StatusCode, String)> {
+    member.require(permissions::MANAGE_EVENTS)?;
+
+    let title = payload.title.trim();
+    validate_details(Some(title), payload.description.as_deref(), payload.location.as_deref())?;
+    if payload.ends_at <= payload.starts_at {
+        return Err((StatusCode::BAD_REQUEST, "An event must end after it starts".into()));
+    }
+
+    let event_id = sqlx::query_scalar!(
+        r#"
+        INSERT INTO room_events (room_id, creator_id, title, description, location, starts_at, ends_at)
+        VALUES ($1, $2, $3, NULLIF($4, ''), NULLIF($5, ''), $6, $7)
+        RETURNING id
+        "#,
+        member.room_id,
+        member.user_id,
+        title,
+        payload.description,
+        payload.location,
+        payload.starts_at,
+        payload.ends_at
+    )
+    .fetch_one(&state.pool)
+    .await
+    .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))?;
+
+    broadcast_event(&state, member.room_id, event_id).await;
+
+    let event = load_event(&state.pool, member.room_id, Some(member.user_id), event_id).await?;
+    Ok(Json(event))
+}
+
+pub async fn update_event(
+    member: RoomMember,
+    Path((_, event_id)): Path<(Uuid, Uuid)>,
+    State(state): State<Arc<AppState>>,
+    Json(payload): Json<UpdateEventInput>,
+) -> Result<Json<ScheduledEvent>, (StatusCode, String)> {
+    let existing = load_event(&state.pool, member.room_id, None, event_id).await?;
+    require_editor(&member, &existing)?;
+
+    let title = payload.title.as_deref().map(str::trim);
+    validate_details(title, payload.description.as_deref(), payload.location.as_deref())?;
+    let starts_at = payload.starts_at.unwrap_or(existing.starts_at);
+    let ends_at = payload.ends_at.unwrap_or(existing.ends_at);
+    if ends_at <= starts_at {
+        return Err((StatusCode::BAD_REQUEST, "An event must end after it starts".into()));
+    }
+
+    // Moving the start re-arms the reminder
+    sqlx::query!(
+        r#"
+        UPDATE room_events
+        SET title = COALESCE($3, title),
+            description = CASE WHEN $4::TEXT IS NULL THEN description ELSE NULLIF($4, '') END,
+            location = CASE WHEN $5::TEXT IS NULL THEN location ELSE NULLIF($5, '') END,
+            reminder_sent_at = CASE WHEN starts_at = $6 THEN reminder_sent_at END,
+            starts_at = $6,
+            ends_at = $7,
+            updated_at = NOW()
+        WHERE id = $1 AND room_id = $2
+        "#,
+        event_id,
+        member.room_id,
+        title,
+        payload.description,
+        payload.location,
+        starts_at,
+        ends_at
+    )
+    .execute(&state.pool)
+    .await
+    .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))?;
+
+    broadcast_event(&state, member.room_id, event_id).await;
+
+    let event = load_event(&state.pool, member.room_id, Some(member.user_id), event_id).await?;
+    Ok(Json(event))
+}
+
+pub async fn delete_event(
+    member: RoomMember,
+    Path((_, event_id)): Path<(Uuid, Uuid)>,
+    State(state): State<Arc<AppState>>,
+) -> Result<Json<serde_json::Value>, (StatusCode, String)> {
+    let existing = load_event(&state.pool, member.room_id, None, event_id).await?;
+    require_editor(&member, &existing)?;
+
+    sqlx::query!(
+        "DELETE FROM room_events WHERE id = $1 AND room_id = $2",
+        event_id,
+        member.room_id
+    )
+    .execute(&state.pool)
+    .await
+    .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))?;
+
+    state.broadcast(member.room_id, &RoomEvent::ScheduledEventDeleted { event_id }).await;
+
+    Ok(Json(json!({ "result": "deleted" })))
+}
+
+pub async fn list_rsvps(
+    member: RoomMember,
+    Path((_, event_id)): Path<(Uuid, Uuid)>,
+    State(state): State<Arc<AppState>>,
+) -> Result<Json<Vec<Rsvp>>, (StatusCode, String)> {
+    let rsvps = sqlx::query_as!(
+        Rsvp,
+        r#"
+        SELECT r.user_id, u.username, r.status, r.updated_at
+        FROM room_event_rsvps r
+        JOIN room_events e ON e.id = r.event_id
+        JOIN users u ON u.id = r.user_id
+        WHERE r.event_id = $1 AND e.room_id = $2
+        ORDER BY r.status, u.username
+        "#,
+        event_id,
+        member.room_id
+    )
+    .fetch_all(&state.pool)
+    .await
+    .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))?;
+
+    Ok(Json(rsvps))
+}
+
+pub async fn set_rsvp(
+    member: RoomMember,
+    Path((_, event_id)): Path<(Uuid, Uuid)>,
+    State(state): State<Arc<AppState>>,
+    Json(payload): Json<RsvpInput>,
+) -> Result<Json<ScheduledEvent>, (StatusCode, String)> {
+    let saved = sqlx::query!(
+        r#"
+        INSERT INTO room_event_rsvps (event_id, user_id, status)
+        SELECT id, $3, $4 FROM room_events WHERE id = $1 AND room_id = $2
+        ON CONFLICT (event_id, user_id)
+        DO UPDATE SET status = EXCLUDED.status, updated_at = NOW()
+        "#,
+        event_id,
+        member.room_id,
+        member.user_id,
+        payload.status.as_str()
+    )
+    .execute(&state.pool)
+    .await
+    .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))?;
+
+    if saved.rows_affected() == 0 {
+        return Err((StatusCode::NOT_FOUND, "Event not found".into()));
+    }
+
+    broadcast_event(&state, member.room_id, event_id).await;
+
+    let event = load_event(&state.pool, member.room_id, Some(member.user_id), event_id).await?;
+    Ok(Json(event))
+}
+
+pub async fn delete_rsvp(
+    member: RoomMember,
+    Path((_, event_id)): Path<(Uuid, Uuid)>,
+    State(state): State<Arc<AppState>>,
+) -> Result<Json<ScheduledEvent>, (StatusCode, String)> {
+    sqlx::query!(
+        r#"
+        DELETE FROM room_event_rsvps r
+        USING room_events e
+        WHERE e.id = r.event_id AND r.event_id = $1 AND e.room_id = $2 AND r.user_id = $3
+        "#,
+        event_id,
+        member.room_id,
+        member.user_id
+    )
+    .execute(&state.pool)
+    .await
+    .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))?;
+
+    broadcast_event(&state, member.room_id, event_id).await;
+
+    let event = load_event(&state.pool, member.room_id, Some(member.user_id), event_id).await?;
+    Ok(Json(event))
+}
+
+/// The caller's private subscription URL for the room's calendar feed.
+pub async fn get_calendar_feed_url(
+    member: RoomMember,
+    State(state): State<Arc<AppState>>,
+) -> Result<Json<serde_json::Value>, (StatusCode, String)> {
+    let token = sqlx::query_scalar!(
+        "SELECT calendar_token FROM room_members WHERE room_id = $1 AND user_id = $2",
+        member.room_id,
+        member.user_id
+    )
+    .fetch_one(&state.pool)
+    .await
+    .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))?;
+
+    let url = format!("/api/rooms/{}/events/calendar.ics?token={token}", member.room_id);
+    Ok(Json(json!({ "url": url })))
+}
+
+/// Invalidates the old subscription URL and hands out a new one.
+pub async fn rotate_calendar_feed_url(
+    member: RoomMember,
+    State(state): State<Arc<AppState>>,
+) -> Result<Json<serde_json::Value>, (StatusCode, String)> {
+    let token = sqlx::query_scalar!(
+        r#"
+        UPDATE room_members SET calendar_token = gen_random_uuid()
+        WHERE room_id = $1 AND user_id = $2
+        RETURNING calendar_token
+        "#,
+        member.room_id,
+        member.user_id
+    )
+    .fetch_one(&state.pool)
+    .await
+    .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))?;
+
+    let url = format!("/api/rooms/{}/events/calendar.ics?token={token}", member.room_id);
+    Ok(Json(json!({ "url": url })))
+}
+
+/// iCalendar feed for calendar apps. Authenticated by the member's feed token
+/// instead of a JWT; covers the last 30 days and everything upcoming.
+pub async fn calendar_feed(
+    Path(room_id): Path<Uuid>,
+    Query(query): Query<CalendarQuery>,
+    State(state): State<Arc<AppState>>,
+) -> Result<impl IntoResponse, (StatusCode, String)> {
+    let room_name = sqlx::query_scalar!(
+        r#"
+        SELECT r.name
+        FROM room_members rm
+        JOIN rooms r ON r.id = rm.room_id AND r.deleted_at IS NULL
+        WHERE rm.room_id = $1 AND rm.calendar_token = $2
+        "#,
+        room_id,
+        query.token
+    )
+    .fetch_optional(&state.pool)
+    .await
+    .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))?
+    .ok_or((StatusCode::NOT_FOUND, "Calendar not found".to_string()))?;
+
+    let from = OffsetDateTime::now_utc() - time::Duration::days(30);
+    let events = load_events(&state.pool, room_id, None, None, Some(from), None)
+        .await
+        .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))?;
+
+    Ok((
+        [(header::CONTENT_TYPE, "text/calendar; charset=utf-8")],
+        ical::calendar(&room_name, &events),
+    ))
+}
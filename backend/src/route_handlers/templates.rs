@@ -0,0 +1,300 @@
+use axum::{
+    extract::{Path, State, Extension},
+    Json,
+    http::StatusCode,
+};
+use uuid::Uuid;
+use serde_json::json;
+use sqlx::PgPool;
+use sqlx::types::Json as SqlJson;
+use crate::auth::membership::RoomMember;
+use crate::auth::middleware::CurrentUser;
+use crate::models::rooms::Room;
+use crate::models::roles::permissions;
+use crate::models::templates::{
+    CategoryConfig, ChannelConfig, CreateTemplateInput, NewRoomInput, RoleConfig, RoomConfig,
+    RoomTemplate,
+};
+use crate::route_handlers::channels::{clean_name, load_layout};
+
+use crate::state::AppState;
+use std::sync::Arc;
+
+/// Captures a room's settings, roles and sidebar as a reusable config.
+async fn snapshot_room(pool: &PgPool, room_id: Uuid) -> Result<RoomConfig, sqlx::Error> {
+    let room = sqlx::query!(
+        r#"
+        SELECT kind, description, icon_url, welcome_message, slowmode_seconds,
+               history_visibility, history_days, rules
+        FROM rooms
+        WHERE id = $1
+        "#,
+        room_id
+    )
+    .fetch_one(pool)
+    .await?;
+
+    let roles = sqlx::query_as!(
+        RoleConfig,
+        r#"
+        SELECT name, permissions, position
+        FROM room_roles
+        WHERE room_id = $1
+        ORDER BY position DESC, created_at ASC
+        "#,
+        room_id
+    )
+    .fetch_all(pool)
+    .await?;
+
+    let layout = load_layout(pool, room_id).await?;
+    let channel = |c: crate::models::channels::Channel| ChannelConfig { name: c.name, topic: c.topic };
+
+    Ok(RoomConfig {
+        kind: room.kind,
+        description: room.description,
+        icon_url: room.icon_url,
+        welcome_message: room.welcome_message,
+        slowmode_seconds: room.slowmode_seconds,
+        history_visibility: room.history_visibility,
+        history_days: room.history_days,
+        rules: room.rules,
+        roles,
+        uncategorized: layout.uncategorized.into_iter().map(channel).collect(),
+        categories: layout
+            .categories
+            .into_iter()
+            .map(|cat| CategoryConfig {
+                name: cat.name,
+                channels: cat.channels.into_iter().map(channel).collect(),
+            })
+            .collect(),
+    })
+}
+
+/// Creates a fresh room owned by `owner_id` and laid out from `config`, all or nothing.
+async fn instantiate(
+    pool: &PgPool,
+    owner_id: Uuid,
+    name: &str,
+    config: &RoomConfig,
+) -> Result<Room, sqlx::Error> {
+    let mut tx = pool.begin().await?;
+
+    let room = sqlx::query_as!(
+        Room,
+        r#"
+        INSERT INTO rooms (
+            name, owner_id, description, icon_url, welcome_message, slowmode_seconds,
+            history_visibility, history_days, rules, rules_version, rules_required_version,
+            rules_updated_at, kind
+        )
+        VALUES (
+            $1, $2, $3, $4, $5, $6, $7, $8, $9::TEXT,
+            CASE WHEN $9 IS NULL THEN 0 ELSE 1 END,
+            CASE WHEN $9 IS NULL THEN 0 ELSE 1 END,
+            CASE WHEN $9 IS NULL THEN NULL ELSE NOW() END,
+            $10
+        )
+        RETURNING id, name, kind, owner_id, created_at
+        "#,
+        name,
+        owner_id,
+        config.description,
+        config.icon_url,
+        config.welcome_message,
+        config.slowmode_seconds,
+        config.history_visibility,
+        config.history_days,
+        config.rules,
+        config.kind
+    )
+    .fetch_one(&mut *tx)
+    .await?;
+
+    sqlx::query!(
+        "INSERT INTO room_members (user_id, room_id) VALUES ($1, $2)",
+        owner_id,
+        room.id
+    )
+    .execute(&mut *tx)
+    .await?;
+
+    for role in &config.roles {
+        sqlx::query!(
+            r#"
+            INSERT INTO room_roles (room_id, name, permissions, position)
+            VALUES ($1, $2, $3, $4)
+            "#,
+            room.id,
+            role.name,
+            role.permissions & permissions::ALL,
+            role.position
+        )
+        .execute(&mut *tx)
+        .await?;
+    }
+
+    for (pos, channel) in config.uncategorized.iter().enumerate() {
+        sqlx::query!(
+            r#"
+            INSERT INTO room_channels (room_id, name, topic, position)
+            VALUES ($1, $2, $3, $4)
+            "#,
+            room.id,
+            channel.name,
+            channel.topic,
+            pos as i32
+        )
+        .execute(&mut *tx)
+        .await?;
+    }
+
+    for (pos, category) in config.categories.iter().enumerate() {
+        let category_id = sqlx::query_scalar!(
+            r#"
+            INSERT INTO room_categories (room_id, name, position)
+            VALUES ($1, $2, $3)
+            RETURNING id
+            "#,
+            room.id,
+            category.name,
+            pos as i32
+        )
+        .fetch_one(&mut *tx)
+        .await?;
+
+        for (pos, channel) in category.channels.iter().enumerate() {
+            sqlx::query!(
+                r#"
+                INSERT INTO room_channels (room_id, category_id, name, topic, position)
+                VALUES ($1, $2, $3, $4, $5)
+                "#,
+                room.id,
+                category_id,
+                channel.name,
+                channel.topic,
+                pos as i32
+            )
+            .execute(&mut *tx)
+            .await?;
+        }
+    }
+
+    tx.commit().await?;
+
+    Ok(room)
+}
+
+pub async fn create_template(
+    member: RoomMember,
+    State(state): State<Arc<AppState>>,
+    Json(payload): Json<CreateTemplateInput>,
+) -> Result<Json<RoomTemplate>, (StatusCode, String)> {
+    member.require(permissions::MANAGE_ROOM)?;
+    let name = clean_name(&payload.name)?;
+
+    let config = snapshot_room(&state.pool, member.room_id)
+        .await
+        .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))?;
+
+    // Short, URL-safe share code
+    let code = Uuid::new_v4().simple().to_string()[..10].to_string();
+
+    let template = sqlx::query_as!(
+        RoomTemplate,
+        r#"
+        INSERT INTO room_templates (code, name, creator_id, source_room_id, config)
+        VALUES ($1, $2, $3, $4, $5)
+        RETURNING id, code, name, creator_id, source_room_id,
+                  config AS "config: SqlJson<RoomConfig>", created_at
+        "#,
+        code,
+        name,
+        member.user_id,
+        member.room_id,
+        SqlJson(config) as _
+    )
+    .fetch_one(&state.pool)
+    .await
+    .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))?;
+
+    Ok(Json(template))
+}
+
+async fn find_template(state: &AppState, code: &str) -> Result<RoomTemplate, (StatusCode, String)> {
+    sqlx::query_as!(
+        RoomTemplate,
+        r#"
+        SELECT id, code, name, creator_id, source_room_id,
+               config AS "config: SqlJson<RoomConfig>", created_at
+        FROM room_templates
+        WHERE code = $1
+        "#,
+        code
+    )
+    .fetch_optional(&state.pool)
+    .await
+    .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))?
+    .ok_or((StatusCode::NOT_FOUND, "Template not found".to_string()))
+}
+
+pub async fn get_template(
+    Path(code): Path<String>,
+    State(state): State<Arc<AppState>>,
+) -> Result<Json<RoomTemplate>, (StatusCode, String)> {
+    Ok(Json(find_template(&state, &code).await?))
+}
+
+pub async fn delete_template(
+    Path(code): Path<String>,
+    Extension(CurrentUser { id: user_id, .. }): Extension<CurrentUser>,
+    State(state): State<Arc<AppState>>,
+) -> Result<Json<serde_json::Value>, (StatusCode, String)> {
+    let template = find_template(&state, &code).await?;
+    if template.creator_id != user_id {
+        return Err((StatusCode::FORBIDDEN, "Only the template creator can delete it".into()));
+    }
+
+    sqlx::query!("DELETE FROM room_templates WHERE id = $1", template.id)
+        .execute(&state.pool)
+        .await
+        .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))?;
+
+    Ok(Json(json!({ "result": "deleted" })))
+}
+
+pub async fn create_room_from_template(
+    Path(code): Path<String>,
+    Extension(CurrentUser { id: user_id, .. }): Extension<CurrentUser>,
+    State(state): State<Arc<AppState>>,
+    Json(payload): Json<NewRoomInput>,
+) -> Result<Json<Room>, (StatusCode, String)> {
+    let name = clean_name(&payload.name)?;
+    let template = find_template(&state, &code).await?;
+
+    let room = instantiate(&state.pool, user_id, &name, &template.config)
+        .await
+        .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))?;
+
+    Ok(Json(room))
+}
+
+pub async fn clone_room(
+    member: RoomMember,
+    State(state): State<Arc<AppState>>,
+    Json(payload): Json<NewRoomInput>,
+) -> Result<Json<Room>, (StatusCode, String)> {
+    member.require(permissions::MANAGE_ROOM)?;
+    let name = clean_name(&payload.name)?;
+
+    let config = snapshot_room(&state.pool, member.room_id)
+        .await
+        .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))?;
+
+    let room = instantiate(&state.pool, member.user_id, &name, &config)
+        .await
+        .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))?;
+
+    Ok(Json(room))
+}
@@ -0,0 +1,270 @@
+use axum::{
+    extract::{Path, Query, State},
+    Json,
+    http::StatusCode,
+};
+use serde_json::json;
+use time::OffsetDateTime;
+use time::format_description::well_known::Rfc3339;
+use uuid::Uuid;
+use crate::audit::{self, AuditAction};
+use crate::auth::membership::RoomMember;
+use crate::markdown::{self, Refs};
+use crate::mentions;
+use crate::messaging::{self, SendError};
+use crate::models::events::RoomEvent;
+use crate::models::markdown::Block;
+use crate::models::roles::permissions;
+use crate::models::rooms::{PurgeMessagesInput, MAX_PURGE_MESSAGES};
+use crate::models::threads::{ThreadQuery, ThreadReply, ThreadReplyInput};
+
+use crate::state::AppState;
+use std::sync::Arc;
+
+pub async fn send_thread_reply(
+    member: RoomMember,
+    Path((_, message_id)): Path<(Uuid, Uuid)>,
+    State(state): State<Arc<AppState>>,
+    Json(payload): Json<ThreadReplyInput>,
+) -> Result<Json<ThreadReply>, SendError> {
+    let reply = messaging::send_thread_reply(&state, &member, message_id, payload.content).await?;
+
+    Ok(Json(reply))
+}
+
+pub async fn get_thread_replies(
+    member: RoomMember,
+    Path((_, message_id)): Path<(Uuid, Uuid)>,
+    Query(query): Query<ThreadQuery>,
+    State(state): State<Arc<AppState>>,
+) -> Result<Json<Vec<ThreadReply>>, (StatusCode, String)> {
+    require_thread(&state, &member, message_id).await?;
+
+    let limit = query.limit.unwrap_or(50).clamp(1, 100);
+    let backwards = query.before.is_some();
+
+    let mut replies = sqlx::query_as!(
+        ThreadReply,
+        r#"
+        SELECT id, parent_id, room_id, author_id, content, created_at, edited_at,
+               content_ast AS "content_ast: sqlx::types::Json<Vec<Block>>"
+        FROM thread_replies
+        WHERE parent_id = $1 AND room_id = $2
+          AND ($3::UUID IS NULL OR (created_at, id) > (SELECT created_at, id FROM thread_replies WHERE id = $3))
+          AND ($4::UUID IS NULL OR (created_at, id) < (SELECT created_at, id FROM thread_replies WHERE id = $4))
+        ORDER BY
+            CASE WHEN $5 THEN created_at END DESC,
+            CASE WHEN $5 THEN id END DESC,
+            created_at ASC,
+            id ASC
+        LIMIT $6
+        "#,
+        message_id,
+        member.room_id,
+        query.after,
+        query.before,
+        backwards,
+        limit
+    )
+    .fetch_all(&state.pool)
+    .await
+    .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))?;
+
+    // A `before` page is fetched newest-first; hand it back in reading order
+    if backwards {
+        replies.reverse();
+    }
+
+    Ok(Json(replies))
+}
+
+/// Authors can edit their own replies.
+pub async fn edit_thread_reply(
+    member: RoomMember,
+    Path((_, message_id, reply_id)): Path<(Uuid, Uuid, Uuid)>,
+    State(state): State<Arc<AppState>>,
+    Json(payload): Json<ThreadReplyInput>,
+) -> Result<Json<ThreadReply>, (StatusCode, String)> {
+    if payload.content.trim().is_empty() {
+        return Err((StatusCode::BAD_REQUEST, "Reply content can't be empty".into()));
+    }
+    require_thread(&state, &member, message_id).await?;
+
+    let author_id = sqlx::query_scalar!(
+        "SELECT author_id FROM thread_replies WHERE id = $1 AND parent_id = $2 AND room_id = $3",
+        reply_id,
+        message_id,
+        member.room_id
+    )
+    .fetch_optional(&state.pool)
+    .await
+    .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))?
+    .ok_or((StatusCode::NOT_FOUND, "Reply not found".to_string()))?;
+
+    if author_id != member.user_id {
+        return Err((StatusCode::FORBIDDEN, "You can only edit your own replies".into()));
+    }
+
+    let mentions = mentions::resolve(&state.pool, member.room_id, &payload.content)
+        .await
+        .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))?;
+    let refs = Refs::for_room(&state.pool, member.room_id, &payload.content)
+        .await
+        .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))?;
+    let ast = markdown::parse(&payload.content, &refs);
+
+    let reply = sqlx::query_as!(
+        ThreadReply,
+        r#"
+        UPDATE thread_replies
+        SET content = $2, edited_at = NOW(),
+            mentioned_user_ids = $3, mentioned_role_ids = $4, mentions_everyone = $5, content_ast = $6
+        WHERE id = $1
+        RETURNING id, parent_id, room_id, author_id, content, created_at, edited_at,
+                  content_ast AS "content_ast: sqlx::types::Json<Vec<Block>>"
+        "#,
+        reply_id,
+        payload.content,
+        &mentions.user_ids,
+        &mentions.role_ids,
+        mentions.everyone,
+        sqlx::types::Json(&ast) as _
+    )
+    .fetch_optional(&state.pool)
+    .await
+    .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))?
+    .ok_or((StatusCode::NOT_FOUND, "Reply not found".to_string()))?;
+
+    state.broadcast(message_id, &RoomEvent::ThreadReplyEdited(reply.clone())).await;
+    // Only members newly mentioned by the edit get a notification
+    mentions::notify(&state, member.room_id, message_id, Some(reply_id), member.user_id, &mentions).await;
+
+    Ok(Json(reply))
+}
+
+/// Authors can delete their own replies, moderators anyone's. Moderator
+/// deletions are audited together with the removed content.
+pub async fn delete_thread_reply(
+    member: RoomMember,
+    Path((_, message_id, reply_id)): Path<(Uuid, Uuid, Uuid)>,
+    State(state): State<Arc<AppState>>,
+) -> Result<Json<serde_json::Value>, (StatusCode, String)> {
+    require_thread(&state, &member, message_id).await?;
+
+    let reply = sqlx::query!(
+        "SELECT author_id, content FROM thread_replies WHERE id = $1 AND parent_id = $2 AND room_id = $3",
+        reply_id,
+        message_id,
+        member.room_id
+    )
+    .fetch_optional(&state.pool)
+    .await
+    .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))?
+    .ok_or((StatusCode::NOT_FOUND, "Reply not found".to_string()))?;
+
+    let own = reply.author_id == member.user_id;
+    if !own {
+        member.require(permissions::MANAGE_MESSAGES)?;
+    }
+
+    let deleted = messaging::delete_thread_replies(&state, member.room_id, message_id, &[reply_id])
+        .await
+        .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))?;
+    if deleted.is_empty() {
+        return Err((StatusCode::NOT_FOUND, "Reply not found".into()));
+    }
+
+    if !own {
+        audit::record(
+            &state.pool,
+            member.room_id,
+            member.user_id,
+            AuditAction::MessageDelete,
+            Some(reply.author_id),
+            Some(json!({ "message_id": message_id, "thread_reply_id": reply_id, "content": reply.content })),
+            None,
+        )
+        .await;
+    }
+
+    Ok(Json(json!({ "result": "deleted" })))
+}
+
+/// Bulk-deletes replies from one thread, newest first, like the room purge.
+pub async fn purge_thread_replies(
+    member: RoomMember,
+    Path((_, message_id)): Path<(Uuid, Uuid)>,
+    State(state): State<Arc<AppState>>,
+    Json(payload): Json<PurgeMessagesInput>,
+) -> Result<Json<serde_json::Value>, (StatusCode, String)> {
+    member.require(permissions::MANAGE_MESSAGES)?;
+
+    if payload.last.is_none() && payload.author_id.is_none() {
+        return Err((StatusCode::BAD_REQUEST, "Give `last` or `author_id` to purge".into()));
+    }
+    if payload.last.is_some_and(|n| !(1..=MAX_PURGE_MESSAGES).contains(&n)) {
+        return Err((StatusCode::BAD_REQUEST, "`last` must be between 1 and 1000".into()));
+    }
+    require_thread(&state, &member, message_id).await?;
+    let limit = payload.last.unwrap_or(MAX_PURGE_MESSAGES);
+
+    let targets = sqlx::query_scalar!(
+        r#"
+        SELECT id FROM thread_replies
+        WHERE parent_id = $1 AND room_id = $2
+          AND ($3::UUID IS NULL OR author_id = $3)
+          AND ($4::TIMESTAMPTZ IS NULL OR created_at >= $4)
+          AND ($5::TIMESTAMPTZ IS NULL OR created_at <= $5)
+        ORDER BY created_at DESC, id DESC
+        LIMIT $6
+        "#,
+        message_id,
+        member.room_id,
+        payload.author_id,
+        payload.since,
+        payload.until,
+        limit
+    )
+    .fetch_all(&state.pool)
+    .await
+    .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))?;
+
+    let reply_ids = messaging::delete_thread_replies(&state, member.room_id, message_id, &targets)
+        .await
+        .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))?;
+
+    if !reply_ids.is_empty() {
+        let fmt = |t: Option<OffsetDateTime>| t.and_then(|t| t.format(&Rfc3339).ok());
+        audit::record(
+            &state.pool,
+            member.room_id,
+            member.user_id,
+            AuditAction::MessagePurge,
+            payload.author_id,
+            None,
+            Some(json!({
+                "message_id": message_id,
+                "count": reply_ids.len(),
+                "last": payload.last,
+                "since": fmt(payload.since),
+                "until": fmt(payload.until),
+            })),
+        )
+        .await;
+    }
+
+    Ok(Json(json!({ "deleted": reply_ids.len(), "reply_ids": reply_ids })))
+}
+
+/// 404s unless the thread's parent is a live message the member can read.
+async fn require_thread(state: &AppState, member: &RoomMember, message_id: Uuid) -> Result<(), (StatusCode, String)> {
+    let visible = member
+        .sees_thread(&state.pool, message_id)
+        .await
+        .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))?;
+    if visible {
+        Ok(())
+    } else {
+        Err((StatusCode::NOT_FOUND, "Message not found".into()))
+    }
+}
@@ -1,11 +1,22 @@
 use axum::{
-    extract::{Path, State,Extension},
+    extract::{Path, Query, State,Extension},
     Json,
     http::StatusCode,
 };
 use uuid::Uuid;
+use sqlx::PgPool;
 use crate::models::user::SimpleUser;
-use crate::models::messages::{DirectMessage,SendMessageInput};
+use crate::models::messages::{DirectMessage,SendMessageInput,EditMessageInput,ReplyPreview,HistoryQuery};
+use crate::models::attachments::Attachment;
+use crate::models::embeds::Embed;
+use crate::models::markdown::Block;
+use crate::link_previews::{self, Target};
+use crate::history;
+use crate::markdown::{self, Refs};
+use crate::messaging;
+use crate::models::events::DmEvent;
+use crate::models::reactions::WithReactions;
+use crate::route_handlers::reactions::dm_reaction_summaries;
 use crate::auth::middleware::CurrentUser;
 
 
@@ -45,18 +56,9 @@ pub async fn send_direct_message(
         return Err((StatusCode::BAD_REQUEST, "Cannot message yourself".into()));
     }
 
-    sqlx::query!(
-        r#"
-        INSERT INTO direct_messages (sender_id, receiver_id, content)
-        VALUES ($1, $2, $3)
-        "#,
-        sender_id,
-        receiver_id,
-        payload.content
-    )
-    .execute(&state.pool)
-    .await
-    .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))?;
+    messaging::send_direct_message(&state, sender_id, receiver_id, payload)
+        .await
+        .map_err(|e| (e.status(), e.to_string()))?;
 
     Ok(Json(serde_json::json!({ "result": "message_sent" })))
 }
@@ -64,25 +66,215 @@ pub async fn send_direct_message(
 pub async fn get_direct_messages(
     Extension(CurrentUser { id: my_id, .. }): Extension<CurrentUser>,
     Path(other_user_id): Path<Uuid>,
+    Query(query): Query<HistoryQuery>,
     State(state): State<Arc<AppState>>,
-) -> Result<Json<Vec<DirectMessage>>, (StatusCode, String)> {
-    let messages = sqlx::query_as!(
+) -> Result<Json<Vec<WithReactions<DirectMessage>>>, (StatusCode, String)> {
+    let cursor = query.cursor()?;
+    let key = match cursor.message_id() {
+        Some(id) => Some(
+            sqlx::query!(
+                r#"
+                SELECT created_at, id FROM direct_messages
+                WHERE id = $1
+                  AND ((sender_id = $2 AND receiver_id = $3) OR (sender_id = $3 AND receiver_id = $2))
+                "#,
+                id,
+                my_id,
+                other_user_id
+            )
+            .fetch_optional(&state.pool)
+            .await
+            .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))?
+            .map(|row| (row.created_at, row.id))
+            .ok_or((StatusCode::NOT_FOUND, "Message not found".to_string()))?,
+        ),
+        None => None,
+    };
+
+    let messages = history::load(cursor, key, None, query.limit(), |range, newest_first, limit| {
+        dm_history_page(&state.pool, my_id, other_user_id, range, newest_first, limit)
+    })
+    .await
+    .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))?;
+
+    let ids: Vec<Uuid> = messages.iter().map(|m| m.id).collect();
+    let mut reactions = dm_reaction_summaries(&state.pool, &ids, my_id)
+        .await
+        .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))?;
+
+    let messages = messages
+        .into_iter()
+        .map(|message| WithReactions {
+            reactions: reactions.remove(&message.id).unwrap_or_default(),
+            message,
+        })
+        .collect();
+
+    Ok(Json(messages))
+}
+
+/// Up to `limit` messages between the two users in `range`, from whichever
+/// end `newest_first` says. Matching on the ordered pair lets the
+/// conversation index serve both directions.
+async fn dm_history_page(
+    pool: &PgPool,
+    user_a: Uuid,
+    user_b: Uuid,
+    range: history::Range,
+    newest_first: bool,
+    limit: i64,
+) -> Result<Vec<DirectMessage>, sqlx::Error> {
+    let (from_at, from_id) = range.from.unzip();
+    let (to_at, to_id) = range.to.unzip();
+
+    if newest_first {
+        sqlx::query_as!(
+            DirectMessage,
+            r#"
+            SELECT id, sender_id, receiver_id, content, created_at, edited_at, deleted_at, reply_to,
+                   direct_message_reply_preview(reply_to) AS "reply_preview: sqlx::types::Json<ReplyPreview>",
+                   direct_message_attachments(id) AS "attachments!: sqlx::types::Json<Vec<Attachment>>",
+                   embeds AS "embeds: sqlx::types::Json<Vec<Embed>>",
+                   content_ast AS "content_ast: sqlx::types::Json<Vec<Block>>"
+            FROM direct_messages
+            WHERE LEAST(sender_id, receiver_id) = LEAST($1::UUID, $2::UUID)
+              AND GREATEST(sender_id, receiver_id) = GREATEST($1::UUID, $2::UUID)
+              AND (created_at, id) >= (COALESCE($3::TIMESTAMPTZ, '-infinity'), COALESCE($4::UUID, '00000000-0000-0000-0000-000000000000'))
+              AND (created_at, id) < (COALESCE($5::TIMESTAMPTZ, 'infinity'), COALESCE($6::UUID, '00000000-0000-0000-0000-000000000000'))
+            ORDER BY created_at DESC, id DESC
+            LIMIT $7
+            "#,
+            user_a,
+            user_b,
+            from_at,
+            from_id,
+            to_at,
+            to_id,
+            limit
+        )
+        .fetch_all(pool)
+        .await
+    } else {
+        sqlx::query_as!(
+            DirectMessage,
+            r#"
+            SELECT id, sender_id, receiver_id, content, created_at, edited_at, deleted_at, reply_to,
+                   direct_message_reply_preview(reply_to) AS "reply_preview: sqlx::types::Json<ReplyPreview>",
+                   direct_message_attachments(id) AS "attachments!: sqlx::types::Json<Vec<Attachment>>",
+                   embeds AS "embeds: sqlx::types::Json<Vec<Embed>>",
+                   content_ast AS "content_ast: sqlx::types::Json<Vec<Block>>"
+            FROM direct_messages
+            WHERE LEAST(sender_id, receiver_id) = LEAST($1::UUID, $2::UUID)
+              AND GREATEST(sender_id, receiver_id) = GREATEST($1::UUID, $2::UUID)
+              AND (created_at, id) >= (COALESCE($3::TIMESTAMPTZ, '-infinity'), COALESCE($4::UUID, '00000000-0000-0000-0000-000000000000'))
+              AND (created_at, id) < (COALESCE($5::TIMESTAMPTZ, 'infinity'), COALESCE($6::UUID, '00000000-0000-0000-0000-000000000000'))
+            ORDER BY created_at ASC, id ASC
+            LIMIT $7
+            "#,
+            user_a,
+            user_b,
+            from_at,
+            from_id,
+            to_at,
+            to_id,
+            limit
+        )
+        .fetch_all(pool)
+        .await
+    }
+}
+
+pub async fn edit_direct_message(
+    Path((other_user_id, message_id)): Path<(Uuid, Uuid)>,
+    Extension(CurrentUser { id: my_id, .. }): Extension<CurrentUser>,
+    State(state): State<Arc<AppState>>,
+    Json(payload): Json<EditMessageInput>,
+) -> Result<Json<DirectMessage>, (StatusCode, String)> {
+    if payload.content.trim().is_empty() {
+        return Err((StatusCode::BAD_REQUEST, "Message content can't be empty".into()));
+    }
+
+    let refs = Refs::for_dm(&state.pool, [my_id, other_user_id], &payload.content)
+        .await
+        .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))?;
+    let ast = markdown::parse(&payload.content, &refs);
+
+    // The old content goes to message_revisions in the same statement
+    let message = sqlx::query_as!(
         DirectMessage,
         r#"
-        SELECT id, sender_id, receiver_id, content, created_at
-        FROM direct_messages
-        WHERE
-            (sender_id = $1 AND receiver_id = $2)
-            OR
-            (sender_id = $2 AND receiver_id = $1)
-        ORDER BY created_at ASC
+        WITH previous AS (
+            SELECT id, content FROM direct_messages
+            WHERE id = $1 AND sender_id = $2 AND receiver_id = $3 AND deleted_at IS NULL
+            FOR UPDATE
+        ), revision AS (
+            INSERT INTO message_revisions (direct_message_id, content, edited_by)
+            SELECT id, content, $2 FROM previous
+        )
+        UPDATE direct_messages dm
+        SET content = $4, content_ast = $5, edited_at = NOW()
+        FROM previous
+        WHERE dm.id = previous.id
+        RETURNING dm.id, dm.sender_id, dm.receiver_id, dm.content, dm.created_at, dm.edited_at,
+                  dm.deleted_at, dm.reply_to,
+                  direct_message_reply_preview(dm.reply_to) AS "reply_preview: sqlx::types::Json<ReplyPreview>",
+                  direct_message_attachments(dm.id) AS "attachments!: sqlx::types::Json<Vec<Attachment>>",
+                  dm.embeds AS "embeds: sqlx::types::Json<Vec<Embed>>",
+                  dm.content_ast AS "content_ast: sqlx::types::Json<Vec<Block>>"
         "#,
+        message_id,
+        my_id,
+        other_user_id,
+        payload.content,
+        sqlx::types::Json(&ast) as _
+    )
+    .fetch_optional(&state.pool)
+    .await
+    .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))?
+    .ok_or((StatusCode::NOT_FOUND, "Message not found".to_string()))?;
+
+    // Delivered to both participants' DM sockets
+    state.broadcast_dm(my_id, other_user_id, &DmEvent::MessageEdited(message.clone())).await;
+
+    let target = Target::Dm { sender_id: my_id, receiver_id: other_user_id, message_id };
+    link_previews::refresh(&state, target, &message.content);
+
+    Ok(Json(message))
+}
+
+/// Only the sender can delete a DM. It stays in the history as a tombstone.
+pub async fn delete_direct_message(
+    Path((other_user_id, message_id)): Path<(Uuid, Uuid)>,
+    Extension(CurrentUser { id: my_id, .. }): Extension<CurrentUser>,
+    State(state): State<Arc<AppState>>,
+) -> Result<Json<serde_json::Value>, (StatusCode, String)> {
+    // Earlier versions go too, nothing of the content is kept
+    sqlx::query_scalar!(
+        r#"
+        WITH gone AS (
+            UPDATE direct_messages SET content = '', embeds = '[]', content_ast = '[]', deleted_at = NOW()
+            WHERE id = $1 AND sender_id = $2 AND receiver_id = $3 AND deleted_at IS NULL
+            RETURNING id
+        ), revisions AS (
+            DELETE FROM message_revisions WHERE direct_message_id IN (SELECT id FROM gone)
+        ), reactions AS (
+            DELETE FROM direct_message_reactions WHERE direct_message_id IN (SELECT id FROM gone)
+        ), attachments AS (
+            UPDATE attachments SET direct_message_id = NULL, deleted_at = NOW()
+            WHERE direct_message_id IN (SELECT id FROM gone)
+        )
+        SELECT id FROM gone
+        "#,
+        message_id,
         my_id,
         other_user_id
     )
-    .fetch_all(&state.pool)
+    .fetch_optional(&state.pool)
     .await
-    .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))?;
+    .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))?
+    .ok_or((StatusCode::NOT_FOUND, "Message not found".to_string()))?;
 
-    Ok(Json(messages))
-}
\ No newline at end of file
+    state.broadcast_dm(my_id, other_user_id, &DmEvent::MessageDeleted { message_id }).await;
+
+    Ok(Json(serde_json::json!({ "result": "deleted" })))
+}
@@ -4,14 +4,22 @@ use axum::{
     response::IntoResponse,
     extract::ws::{Message, WebSocket},
 };
-use tokio::sync::broadcast;
 use futures_util::SinkExt;
-use futures_util::stream::StreamExt;
+use futures_util::stream::{SplitSink, StreamExt};
 use uuid::Uuid;
 use crate::AppState;
+use crate::auth::middleware::authenticate;
+use crate::auth::membership::{RoomMember, require_member};
+use crate::messaging::{self, SendError};
+use crate::models::events::{ClientCommand, RoomEvent};
+use crate::models::messages::SendMessageInput;
+use crate::models::rooms::RoomMessageInput;
+use crate::state::Frame;
+use tokio::sync::broadcast::{self, error::RecvError};
+use tokio::sync::mpsc;
+use tokio::task::JoinHandle;
 use axum::debug_handler;
 use std::sync::Arc;
-use serde_json::json;
 use std::collections::HashMap;
 
 #[debug_handler]
@@ -26,7 +34,22 @@ pub async fn ws_handler(
         None => return StatusCode::UNAUTHORIZED.into_response(),
     };
 
-    ws.on_upgrade(move |socket| handle_socket(socket, state, room_id, token))
+    let user = match authenticate(&state, &token).await {
+        Ok(user) => user,
+        Err(e) => return e.into_response(),
+    };
+
+    // Same membership guard as the REST room routes
+    let member = match RoomMember::load(&state.pool, room_id, user.id).await {
+        Ok(member) => member,
+        Err(_) => return StatusCode::INTERNAL_SERVER_ERROR.into_response(),
+    };
+    let member = match require_member(member) {
+        Ok(member) => member,
+        Err(e) => return e.into_response(),
+    };
+
+    ws.on_upgrade(move |socket| handle_socket(socket, state, member))
 }
 
 #[debug_handler]
@@ -41,122 +64,234 @@ pub async fn ws_dm_handler(
         None => return StatusCode::UNAUTHORIZED.into_response(),
     };
 
-    ws.on_upgrade(move |socket| handle_dm_socket(socket, state, other_user_id, token))
+    let user = match authenticate(&state, &token).await {
+        Ok(user) => user,
+        Err(e) => return e.into_response(),
+    };
+
+    ws.on_upgrade(move |socket| handle_dm_socket(socket, state, other_user_id, user.id))
 }
 
 
+/// The caller's own event stream: new notifications and read-state changes.
+#[debug_handler]
+pub async fn ws_notifications_handler(
+    Query(params): Query<HashMap<String, String>>,
+    State(state): State<Arc<AppState>>,
+    ws: WebSocketUpgrade,
+) -> impl IntoResponse {
+    let token = match params.get("token") {
+        Some(t) => t.clone(),
+        None => return StatusCode::UNAUTHORIZED.into_response(),
+    };
+
+    let user = match authenticate(&state, &token).await {
+        Ok(user) => user,
+        Err(e) => return e.into_response(),
+    };
+
+    ws.on_upgrade(move |socket| handle_notifications_socket(socket, state, user.id))
+}
 
 pub async fn handle_socket(
     socket: WebSocket,
     state: Arc<AppState>,
-    room_id: Uuid,
-    token: String,
+    mut member: RoomMember,
 ) {
-    // Decode and verify JWT
-    let claims = match crate::auth::jwt::decode_jwt(&token) {
-        Ok(c) => c,
-        Err(_) => return,
-    };
+    // Subscribe to the room's broadcast channel
+    let rx = state.subscribe(member.room_id).await;
 
-    let user_id = match Uuid::parse_str(&claims.claims.sub) {
-        Ok(uuid) => uuid,
-        Err(_) => return,
-    };
-
-    // Get or create broadcast sender for the room
-    let tx = {
-        let mut rooms = state.rooms.write().await;
-        rooms.entry(room_id)
-            .or_insert_with(|| broadcast::channel::<String>(100).0) // buffer size: 100
-            .clone()
-    };
-
-    // Subscribe to the broadcast channel
-    let mut rx = tx.subscribe();
+    // Replies meant for this socket only (e.g. a rejected send)
+    let (direct_tx, direct_rx) = mpsc::unbounded_channel::<String>();
 
     // Split the socket into send and receive halves
-    let (mut sender, mut receiver) = socket.split();
+    let (sender, mut receiver) = socket.split();
 
     // Spawn a task to send messages from broadcast receiver to this WebSocket
-    tokio::spawn(async move {
-        while let Ok(msg) = rx.recv().await {
-            if sender.send(Message::Text(msg.into())).await.is_err() {
+    let mut forward = tokio::spawn(forward_room(state.clone(), member.clone(), rx, direct_rx, sender));
+
+    // Live thread subscriptions: parent message id -> forwarding task
+    let mut threads: HashMap<Uuid, JoinHandle<()>> = HashMap::new();
+
+    // Main receive loop from the client
+    loop {
+        let text = tokio::select! {
+            frame = receiver.next() => match frame {
+                Some(Ok(Message::Text(text))) => text,
+                _ => break,
+            },
+            // The forwarder closed the socket: the member lost access
+            _ = &mut forward => break,
+        };
+
+        let command = serde_json::from_str::<ClientCommand>(&text)
+            .unwrap_or_else(|_| ClientCommand::Message {
+                content: text.to_string(),
+                channel_id: None,
+                reply_to: None,
+                attachment_ids: Vec::new(),
+            });
+
+        // The upgrade-time snapshot goes stale: the member may have left or
+        // lost roles, or the room may have been deleted
+        member = match RoomMember::load(&state.pool, member.room_id, member.user_id).await {
+            Ok(Some(member)) => member,
+            Ok(None) => {
+                let _ = direct_tx.send(serde_json::to_string(&SendError::NotMember.to_event()).unwrap());
                 break;
             }
+            Err(e) => {
+                let _ = direct_tx.send(serde_json::to_string(&SendError::from(e).to_event()).unwrap());
+                continue;
+            }
+        };
+
+        match command {
+            ClientCommand::Message { content, channel_id, reply_to, attachment_ids } => {
+                // Persist and broadcast through the same path as the REST endpoint
+                let input = RoomMessageInput { content, channel_id, reply_to, attachment_ids };
+                if let Err(e) = messaging::send_room_message(&state, &member, input).await {
+                    let _ = direct_tx.send(serde_json::to_string(&e.to_event()).unwrap());
+                }
+            }
+            ClientCommand::SubscribeThread { message_id } => {
+                if threads.contains_key(&message_id) {
+                    continue;
+                }
+                if !member.sees_thread(&state.pool, message_id).await.unwrap_or(false) {
+                    let err = SendError::NotFound("Message").to_event();
+                    let _ = direct_tx.send(serde_json::to_string(&err).unwrap());
+                    continue;
+                }
+
+                let mut thread_rx = state.subscribe(message_id).await;
+                let forward = direct_tx.clone();
+                threads.insert(
+                    message_id,
+                    tokio::spawn(async move {
+                        loop {
+                            let msg = match thread_rx.recv().await {
+                                Ok(frame) => frame.json,
+                                Err(RecvError::Lagged(_)) => continue,
+                                Err(RecvError::Closed) => break,
+                            };
+                            if forward.send(msg).is_err() {
+                                break;
+                            }
+                        }
+                    }),
+                );
+            }
+            ClientCommand::UnsubscribeThread { message_id } => {
+                if let Some(task) = threads.remove(&message_id) {
+                    stop_thread(&state, message_id, task).await;
+                }
+            }
         }
-    });
+    }
 
-    // Main receive loop from the client
-    while let Some(Ok(Message::Text(content))) = receiver.next().await {
-        let content_str = content.to_string();
-
-        // Save message to the database
-        let _ = sqlx::query!(
-            r#"
-            INSERT INTO messages (room_id, author_id, content)
-            VALUES ($1, $2, $3)
-            "#,
-            room_id,
-            user_id,
-            content_str
-        )
-        .execute(&state.pool)
-        .await;
-
-        // Broadcast to the room (including sender)
-        let _ = tx.send(
-            serde_json::to_string(&json!({
-                "author_id": user_id,
-                "content": content_str,
-                "created_at": chrono::Utc::now(),
-            })).unwrap()
-        );
+    for (message_id, task) in threads {
+        stop_thread(&state, message_id, task).await;
+    }
+
+    // Let the forwarder flush this socket's last replies before letting go
+    drop(direct_tx);
+    if !forward.is_finished() {
+        let _ = forward.await;
+    }
+    state.release(member.room_id).await;
+}
+
+/// Sends room broadcasts and this socket's own replies to the client until
+/// either side is done. Broadcasts are filtered by the member's history
+/// cutoff. Room updates and deletions make it reload the membership, and
+/// close the socket once the member can't see the room.
+async fn forward_room(
+    state: Arc<AppState>,
+    mut member: RoomMember,
+    mut rx: broadcast::Receiver<Frame>,
+    mut direct_rx: mpsc::UnboundedReceiver<String>,
+    mut sender: SplitSink<WebSocket, Message>,
+) {
+    loop {
+        let msg = tokio::select! {
+            frame = rx.recv() => match frame {
+                Ok(frame) => {
+                    if frame.event.as_deref().is_some_and(RoomEvent::changes_access) {
+                        match RoomMember::load(&state.pool, member.room_id, member.user_id).await {
+                            Ok(Some(fresh)) => member = fresh,
+                            Ok(None) => {
+                                let _ = sender.send(Message::Text(frame.json.into())).await;
+                                let gone = serde_json::to_string(&SendError::NotMember.to_event()).unwrap();
+                                let _ = sender.send(Message::Text(gone.into())).await;
+                                break;
+                            }
+                            // Keep the last known membership
+                            Err(_) => {}
+                        }
+                    }
+                    match frame.for_cutoff(member.history_cutoff) {
+                        Some(json) => json,
+                        // About messages before this member's history
+                        None => continue,
+                    }
+                }
+                // A slow client missed some frames; keep going from here
+                Err(RecvError::Lagged(_)) => continue,
+                Err(RecvError::Closed) => break,
+            },
+            msg = direct_rx.recv() => match msg {
+                Some(msg) => msg,
+                // The receive loop has ended
+                None => break,
+            },
+        };
+        if sender.send(Message::Text(msg.into())).await.is_err() {
+            break;
+        }
     }
+    let _ = sender.close().await;
+}
+
+/// Ends a thread subscription and drops the thread's channel if this was its
+/// last subscriber.
+async fn stop_thread(state: &AppState, message_id: Uuid, task: JoinHandle<()>) {
+    task.abort();
+    // Wait until the task, and with it the receiver, is actually gone
+    let _ = task.await;
+    state.release(message_id).await;
 }
 
 pub async fn handle_dm_socket(
     socket: WebSocket,
     state: Arc<AppState>,
     other_user_id: Uuid,
-    token: String,
+    user_id: Uuid,
 ) {
-    // Decode and verify JWT
-    let claims = match crate::auth::jwt::decode_jwt(&token) {
-        Ok(c) => c,
-        Err(_) => return,
-    };
-
-    let user_id = match Uuid::parse_str(&claims.claims.sub) {
-        Ok(uuid) => uuid,
-        Err(_) => return,
-    };
-
-    // Create a unique key for the DM pair (sorted to avoid duplication)
-    let (a, b) = if user_id < other_user_id {
-        (user_id, other_user_id)
-    } else {
-        (other_user_id, user_id)
-    };
-    let dm_key = format!("{a}_{b}");
-
-    // Get or create broadcast sender for this DM session
-    let tx = {
-        let mut rooms = state.rooms.write().await;
-        rooms
-            .entry(Uuid::new_v5(&Uuid::NAMESPACE_OID, dm_key.as_bytes()))
-            .or_insert_with(|| broadcast::channel::<String>(100).0)
-            .clone()
-    };
+    // Subscribe to this conversation's broadcast channel
+    let key = AppState::dm_key(user_id, other_user_id);
+    let mut rx = state.subscribe(key).await;
 
-    // Subscribe to the broadcast channel
-    let mut rx = tx.subscribe();
+    // Replies meant for this socket only (e.g. a rejected send)
+    let (direct_tx, mut direct_rx) = mpsc::unbounded_channel::<String>();
 
     // Split the socket into send and receive halves
     let (mut sender, mut receiver) = socket.split();
 
     // Spawn a task to forward broadcasted messages to this socket
-    tokio::spawn(async move {
-        while let Ok(msg) = rx.recv().await {
+    let forward = tokio::spawn(async move {
+        loop {
+            let msg = tokio::select! {
+                frame = rx.recv() => match frame {
+                    Ok(frame) => frame.json,
+                    Err(RecvError::Lagged(_)) => continue,
+                    Err(RecvError::Closed) => break,
+                },
+                msg = direct_rx.recv() => match msg {
+                    Some(msg) => msg,
+                    None => break,
+                },
+            };
             if sender.send(Message::Text(msg.into())).await.is_err() {
                 break;
             }
@@ -164,29 +299,47 @@ pub async fn handle_dm_socket(
     });
 
     // Receive loop
-    while let Some(Ok(Message::Text(content))) = receiver.next().await {
-        let content_str = content.to_string();
-
-        // Save to direct_messages table
-        let _ = sqlx::query!(
-            r#"
-            INSERT INTO direct_messages (sender_id, receiver_id, content)
-            VALUES ($1, $2, $3)
-            "#,
-            user_id,
-            other_user_id,
-            content_str
-        )
-        .execute(&state.pool)
-        .await;
-
-        // Broadcast the message as JSON
-        let _ = tx.send(
-            serde_json::to_string(&json!({
-                "sender_id": user_id,
-                "content": content_str,
-                "created_at": chrono::Utc::now(),
-            })).unwrap()
-        );
+    while let Some(Ok(Message::Text(text))) = receiver.next().await {
+        let input = serde_json::from_str::<SendMessageInput>(&text).unwrap_or_else(|_| SendMessageInput {
+            content: text.to_string(),
+            reply_to: None,
+            attachment_ids: Vec::new(),
+        });
+
+        // Persist and broadcast through the same path as the REST endpoint
+        if let Err(e) = messaging::send_direct_message(&state, user_id, other_user_id, input).await {
+            let _ = direct_tx.send(serde_json::to_string(&e.to_event()).unwrap());
+        }
     }
-}
\ No newline at end of file
+
+    forward.abort();
+    let _ = forward.await;
+    state.release(key).await;
+}
+
+pub async fn handle_notifications_socket(socket: WebSocket, state: Arc<AppState>, user_id: Uuid) {
+    let key = AppState::user_key(user_id);
+    let mut rx = state.subscribe(key).await;
+
+    let (mut sender, mut receiver) = socket.split();
+
+    let forward = tokio::spawn(async move {
+        loop {
+            let msg = match rx.recv().await {
+                Ok(frame) => frame.json,
+                Err(RecvError::Lagged(_)) => continue,
+                Err(RecvError::Closed) => break,
+            };
+            if sender.send(Message::Text(msg.into())).await.is_err() {
+                break;
+            }
+        }
+    });
+
+    // Nothing to handle from the client; just wait for it to go away
+    while let Some(Ok(_)) = receiver.next().await {}
+
+    forward.abort();
+    let _ = forward.await;
+    state.release(key).await;
+}
@@ -1,29 +1,81 @@
 use axum::{
+    extract::DefaultBodyLimit,
     middleware::from_fn_with_state,
-    routing::{get, post},
+    routing::{get, post, put, patch, delete},
     Router,
 };
 use std::sync::Arc;
 use crate::auth::handlers::{login, register};
 use crate::route_handlers::me::get_me;
-use crate::route_handlers::users::{get_user_by_id, get_direct_messages, send_direct_message};
+use crate::route_handlers::users::{
+    get_user_by_id, get_direct_messages, send_direct_message, edit_direct_message, delete_direct_message,
+};
 use crate::route_handlers::room::{
-    create_room, join_room, list_my_rooms, send_room_message, get_room_messages, get_room, list_room_members
+    create_room, join_room, list_my_rooms, send_room_message, get_room_messages, get_room, list_room_members,
+    update_room, delete_room, transfer_room, list_deleted_rooms, restore_room, mark_room_read,
+    update_my_membership, edit_room_message, get_message_revisions, delete_room_message,
+    purge_room_messages,
 };
 use crate::route_handlers::relationships::{
     send_friend_request, accept_friend_request, block_user, list_friends,
     remove_relationship, list_pending_requests,
 };
-use crate::route_handlers::ws::{ws_handler,ws_dm_handler};
+use crate::route_handlers::roles::{list_roles, create_role, delete_role, assign_role, unassign_role};
+use crate::route_handlers::audit::get_audit_log;
+use crate::route_handlers::pins::{list_pins, pin_message, unpin_message};
+use crate::route_handlers::threads::{
+    delete_thread_reply, edit_thread_reply, get_thread_replies, purge_thread_replies, send_thread_reply,
+};
+use crate::route_handlers::channels::{
+    create_category, update_category, delete_category, create_channel, update_channel,
+    delete_channel, update_layout,
+};
+use crate::route_handlers::templates::{
+    create_template, get_template, delete_template, create_room_from_template, clone_room,
+};
+use crate::route_handlers::rules::{get_rules, update_rules, accept_rules};
+use crate::route_handlers::scheduled_events::{
+    list_events, get_event, create_event, update_event, delete_event, list_rsvps, set_rsvp, delete_rsvp,
+    get_calendar_feed_url, rotate_calendar_feed_url, calendar_feed,
+};
+use crate::route_handlers::forum::{
+    list_posts, get_post, create_post, update_post, solve_post, unsolve_post, list_tags,
+};
+use crate::route_handlers::reactions::{
+    add_room_reaction, remove_room_reaction, list_room_reactors, add_dm_reaction, remove_dm_reaction,
+};
+use crate::route_handlers::attachments::{
+    upload_room_attachments, upload_dm_attachments, download_attachment, download_thumbnail,
+};
+use crate::route_handlers::notifications::{
+    list_notifications, mark_notification_read, mark_all_notifications_read,
+};
+use crate::route_handlers::markdown::{preview_room_markdown, preview_dm_markdown, get_room_message_html};
+use crate::route_handlers::ws::{ws_handler,ws_dm_handler,ws_notifications_handler};
 use crate::auth::middleware::auth_middleware;
+use crate::models::attachments::MAX_ATTACHMENTS_PER_MESSAGE;
 use crate::state::AppState;
 
 pub fn create_routes(app_state: Arc<AppState>) -> Router<Arc<AppState>> {
+    // Room for a full batch of files plus the multipart framing
+    let upload_limit = DefaultBodyLimit::max(app_state.attachment_max_bytes * MAX_ATTACHMENTS_PER_MESSAGE + 64 * 1024);
+
     let protected_routes = Router::new()
         //users
         .route("/api/me", get(get_me))
         .route("/api/user/{:user}", get(get_user_by_id))
         .route("/api/dm/{:user}", get(get_direct_messages).post(send_direct_message))
+        .route("/api/dm/{:user}/messages/{message_id}", patch(edit_direct_message).delete(delete_direct_message))
+        .route("/api/dm/{:user}/messages/{message_id}/reactions/{emoji}", put(add_dm_reaction).delete(remove_dm_reaction))
+        .route("/api/dm/{:user}/attachments", post(upload_dm_attachments).layer(upload_limit))
+        .route("/api/dm/{:user}/markdown", post(preview_dm_markdown))
+        //attachments
+        .route("/api/attachments/{:id}", get(download_attachment))
+        .route("/api/attachments/{:id}/thumbnails/{size}", get(download_thumbnail))
+        //notifications
+        .route("/api/notifications", get(list_notifications))
+        .route("/api/notifications/read", post(mark_all_notifications_read))
+        .route("/api/notifications/{id}/read", post(mark_notification_read))
         //relationships
         .route("/api/relationships/{:id}", post(send_friend_request).delete(remove_relationship))
         .route("/api/relationships/{:id}/accept", post(accept_friend_request))
@@ -31,15 +83,75 @@ pub fn create_routes(app_state: Arc<AppState>) -> Router<Arc<AppState>> {
         .route("/api/relationships/friends", get(list_friends))
         .route("/api/relationships/pending", get(list_pending_requests))
         //Rooms
-        .route("/api/rooms/{:id}", get(get_room))
+        .route("/api/rooms/{:id}", get(get_room).patch(update_room).delete(delete_room))
+        .route("/api/rooms/{:id}/transfer", post(transfer_room))
         .route("/api/rooms", post(create_room).get(list_my_rooms))
+        .route("/api/rooms/deleted", get(list_deleted_rooms))
+        .route("/api/rooms/{:id}/restore", post(restore_room))
         .route("/api/rooms/{:id}/join", post(join_room))
         .route("/api/rooms/{:id}/messages", get(get_room_messages).post(send_room_message))
-        .route("/api/rooms/{:id}/members",get(list_room_members));
+        .route("/api/rooms/{:id}/messages/purge", post(purge_room_messages))
+        .route("/api/rooms/{:id}/messages/{message_id}", patch(edit_room_message).delete(delete_room_message))
+        .route("/api/rooms/{:id}/messages/{message_id}/revisions", get(get_message_revisions))
+        .route("/api/rooms/{:id}/messages/{message_id}/html", get(get_room_message_html))
+        .route("/api/rooms/{:id}/markdown", post(preview_room_markdown))
+        .route(
+            "/api/rooms/{:id}/messages/{message_id}/reactions/{emoji}",
+            get(list_room_reactors).put(add_room_reaction).delete(remove_room_reaction),
+        )
+        .route("/api/rooms/{:id}/attachments", post(upload_room_attachments).layer(upload_limit))
+        .route("/api/rooms/{:id}/members",get(list_room_members))
+        .route("/api/rooms/{:id}/read", post(mark_room_read))
+        .route("/api/rooms/{:id}/members/me", patch(update_my_membership))
+        .route("/api/rooms/{:id}/audit-log", get(get_audit_log))
+        .route("/api/rooms/{:id}/pins", get(list_pins))
+        .route("/api/rooms/{:id}/pins/{message_id}", put(pin_message).delete(unpin_message))
+        .route("/api/rooms/{:id}/messages/{message_id}/thread", get(get_thread_replies).post(send_thread_reply))
+        .route("/api/rooms/{:id}/messages/{message_id}/thread/purge", post(purge_thread_replies))
+        .route(
+            "/api/rooms/{:id}/messages/{message_id}/thread/{reply_id}",
+            patch(edit_thread_reply).delete(delete_thread_reply),
+        )
+        //Sidebar
+        .route("/api/rooms/{:id}/categories", post(create_category))
+        .route("/api/rooms/{:id}/categories/{category_id}", patch(update_category).delete(delete_category))
+        .route("/api/rooms/{:id}/channels", post(create_channel))
+        .route("/api/rooms/{:id}/channels/{channel_id}", patch(update_channel).delete(delete_channel))
+        .route("/api/rooms/{:id}/layout", put(update_layout))
+        //Templates
+        .route("/api/rooms/{:id}/templates", post(create_template))
+        .route("/api/rooms/{:id}/clone", post(clone_room))
+        .route("/api/rooms/{:id}/rules", get(get_rules).put(update_rules))
+        .route("/api/rooms/{:id}/rules/accept", post(accept_rules))
+        //Forum rooms; a post's replies are its thread
+        .route("/api/rooms/{:id}/posts", get(list_posts).post(create_post))
+        .route("/api/rooms/{:id}/posts/tags", get(list_tags))
+        .route("/api/rooms/{:id}/posts/{post_id}", get(get_post).patch(update_post))
+        .route("/api/rooms/{:id}/posts/{post_id}/replies", get(get_thread_replies).post(send_thread_reply))
+        .route("/api/rooms/{:id}/posts/{post_id}/replies/purge", post(purge_thread_replies))
+        .route(
+            "/api/rooms/{:id}/posts/{post_id}/replies/{reply_id}",
+            patch(edit_thread_reply).delete(delete_thread_reply),
+        )
+        .route("/api/rooms/{:id}/posts/{post_id}/solved", put(solve_post).delete(unsolve_post))
+        //Scheduled events
+        .route("/api/rooms/{:id}/events", get(list_events).post(create_event))
+        .route("/api/rooms/{:id}/events/feed", get(get_calendar_feed_url).post(rotate_calendar_feed_url))
+        .route("/api/rooms/{:id}/events/{event_id}", get(get_event).patch(update_event).delete(delete_event))
+        .route("/api/rooms/{:id}/events/{event_id}/rsvps", get(list_rsvps))
+        .route("/api/rooms/{:id}/events/{event_id}/rsvp", put(set_rsvp).delete(delete_rsvp))
+        .route("/api/templates/{code}", get(get_template).delete(delete_template))
+        .route("/api/templates/{code}/rooms", post(create_room_from_template))
+        //Roles
+        .route("/api/rooms/{:id}/roles", get(list_roles).post(create_role))
+        .route("/api/rooms/{:id}/roles/{role_id}", delete(delete_role))
+        .route("/api/rooms/{:id}/members/{user_id}/roles/{role_id}", put(assign_role).delete(unassign_role));
 
     let unprotected_routes = Router::new()
         .route("/api/register", post(register))
-        .route("/api/login", post(login));
+        .route("/api/login", post(login))
+        // Calendar apps authenticate with the feed token in the URL
+        .route("/api/rooms/{:id}/events/calendar.ics", get(calendar_feed));
 
     Router::new()
         .merge(unprotected_routes)
@@ -50,5 +162,6 @@ pub fn ws_routes(app_state: Arc<AppState>) -> Router<Arc<AppState>> {
     Router::new()
         .route("/api/ws/{:room_id}", get(ws_handler))
         .route("/api/dm/ws/{other_user_id}", get(ws_dm_handler))
+        .route("/api/notifications/ws", get(ws_notifications_handler))
         .with_state(app_state)
 }
@@ -1,13 +1,119 @@
+use std::borrow::Cow;
 use std::collections::HashMap;
 use std::sync::Arc;
-use tokio::sync::{RwLock, broadcast};
+use tokio::sync::{Notify, RwLock, broadcast};
 use sqlx::PgPool;
+use time::{Duration, OffsetDateTime};
 use uuid::Uuid;
+use crate::models::events::{DmEvent, RoomEvent, UserEvent};
+use crate::link_previews::LinkFetcher;
+use crate::storage::BlobStore;
 
-pub type Tx = broadcast::Sender<String>;
+pub type Tx = broadcast::Sender<Frame>;
+
+/// One message on a broadcast channel. Room events keep the event itself
+/// next to its JSON so each room socket can check it against its member.
+#[derive(Clone)]
+pub struct Frame {
+    pub json: String,
+    pub event: Option<Arc<RoomEvent>>,
+}
+
+impl Frame {
+    /// The JSON for a room member whose history starts at `cutoff`, or `None`
+    /// if the frame is only about messages they can't see.
+    pub fn for_cutoff(&self, cutoff: Option<OffsetDateTime>) -> Option<String> {
+        let (Some(event), Some(cutoff)) = (&self.event, cutoff) else {
+            return Some(self.json.clone());
+        };
+        match event.visible_from(cutoff)? {
+            Cow::Borrowed(_) => Some(self.json.clone()),
+            Cow::Owned(event) => Some(serde_json::to_string(&event).unwrap()),
+        }
+    }
+}
+
+impl From<String> for Frame {
+    fn from(json: String) -> Self {
+        Frame { json, event: None }
+    }
+}
 
 #[derive(Clone)]
 pub struct AppState {
     pub pool: PgPool,
     pub rooms: Arc<RwLock<HashMap<Uuid, Tx>>>,
-}
\ No newline at end of file
+    /// How long a soft-deleted room can be restored before it is purged.
+    pub room_restore_window: Duration,
+    /// How long before an event starts its reminder is posted.
+    pub event_reminder_lead: Duration,
+    /// Attachment bytes; the database keeps only the keys.
+    pub blobs: Arc<dyn BlobStore>,
+    /// Per-file upload limit.
+    pub attachment_max_bytes: usize,
+    /// Wakes the image worker when new uploads are queued.
+    pub image_jobs: Arc<Notify>,
+    /// Client for link previews, restricted to public addresses.
+    pub link_fetcher: Arc<LinkFetcher>,
+}
+
+impl AppState {
+    /// Subscribe to a room's (or DM pair's, thread's, user's) channel,
+    /// creating it for the first subscriber.
+    pub async fn subscribe(&self, key: Uuid) -> broadcast::Receiver<Frame> {
+        let mut rooms = self.rooms.write().await;
+        rooms
+            .entry(key)
+            .or_insert_with(|| broadcast::channel::<Frame>(100).0) // buffer size: 100
+            .subscribe()
+    }
+
+    /// Drop a channel once its last subscriber is gone. Sockets call this
+    /// after dropping their receiver, so channels don't pile up for every
+    /// room, conversation and thread ever opened.
+    pub async fn release(&self, key: Uuid) {
+        let mut rooms = self.rooms.write().await;
+        if rooms.get(&key).is_some_and(|tx| tx.receiver_count() == 0) {
+            rooms.remove(&key);
+        }
+    }
+
+    /// Push a frame to everyone subscribed to the channel. No-op if nobody is listening.
+    pub async fn send(&self, key: Uuid, frame: Frame) {
+        let rooms = self.rooms.read().await;
+        if let Some(tx) = rooms.get(&key) {
+            let _ = tx.send(frame);
+        }
+    }
+
+    /// Channel key shared by both sides of a DM conversation.
+    pub fn dm_key(a: Uuid, b: Uuid) -> Uuid {
+        // Sorted so both participants land on the same channel
+        let (a, b) = if a < b { (a, b) } else { (b, a) };
+        Uuid::new_v5(&Uuid::NAMESPACE_OID, format!("{a}_{b}").as_bytes())
+    }
+
+    /// Channel key for a user's notification socket.
+    pub fn user_key(user_id: Uuid) -> Uuid {
+        Uuid::new_v5(&Uuid::NAMESPACE_OID, format!("user_{user_id}").as_bytes())
+    }
+
+    /// Push an event to every notification socket the user has open.
+    pub async fn notify_user(&self, user_id: Uuid, event: &UserEvent) {
+        self.send(Self::user_key(user_id), serde_json::to_string(event).unwrap().into()).await;
+    }
+
+    /// Push an event to everyone connected to the room.
+    pub async fn broadcast(&self, room_id: Uuid, event: &RoomEvent) {
+        let frame = Frame {
+            json: serde_json::to_string(event).unwrap(),
+            event: Some(Arc::new(event.clone())),
+        };
+        self.send(room_id, frame).await;
+    }
+
+    /// Push an event to both participants' DM sockets.
+    pub async fn broadcast_dm(&self, a: Uuid, b: Uuid, event: &DmEvent) {
+        self.send(Self::dm_key(a, b), serde_json::to_string(event).unwrap().into()).await;
+    }
+}
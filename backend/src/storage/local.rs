@@ -0,0 +1,94 @@
+use async_trait::async_trait;
+use axum::body::Bytes;
+use std::io::ErrorKind;
+use std::path::PathBuf;
+use std::time::Duration;
+use super::{BlobError, BlobStore};
+
+/// Blobs as files under one directory. Keys are generated by the server, but
+/// anything that could leave the directory is still rejected.
+pub struct LocalStore {
+    root: PathBuf,
+}
+
+impl LocalStore {
+    pub fn new(root: impl Into<PathBuf>) -> Self {
+        LocalStore { root: root.into() }
+    }
+
+    fn path(&self, key: &str) -> Result<PathBuf, BlobError> {
+        let safe = !key.is_empty()
+            && key.split('/').all(|part| !part.is_empty() && part != "." && part != "..")
+            && !key.contains('\\');
+        if !safe {
+            return Err(BlobError::Backend(format!("invalid blob key {key:?}")));
+        }
+        Ok(self.root.join(key))
+    }
+}
+
+#[async_trait]
+impl BlobStore for LocalStore {
+    async fn put(&self, key: &str, data: Bytes, _content_type: &str) -> Result<(), BlobError> {
+        let path = self.path(key)?;
+        if let Some(parent) = path.parent() {
+            tokio::fs::create_dir_all(parent).await?;
+        }
+        tokio::fs::write(path, &data).await?;
+        Ok(())
+    }
+
+    async fn get(&self, key: &str) -> Result<Bytes, BlobError> {
+        match tokio::fs::read(self.path(key)?).await {
+            Ok(data) => Ok(Bytes::from(data)),
+            Err(e) if e.kind() == ErrorKind::NotFound => Err(BlobError::NotFound),
+            Err(e) => Err(e.into()),
+        }
+    }
+
+    async fn delete(&self, key: &str) -> Result<(), BlobError> {
+        match tokio::fs::remove_file(self.path(key)?).await {
+            Err(e) if e.kind() != ErrorKind::NotFound => Err(e.into()),
+            _ => Ok(()),
+        }
+    }
+
+    async fn presigned_get(
+        &self,
+        _key: &str,
+        _expires_in: Duration,
+        _content_disposition: &str,
+    ) -> Result<Option<String>, BlobError> {
+        Ok(None)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn keys_cannot_leave_the_directory() {
+        let store = LocalStore::new("/srv/uploads");
+        assert_eq!(store.path("attachments/ab/cd").unwrap(), PathBuf::from("/srv/uploads/attachments/ab/cd"));
+        for key in ["", "..", "../etc/passwd", "a/../../b", "a/./b", "/etc/passwd", "a//b", "a/", "a\\..\\b"] {
+            assert!(store.path(key).is_err(), "{key:?}");
+        }
+    }
+
+    #[tokio::test]
+    async fn round_trip() {
+        let root = std::env::temp_dir().join(format!("blobs-{}", uuid::Uuid::new_v4()));
+        let store = LocalStore::new(&root);
+
+        store.put("a/b", Bytes::from_static(b"hello"), "text/plain").await.unwrap();
+        assert_eq!(store.get("a/b").await.unwrap(), Bytes::from_static(b"hello"));
+        assert!(store.presigned_get("a/b", Duration::from_secs(60), "inline").await.unwrap().is_none());
+
+        store.delete("a/b").await.unwrap();
+        assert!(matches!(store.get("a/b").await, Err(BlobError::NotFound)));
+        store.delete("a/b").await.unwrap();
+
+        let _ = std::fs::remove_dir_all(root);
+    }
+}
@@ -0,0 +1,52 @@
+//! Where attachment bytes live. The database only keeps the key; the store is
+//! picked at startup with `BLOB_STORE` (`local`, the default, or `s3`).
+
+pub mod local;
+pub mod s3;
+
+use async_trait::async_trait;
+use axum::body::Bytes;
+use std::sync::Arc;
+use std::time::Duration;
+
+#[derive(Debug, thiserror::Error)]
+pub enum BlobError {
+    #[error("blob not found")]
+    NotFound,
+    #[error("storage I/O failed: {0}")]
+    Io(#[from] std::io::Error),
+    #[error("storage backend failed: {0}")]
+    Backend(String),
+}
+
+#[async_trait]
+pub trait BlobStore: Send + Sync {
+    async fn put(&self, key: &str, data: Bytes, content_type: &str) -> Result<(), BlobError>;
+
+    async fn get(&self, key: &str) -> Result<Bytes, BlobError>;
+
+    /// Deleting a missing blob is not an error.
+    async fn delete(&self, key: &str) -> Result<(), BlobError>;
+
+    /// A short-lived URL the client can fetch directly, if the backend can
+    /// sign one; it is served with `content_disposition`. Otherwise the API
+    /// streams the bytes itself.
+    async fn presigned_get(
+        &self,
+        key: &str,
+        expires_in: Duration,
+        content_disposition: &str,
+    ) -> Result<Option<String>, BlobError>;
+}
+
+/// Builds the store configured in the environment. Panics on incomplete S3
+/// settings, like the other startup configuration.
+pub fn from_env() -> Arc<dyn BlobStore> {
+    match std::env::var("BLOB_STORE").as_deref() {
+        Ok("s3") => Arc::new(s3::S3Store::from_env()),
+        _ => {
+            let dir = std::env::var("BLOB_LOCAL_DIR").unwrap_or_else(|_| "uploads".into());
+            Arc::new(local::LocalStore::new(dir))
+        }
+    }
+}
@@ -0,0 +1,156 @@
+use async_trait::async_trait;
+use axum::body::Bytes;
+use s3::creds::Credentials;
+use s3::error::S3Error;
+use s3::{Bucket, Region};
+use std::collections::HashMap;
+use std::time::Duration;
+use super::{BlobError, BlobStore};
+
+/// Any S3-compatible bucket. Setting `S3_ENDPOINT` (e.g. a local MinIO at
+/// `http://localhost:9000`) switches to path-style addressing.
+pub struct S3Store {
+    bucket: Box<Bucket>,
+}
+
+impl S3Store {
+    pub fn from_env() -> Self {
+        let var = |name: &str| std::env::var(name).ok().filter(|v| !v.is_empty());
+
+        let name = var("S3_BUCKET").expect("S3_BUCKET must be set when BLOB_STORE=s3");
+        let region_name = var("S3_REGION").unwrap_or_else(|| "us-east-1".into());
+        let endpoint = var("S3_ENDPOINT");
+        let region = match &endpoint {
+            Some(endpoint) => Region::Custom { region: region_name, endpoint: endpoint.clone() },
+            None => region_name.parse().expect("S3_REGION is not a valid region"),
+        };
+        let credentials = Credentials::new(
+            var("S3_ACCESS_KEY").as_deref(),
+            var("S3_SECRET_KEY").as_deref(),
+            None,
+            None,
+            None,
+        )
+        .expect("S3 credentials could not be loaded");
+
+        S3Store::new(&name, region, credentials, endpoint.is_some()).expect("Invalid S3 bucket configuration")
+    }
+
+    pub fn new(name: &str, region: Region, credentials: Credentials, path_style: bool) -> Result<Self, S3Error> {
+        let bucket = Bucket::new(name, region, credentials)?;
+        let bucket = if path_style { bucket.with_path_style() } else { bucket };
+        Ok(S3Store { bucket })
+    }
+}
+
+fn backend_error(e: S3Error) -> BlobError {
+    match e {
+        S3Error::HttpFailWithBody(404, _) => BlobError::NotFound,
+        e => BlobError::Backend(e.to_string()),
+    }
+}
+
+#[async_trait]
+impl BlobStore for S3Store {
+    async fn put(&self, key: &str, data: Bytes, content_type: &str) -> Result<(), BlobError> {
+        self.bucket
+            .put_object_with_content_type(key, &data, content_type)
+            .await
+            .map_err(backend_error)?;
+        Ok(())
+    }
+
+    async fn get(&self, key: &str) -> Result<Bytes, BlobError> {
+        let response = self.bucket.get_object(key).await.map_err(backend_error)?;
+        Ok(response.bytes().clone())
+    }
+
+    async fn delete(&self, key: &str) -> Result<(), BlobError> {
+        match self.bucket.delete_object(key).await {
+            Ok(_) | Err(S3Error::HttpFailWithBody(404, _)) => Ok(()),
+            Err(e) => Err(backend_error(e)),
+        }
+    }
+
+    async fn presigned_get(
+        &self,
+        key: &str,
+        expires_in: Duration,
+        content_disposition: &str,
+    ) -> Result<Option<String>, BlobError> {
+        // Overrides the stored headers so the bucket serves it like the API would
+        let queries = HashMap::from([(
+            "response-content-disposition".to_string(),
+            content_disposition.to_string(),
+        )]);
+        let url = self
+            .bucket
+            .presign_get(key, expires_in.as_secs() as u32, Some(queries))
+            .await
+            .map_err(backend_error)?;
+        Ok(Some(url))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use s3::BucketConfiguration;
+
+    fn minio(endpoint: &str, bucket: &str, access_key: &str, secret_key: &str) -> (Region, Credentials, String) {
+        let region = Region::Custom { region: "us-east-1".into(), endpoint: endpoint.into() };
+        let credentials = Credentials::new(Some(access_key), Some(secret_key), None, None, None).unwrap();
+        (region, credentials, bucket.into())
+    }
+
+    #[tokio::test]
+    async fn presigned_urls_carry_the_disposition() {
+        let (region, credentials, name) = minio("http://localhost:9000", "chat", "key", "secret");
+        let store = S3Store::new(&name, region, credentials, true).unwrap();
+
+        let url = store
+            .presigned_get("attachments/a/photo.png", Duration::from_secs(60), "inline; filename*=UTF-8''photo.png")
+            .await
+            .unwrap()
+            .unwrap();
+        assert!(url.starts_with("http://localhost:9000/chat/attachments/a/photo.png?"), "{url}");
+        assert!(url.contains("X-Amz-Expires=60"));
+        assert!(url.contains("response-content-disposition=inline"));
+    }
+
+    /// Run with `cargo test -- --ignored` against a MinIO, e.g.
+    /// `docker run -p 9000:9000 minio/minio server /data`. `S3_TEST_ENDPOINT`,
+    /// `S3_TEST_BUCKET`, `S3_TEST_ACCESS_KEY` and `S3_TEST_SECRET_KEY`
+    /// override the MinIO defaults.
+    #[tokio::test]
+    #[ignore = "needs a local MinIO"]
+    async fn round_trip_against_minio() {
+        let var = |name: &str, default: &str| std::env::var(name).unwrap_or_else(|_| default.into());
+        let (region, credentials, name) = minio(
+            &var("S3_TEST_ENDPOINT", "http://localhost:9000"),
+            &var("S3_TEST_BUCKET", "chat-test"),
+            &var("S3_TEST_ACCESS_KEY", "minioadmin"),
+            &var("S3_TEST_SECRET_KEY", "minioadmin"),
+        );
+        // Already existing is fine
+        let _ = Bucket::create_with_path_style(&name, region.clone(), credentials.clone(), BucketConfiguration::default()).await;
+        let store = S3Store::new(&name, region, credentials, true).unwrap();
+
+        let key = format!("tests/{}", uuid::Uuid::new_v4());
+        store.put(&key, Bytes::from_static(b"hello"), "text/plain").await.unwrap();
+        assert_eq!(store.get(&key).await.unwrap(), Bytes::from_static(b"hello"));
+
+        let url = store
+            .presigned_get(&key, Duration::from_secs(60), "attachment; filename*=UTF-8''hello.txt")
+            .await
+            .unwrap()
+            .unwrap();
+        let response = reqwest::get(&url).await.unwrap();
+        assert_eq!(response.headers()["content-disposition"], "attachment; filename*=UTF-8''hello.txt");
+        assert_eq!(response.bytes().await.unwrap(), "hello");
+
+        store.delete(&key).await.unwrap();
+        assert!(matches!(store.get(&key).await, Err(BlobError::NotFound)));
+        store.delete(&key).await.unwrap();
+    }
+}
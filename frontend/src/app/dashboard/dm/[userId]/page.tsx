@@ -45,7 +45,9 @@ export default function DirectMessagePage() {
 
     ws.onmessage = (event) => {
       try {
-        const msg: DMMessage = JSON.parse(event.data)
+        const msg = JSON.parse(event.data) as DMMessage & { type?: string }
+        // Typed frames (e.g. message_edited) aren't new messages
+        if (msg.type) return
         setMessages(prev => [...prev, msg])
       } catch {
         console.error('❌ Invalid WS message:', event.data)
@@ -75,7 +75,8 @@ export default function RoomChatPage() {
 
     ws.onmessage = (event) => {
       try {
-        const msg = JSON.parse(event.data) as WSMessage
+        const msg = JSON.parse(event.data) as WSMessage & { type?: string }
+        if (msg.type && msg.type !== 'message') return
         setMessages((prev) => [...prev, msg])
       } catch (err) {
         console.error('Invalid WS message:', event.data)